@@ -4,7 +4,6 @@
 //! unless the provided IP is public and the port number is forwarded.
 
 use tracing::Level;
-use tracing_subscriber;
 
 use axum::{routing::get, Router};
 use axum_server::tls_rustls::RustlsConfig;
@@ -73,7 +72,7 @@ async fn publish_server_pkarr(
     let signed_packet = SignedPacket::builder()
         .https(".".try_into().unwrap(), svcb, 60 * 60)
         .address(".".try_into().unwrap(), socket_addr.ip(), 60 * 60)
-        .sign(&keypair)?;
+        .sign(keypair)?;
 
     client.publish(&signed_packet, None).await?;
 
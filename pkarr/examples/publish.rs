@@ -8,7 +8,6 @@
 
 use clap::{Parser, ValueEnum};
 use std::time::Instant;
-use tracing_subscriber;
 
 use pkarr::{Client, Keypair, SignedPacket};
 
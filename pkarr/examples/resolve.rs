@@ -5,7 +5,6 @@
 
 use clap::{Parser, ValueEnum};
 use std::time::Instant;
-use tracing_subscriber;
 
 use pkarr::{Client, PublicKey};
 
@@ -11,47 +11,125 @@ macro_rules! cross_debug {
 
 pub mod cache;
 
+mod batch;
 #[cfg(not(wasm_browser))]
 pub mod blocking;
 pub mod builder;
-#[cfg(not(wasm_browser))]
+mod cancel;
+mod diagnostics;
+#[cfg(all(dht, relays))]
+mod discovery;
+#[cfg(all(dht, relays))]
 mod futures;
 #[cfg(relays)]
+mod health;
+mod observer;
+mod options;
+mod prefetch;
+mod quorum;
+#[cfg(relays)]
 mod relays;
+mod report;
+#[cfg(not(wasm_browser))]
+pub mod republisher;
+mod resolved;
+mod retry;
+mod static_zones;
+#[cfg(relays)]
+mod transport;
+mod update;
+mod watch;
 
 #[cfg(all(test, not(wasm_browser)))]
 mod tests;
 #[cfg(all(test, wasm_browser))]
 mod tests_web;
 
-#[cfg(not(wasm_browser))]
+#[cfg(all(dht, relays))]
 use futures::publish_both_networks;
 use futures_lite::{Stream, StreamExt};
 use ntimestamp::Timestamp;
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+#[cfg(dht)]
+use std::sync::RwLock;
+use std::time::Duration;
 use std::{hash::Hash, num::NonZeroUsize};
 
 #[cfg(dht)]
 use mainline::{errors::PutMutableError, Dht};
 
+pub use batch::DEFAULT_PUBLISH_CONCURRENCY;
 use builder::{ClientBuilder, Config};
+pub use cancel::CancellationToken;
+#[cfg(dht)]
+pub use diagnostics::DhtDiagnostic;
+pub use diagnostics::{CacheDiagnostic, DiagnosticReport};
+#[cfg(relays)]
+pub use diagnostics::{RelayDiagnostic, RelayOutcome};
+#[cfg(all(dht, relays))]
+pub use discovery::{DEFAULT_RELAYS_REFRESH_INTERVAL, RELAYS_RECORD_NAME};
+#[cfg(relays)]
+pub use health::{RelayHealth, DEFAULT_RELAY_COOLDOWN, DEFAULT_RELAY_FAILURE_THRESHOLD};
+#[cfg(relays)]
+pub use observer::RelayRequest;
+pub use observer::{ClientEvent, ClientObserver};
+pub use options::{CachePolicy, NetworkSelection, RequestOptions};
+use prefetch::Prefetcher;
+pub use prefetch::{PrefetchStats, DEFAULT_MAX_CONCURRENT_PREFETCHES};
+pub use quorum::{Quorum, QuorumError, ResolveOptions};
+#[cfg(dht)]
+pub use report::DhtPublishReport;
+pub use report::PublishReport;
+#[cfg(relays)]
+pub use report::RelayPublishReport;
+#[cfg(dht)]
+use resolved::elapsed_since;
+pub use resolved::{Resolved, Source};
+pub use retry::RetryPolicy;
+pub use static_zones::StaticZonesError;
+#[cfg(relays)]
+pub use transport::{
+    RelayResponse, RelayTransport, RelayTransportError, RelayTransportFuture, ReqwestTransport,
+    MAX_RESPONSE_BYTES,
+};
+pub use update::{UpdateError, DEFAULT_UPDATE_ATTEMPTS};
 
 #[cfg(relays)]
 use crate::client::relays::RelaysClient;
-use crate::{Cache, CacheKey, InMemoryCache};
+use crate::{AsyncCache, Cache, CacheKey, Clock, InMemoryCache};
 use crate::{PublicKey, SignedPacket};
 
+#[cfg(not(wasm_browser))]
+/// A [Stream] of [Resolved] packets, see [Client::resolve_stream].
+pub(crate) type ResolvedStream = Pin<Box<dyn Stream<Item = Resolved> + Send>>;
+#[cfg(wasm_browser)]
+/// A [Stream] of [Resolved] packets, see [Client::resolve_stream].
+pub(crate) type ResolvedStream = Pin<Box<dyn Stream<Item = Resolved>>>;
+
 #[derive(Debug)]
 pub(crate) struct Inner {
     minimum_ttl: u32,
     maximum_ttl: u32,
+    negative_ttl: u32,
     cache: Option<Arc<dyn Cache>>,
+    /// Either the [Self::cache], or a custom [AsyncCache].
+    async_cache: Option<Arc<dyn AsyncCache>>,
+    prefetcher: Option<Prefetcher>,
+    observer: Option<Arc<dyn ClientObserver>>,
+    clock: Arc<dyn Clock>,
     #[cfg(dht)]
-    dht: Option<Dht>,
+    retry_policy: Option<RetryPolicy>,
+    #[cfg(dht)]
+    /// Taken on [Client::shutdown].
+    dht: RwLock<Option<Dht>>,
     #[cfg(relays)]
     relays: Option<RelaysClient>,
+    shutdown: CancellationToken,
+    static_zones: HashMap<PublicKey, SignedPacket>,
+    inflight_resolves: batch::InflightResolves,
     #[cfg(feature = "endpoints")]
     pub(crate) max_recursion_depth: u8,
 }
@@ -63,27 +141,38 @@ pub struct Client(pub(crate) Arc<Inner>);
 
 impl Client {
     pub(crate) fn new(config: Config) -> Result<Client, BuildError> {
-        let cache = if config.cache_size == 0 {
+        let cache = if config.cache_size == 0 || config.async_cache.is_some() {
             None
         } else {
             let cache = config.cache.clone();
 
             if let Some(cache) = cache {
-                if cache.capacity() == 0 {
+                if Cache::capacity(cache.as_ref()) == 0 {
                     None
                 } else {
                     Some(cache)
                 }
             } else {
                 Some(
-                    cache.unwrap_or(Arc::new(InMemoryCache::new(
-                        NonZeroUsize::new(config.cache_size)
-                            .expect("if cache size is zero cache should be disabled."),
-                    ))),
+                    cache.unwrap_or(Arc::new(
+                        InMemoryCache::new(
+                            NonZeroUsize::new(config.cache_size)
+                                .expect("if cache size is zero cache should be disabled."),
+                        )
+                        .with_clock(config.clock.clone()),
+                    )),
                 )
             }
         };
 
+        let async_cache: Option<Arc<dyn AsyncCache>> = match &config.async_cache {
+            Some(async_cache) if config.cache_size == 0 || async_cache.capacity() == 0 => None,
+            Some(async_cache) => Some(async_cache.clone()),
+            None => cache
+                .clone()
+                .map(|cache| Arc::new(cache) as Arc<dyn AsyncCache>),
+        };
+
         cross_debug!("Starting Pkarr Client {:?}", config);
 
         #[cfg(dht)]
@@ -95,38 +184,108 @@ impl Client {
         #[cfg(not(dht))]
         let dht: Option<()> = None;
 
+        #[cfg(all(dht, relays))]
+        let discover_relays = dht.is_some() && !config.relays_from_keys.is_empty();
+        #[cfg(all(not(dht), relays))]
+        let discover_relays = false;
+
+        #[cfg(relays)]
+        let transport: Arc<dyn RelayTransport> =
+            match (&config.relay_transport, &config.relay_http_client) {
+                (Some(transport), _) => transport.clone(),
+                (None, Some(http_client)) => Arc::new(ReqwestTransport::new(http_client.clone())),
+                (None, None) => {
+                    #[allow(unused_mut)]
+                    let mut builder = reqwest::Client::builder();
+
+                    #[cfg(not(wasm_browser))]
+                    if let Some(proxy) = &config.relay_proxy {
+                        builder = builder.proxy(proxy.clone());
+                    }
+
+                    Arc::new(ReqwestTransport::new(
+                        builder
+                            .build()
+                            .expect("Client building should be infallible"),
+                    ))
+                }
+            };
+
         #[cfg(relays)]
         let relays = if let Some(ref relays) = config.relays {
             if relays.is_empty() {
                 return Err(BuildError::EmptyListOfRelays);
             }
 
-            let relays_client =
-                RelaysClient::new(relays.clone().into_boxed_slice(), config.request_timeout);
+            let relays_client = RelaysClient::new(
+                relays.clone().into_boxed_slice(),
+                transport,
+                config.request_timeout,
+                config.relay_health,
+                config.retry_policy,
+                config.observer.clone(),
+                config.clock.clone(),
+            );
 
             Some(relays_client)
+        } else if discover_relays {
+            // Start with no relays, until the relays lists are resolved from the Dht.
+            Some(RelaysClient::new(
+                Box::new([]),
+                transport,
+                config.request_timeout,
+                config.relay_health,
+                config.retry_policy,
+                config.observer.clone(),
+                config.clock.clone(),
+            ))
         } else {
             None
         };
         #[cfg(not(relays))]
         let relays: Option<()> = None;
 
-        if dht.is_none() && relays.is_none() {
+        if dht.is_none() && relays.is_none() && config.static_zones.is_empty() {
             return Err(BuildError::NoNetwork);
         }
 
         let client = Client(Arc::new(Inner {
             minimum_ttl: config.minimum_ttl,
             maximum_ttl: config.maximum_ttl,
+            negative_ttl: config.negative_ttl,
             cache,
+            async_cache,
+            prefetcher: Prefetcher::new(
+                config.prefetch_threshold,
+                config.max_concurrent_prefetches,
+                config.prefetch_min_hits,
+                config.cache_size,
+            ),
+            observer: config.observer,
+            clock: config.clock,
+            #[cfg(dht)]
+            retry_policy: config.retry_policy,
             #[cfg(dht)]
-            dht,
+            dht: RwLock::new(dht),
             #[cfg(relays)]
             relays,
+            shutdown: CancellationToken::new(),
+            static_zones: config.static_zones,
+            inflight_resolves: Default::default(),
             #[cfg(feature = "endpoints")]
             max_recursion_depth: config.max_recursion_depth,
         }));
 
+        #[cfg(all(dht, relays))]
+        if discover_relays {
+            discovery::spawn(
+                &client,
+                config.relays_from_keys,
+                config.relays.unwrap_or_default(),
+                config.relays_refresh_interval,
+            );
+        }
+
         Ok(client)
     }
 
@@ -141,10 +300,25 @@ impl Client {
     // === Getters ===
 
     /// Returns a reference to the internal cache.
+    ///
+    /// Returns `None` if the client uses a custom [ClientBuilder::async_cache],
+    /// see [Self::async_cache].
     pub fn cache(&self) -> Option<&dyn Cache> {
         self.0.cache.as_deref()
     }
 
+    /// Returns a reference to the internal cache as an [AsyncCache].
+    ///
+    /// Unlike [Self::cache], this also returns a custom [ClientBuilder::async_cache].
+    pub fn async_cache(&self) -> Option<&dyn AsyncCache> {
+        self.0.async_cache.as_deref()
+    }
+
+    /// Returns the [Clock] used for TTL and expiry, see [ClientBuilder::clock].
+    pub fn clock(&self) -> &dyn Clock {
+        self.0.clock.as_ref()
+    }
+
     /// Returns a reference to the internal [mainline::Dht] node.
     ///
     /// Gives you access to methods like [mainline::Dht::info],
@@ -152,7 +326,18 @@ impl Client {
     /// among the rest of the API.
     #[cfg(dht)]
     pub fn dht(&self) -> Option<mainline::Dht> {
-        self.0.dht.as_ref().cloned()
+        self.0.dht.read().expect("Client::dht lock").clone()
+    }
+
+    /// Returns the current [Relays](https://pkarr.org/relays), including relays discovered
+    /// with [ClientBuilder::relays_from_key], or an empty list if relays are disabled.
+    #[cfg(relays)]
+    pub fn relays(&self) -> Vec<url::Url> {
+        self.0
+            .relays
+            .as_ref()
+            .map(|relays| relays.relays().to_vec())
+            .unwrap_or_default()
     }
 
     // === Publish ===
@@ -235,13 +420,18 @@ impl Client {
     /// 2. [ConcurrencyError]: when an write conflict (or the risk of it) is detedcted.
     ///
     /// If you get a [ConcurrencyError]; you should resolver the most recent packet again,
-    /// and repeat the steps in the previous example.
+    /// and repeat the steps in the previous example, or use [Self::update] which does that for you.
     pub async fn publish(
         &self,
         signed_packet: &SignedPacket,
         cas: Option<Timestamp>,
     ) -> Result<(), PublishError> {
-        async_compat_if_necessary(self.publish_inner(signed_packet, cas)).await
+        async_compat_if_necessary(self.publish_inner(
+            signed_packet,
+            cas,
+            &RequestOptions::default(),
+        ))
+        .await
     }
 
     // === Resolve ===
@@ -253,7 +443,7 @@ impl Client {
     /// If you want to get the most recent version of a [SignedPacket],
     /// you should use [Self::resolve_most_recent].
     pub async fn resolve(&self, public_key: &PublicKey) -> Option<SignedPacket> {
-        async_compat_if_necessary(self.resolve_inner(public_key)).await
+        async_compat_if_necessary(self.resolve_inner(public_key, &RequestOptions::default())).await
     }
 
     /// Returns the most recent [SignedPacket] found after querying all
@@ -264,40 +454,170 @@ impl Client {
     ///
     /// This is a best effort, and doesn't guarantee consistency.
     pub async fn resolve_most_recent(&self, public_key: &PublicKey) -> Option<SignedPacket> {
+        if let Some(static_zone) = self.static_zone(public_key) {
+            return Some(static_zone);
+        }
+
         async_compat_if_necessary(async move {
             let cache_key: CacheKey = public_key.as_ref().into();
 
-            let cache = self.0.cache.clone().unwrap_or(Arc::new(InMemoryCache::new(
-                1.try_into().expect("infallible"),
-            )));
+            let cache = self
+                .0
+                .async_cache
+                .clone()
+                .unwrap_or(Arc::new(InMemoryCache::new(
+                    1.try_into().expect("infallible"),
+                )));
 
-            let mut stream = self.resolve_stream(
+            let mut stream = self.more_recent_stream(
                 public_key.clone(),
                 Some(cache.clone()),
                 cache_key,
-                cache.get(&cache_key).map(|s| s.timestamp()),
+                cache.get(&cache_key).await.map(|s| s.timestamp()),
+                &RequestOptions::default(),
             );
             while stream.next().await.is_some() {}
 
-            cache.get(&public_key.into())
+            cache.get_read_only(&cache_key).await
         })
         .await
     }
 
+    /// Returns a [Stream] of every [Resolved] packet for the given [PublicKey],
+    /// along with its [Source] and the latency of receiving it.
+    ///
+    /// The stream starts with the cached packet (if any), followed by every valid
+    /// packet received from the [mainline] Dht and or [Relays](https://pkarr.org/relays),
+    /// including duplicates and packets older than the one in the cache.
+    /// It ends once all queries are done.
+    ///
+    /// More recent packets are still stored in the cache as they arrive, same as [Self::resolve].
+    ///
+    /// Useful for showing where a record came from, or implementing your own selection logic.
+    ///
+    /// If the key has a [static zone](ClientBuilder::static_zone), the stream only yields that packet.
+    pub fn resolve_stream(&self, public_key: &PublicKey) -> ResolvedStream {
+        if let Some(packet) = self.static_zone(public_key) {
+            return Box::pin(futures_lite::stream::once(Resolved {
+                packet,
+                source: Source::Static,
+                latency: Duration::ZERO,
+            }));
+        }
+
+        let cache_key: CacheKey = public_key.into();
+        let cache = self.0.async_cache.clone();
+
+        let cached = futures_lite::stream::once_future({
+            let cache = cache.clone();
+
+            async move { cache?.get(&cache_key).await }
+        })
+        .filter_map(|cached| {
+            cached.map(|packet| Resolved {
+                packet,
+                source: Source::Cache,
+                latency: Duration::ZERO,
+            })
+        });
+
+        let public_key = public_key.clone();
+
+        let network = self
+            .network_stream(&public_key, None, &RequestOptions::default())
+            .then(move |resolved| {
+                let public_key = public_key.clone();
+                let cache = cache.clone();
+
+                async move {
+                    filter_incoming_signed_packet(
+                        &public_key,
+                        cache,
+                        &cache_key,
+                        resolved.packet.clone(),
+                    )
+                    .await;
+
+                    resolved
+                }
+            });
+
+        let stream = Box::pin(cached.chain(network));
+
+        // Poll each item in a tokio runtime if necessary.
+        Box::pin(futures_lite::stream::unfold(stream, |mut stream| {
+            async_compat_if_necessary(async move {
+                let next = stream.next().await?;
+
+                Some((next, stream))
+            })
+        }))
+    }
+
     // === Private Methods ===
 
     async fn publish_inner(
         &self,
         signed_packet: &SignedPacket,
         cas: Option<Timestamp>,
+        options: &RequestOptions,
+    ) -> Result<(), PublishError> {
+        let result =
+            futures_lite::future::or(self.check_and_publish(signed_packet, cas, options), async {
+                self.0.shutdown.cancelled().await;
+
+                Err(QueryError::Cancelled.into())
+            })
+            .await;
+
+        self.notify(|| ClientEvent::Published {
+            public_key: signed_packet.public_key(),
+            result: result.clone(),
+        });
+
+        result
+    }
+
+    async fn check_and_publish(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        options: &RequestOptions,
+    ) -> Result<(), PublishError> {
+        if self.is_shutdown() {
+            return Err(QueryError::Cancelled.into());
+        }
+
+        self.check_and_cache(signed_packet, cas, options).await?;
+
+        if options.cache == CachePolicy::Only {
+            return Ok(());
+        }
+
+        self.select_publish_future(signed_packet, cas, options)
+            .await
+    }
+
+    /// Check the cache for conflicts, then store the packet in the cache.
+    async fn check_and_cache(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        options: &RequestOptions,
     ) -> Result<(), PublishError> {
         let cache_key: CacheKey = signed_packet.public_key().into();
 
+        let cache = self.async_cache();
+
+        let cached = match cache {
+            Some(cache) => cache.get_read_only(&cache_key).await,
+            None => None,
+        };
+
         // Check conflict
-        if let Some(cached) = self
-            .cache()
+        if let Some(cached) = cached
             .as_ref()
-            .and_then(|cache| cache.get(&cache_key))
+            .filter(|_| options.cache != CachePolicy::Bypass)
         {
             if cached.more_recent_than(signed_packet) {
                 return Err(ConcurrencyError::NotMostRecent)?;
@@ -308,11 +628,14 @@ impl Client {
             }
         }
 
-        if let Some(cache) = self.cache() {
-            cache.put(&cache_key, signed_packet);
+        // Even when bypassing the conflict check, never replace a more recent cached packet.
+        let outdated = cached.is_some_and(|cached| cached.more_recent_than(signed_packet));
+
+        if let Some(cache) = cache.filter(|_| options.cache != CachePolicy::NoStore && !outdated) {
+            cache.put(&cache_key, signed_packet).await;
         }
 
-        self.select_publish_future(signed_packet, cas).await
+        Ok(())
     }
 
     /// Returns the first result from either the DHT or the Relays client or both.
@@ -320,37 +643,49 @@ impl Client {
         &self,
         signed_packet: &SignedPacket,
         cas: Option<Timestamp>,
+        options: &RequestOptions,
     ) -> Result<(), PublishError> {
         // Handle DHT and Relay futures based on feature flags and target family
         #[cfg(dht)]
         let dht_future = {
             let signed_packet = signed_packet.clone();
-            self.dht().map(|node| async move {
-                node.as_async()
-                    .put_mutable((&signed_packet).into(), cas.map(|t| t.as_u64() as i64))
+            self.dht().filter(|_| options.use_dht()).map(|node| {
+                let retry_policy = self.0.retry_policy;
+
+                async move {
+                    retry::put_mutable(
+                        &node.as_async(),
+                        (&signed_packet).into(),
+                        cas.map(|t| t.as_u64() as i64),
+                        retry_policy.as_ref(),
+                    )
                     .await
-                    .map(|_| Ok(()))?
+                }
             })
         };
 
         #[cfg(relays)]
         let relays_future = {
             let signed_packet = signed_packet.clone();
-            self.0
-                .relays
-                .clone()
+            self.relays_for(options)
                 .map(|relays| async move { relays.publish(&signed_packet, cas).await })
         };
 
         #[cfg(all(dht, not(relays)))]
-        return dht_future.expect("infallible").await;
+        return match dht_future {
+            Some(dht_future) => dht_future.await,
+            None => Err(QueryError::NoNetwork.into()),
+        };
 
         #[cfg(all(relays, not(dht)))]
-        return relays_future.expect("infallible").await;
+        return match relays_future {
+            Some(relays_future) => relays_future.await,
+            None => Err(QueryError::NoNetwork.into()),
+        };
 
         #[cfg(all(dht, relays))]
-        return if let Some(dht_future) = dht_future {
-            if let Some(relays_future) = relays_future {
+        return match (dht_future, relays_future) {
+            (Some(dht_future), Some(relays_future)) => {
                 let result = publish_both_networks(dht_future, relays_future).await;
 
                 self.0
@@ -360,40 +695,112 @@ impl Client {
                     .cancel_publish(&signed_packet.public_key());
 
                 result
-            } else {
-                dht_future.await
             }
-        } else {
-            relays_future.expect("infallible").await
+            (Some(dht_future), None) => dht_future.await,
+            (None, Some(relays_future)) => relays_future.await,
+            (None, None) => Err(QueryError::NoNetwork.into()),
         };
     }
 
-    pub(crate) async fn resolve_inner(&self, public_key: &PublicKey) -> Option<SignedPacket> {
+    pub(crate) async fn resolve_inner(
+        &self,
+        public_key: &PublicKey,
+        options: &RequestOptions,
+    ) -> Option<SignedPacket> {
+        if let Some(static_zone) = self.static_zone(public_key) {
+            return Some(static_zone);
+        }
+
         let public_key = public_key.clone();
 
         let cache_key: CacheKey = public_key.as_ref().into();
 
-        let cached_packet = self
-            .cache()
-            .as_ref()
-            .and_then(|cache| cache.get(&cache_key));
+        match options.cache {
+            CachePolicy::Only => {
+                let cached_packet = self.async_cache()?.get(&cache_key).await;
+
+                self.notify_cache(&public_key, cached_packet.as_ref());
+
+                return cached_packet;
+            }
+            CachePolicy::Bypass => {
+                // Wait for the earliest positive response, regardless of the cached packet.
+                let first = self
+                    .network_stream(&public_key, None, options)
+                    .next()
+                    .await?
+                    .packet;
+
+                filter_incoming_signed_packet(
+                    &public_key,
+                    self.0.async_cache.clone(),
+                    &cache_key,
+                    first.clone(),
+                )
+                .await;
+
+                return Some(first);
+            }
+            CachePolicy::Use | CachePolicy::NoStore => {}
+        }
+
+        let store = options.cache == CachePolicy::Use;
 
-        // Stream is a future, so it won't run until we await or spawn it.
-        let mut stream = self.resolve_stream(
-            public_key.clone(),
-            self.0.cache.clone(),
-            cache_key,
-            cached_packet.as_ref().map(|s| s.timestamp()),
-        );
+        let cached_packet = match self.async_cache() {
+            Some(cache) => cache.get(&cache_key).await,
+            None => None,
+        };
+
+        self.notify_cache(&public_key, cached_packet.as_ref());
 
         if let Some(cached_packet) = cached_packet {
-            if cached_packet.is_expired(self.0.minimum_ttl, self.0.maximum_ttl) {
+            let expired = cached_packet.is_expired_at(
+                self.0.minimum_ttl,
+                self.0.maximum_ttl,
+                self.0.clock.now(),
+            );
+
+            if !store {
+                if !expired {
+                    return Some(cached_packet);
+                }
+
+                // The cache can't be refreshed in the background without storing,
+                // so wait for a more recent packet instead.
+                let mut stream = self.more_recent_stream(
+                    public_key.clone(),
+                    None,
+                    cache_key,
+                    Some(cached_packet.timestamp()),
+                    options,
+                );
+
+                while let Some(packet) = stream.next().await {
+                    if packet.more_recent_than(&cached_packet) {
+                        return Some(packet);
+                    }
+                }
+
+                return Some(cached_packet);
+            }
+
+            if expired {
+                let mut stream = self.more_recent_stream(
+                    public_key.clone(),
+                    self.0.async_cache.clone(),
+                    cache_key,
+                    Some(cached_packet.timestamp()),
+                    options,
+                );
+
                 #[cfg(not(wasm_browser))]
                 tokio::spawn(async move { while stream.next().await.is_some() {} });
                 #[cfg(wasm_browser)]
                 wasm_bindgen_futures::spawn_local(
                     async move { while stream.next().await.is_some() {} },
                 );
+            } else {
+                self.maybe_prefetch(&public_key, &cached_packet);
             }
 
             cross_debug!(
@@ -401,108 +808,231 @@ impl Client {
                 &public_key
             );
 
-            self.cache().expect("infallible").get(&cache_key)
+            self.async_cache()
+                .expect("infallible")
+                .get_read_only(&cache_key)
+                .await
         } else {
+            if self.has_recent_miss(&cache_key).await {
+                cross_debug!(
+                    "responding with None after a recent miss. public_key: {}",
+                    &public_key
+                );
+
+                return None;
+            }
+
+            // Only create the stream once the network is needed,
+            // because the Dht query is sent as soon as it is created.
+            let mut stream = self.more_recent_stream(
+                public_key.clone(),
+                self.0.async_cache.clone().filter(|_| store),
+                cache_key,
+                None,
+                options,
+            );
+
             // Wait for the earliest positive response.
             let first = stream.next().await;
 
-            if let Some(cache) = self.cache() {
-                cache.get(&cache_key)
+            if first.is_none() && store && self.0.negative_ttl > 0 {
+                if let Some(cache) = self.async_cache() {
+                    cache.put_miss(&cache_key).await;
+                }
+            }
+
+            if let Some(cache) = self.async_cache().filter(|_| store) {
+                cache.get_read_only(&cache_key).await
             } else {
                 first
             }
         }
     }
 
-    #[cfg(wasm_browser)]
-    fn resolve_stream(
+    /// Emit a cache hit or miss event, if the cache is enabled.
+    fn notify_cache(&self, public_key: &PublicKey, cached_packet: Option<&SignedPacket>) {
+        if self.0.async_cache.is_none() {
+            return;
+        }
+
+        self.notify(|| match cached_packet {
+            Some(cached_packet) => ClientEvent::CacheHit {
+                public_key: public_key.clone(),
+                expired: cached_packet.is_expired_at(
+                    self.0.minimum_ttl,
+                    self.0.maximum_ttl,
+                    self.0.clock.now(),
+                ),
+            },
+            None => ClientEvent::CacheMiss {
+                public_key: public_key.clone(),
+            },
+        });
+    }
+
+    /// Returns true if a miss was recorded for this key within the negative TTL.
+    async fn has_recent_miss(&self, cache_key: &CacheKey) -> bool {
+        if self.0.negative_ttl == 0 {
+            return false;
+        }
+
+        let missed_at = match self.async_cache() {
+            Some(cache) => cache.get_miss(cache_key).await,
+            None => None,
+        };
+
+        missed_at.is_some_and(|missed_at| {
+            let elapsed = self
+                .0
+                .clock
+                .now()
+                .as_u64()
+                .saturating_sub(missed_at.as_u64());
+
+            Duration::from_micros(elapsed) < Duration::from_secs(self.0.negative_ttl.into())
+        })
+    }
+
+    /// Returns a [Stream] of incoming [SignedPacket]s that are more recent than
+    /// the one in the cache, while storing them in the cache.
+    fn more_recent_stream(
         &self,
         public_key: PublicKey,
-        cache: Option<Arc<dyn Cache>>,
+        cache: Option<Arc<dyn AsyncCache>>,
         cache_key: CacheKey,
         more_recent_than: Option<Timestamp>,
-    ) -> Pin<Box<dyn Stream<Item = SignedPacket>>> {
+        options: &RequestOptions,
+    ) -> impl Stream<Item = SignedPacket> + Unpin {
+        Box::pin(
+            self.network_stream(&public_key, more_recent_than, options)
+                .then(move |resolved| {
+                    let public_key = public_key.clone();
+                    let cache = cache.clone();
+
+                    async move {
+                        filter_incoming_signed_packet(
+                            &public_key,
+                            cache,
+                            &cache_key,
+                            resolved.packet,
+                        )
+                        .await
+                    }
+                })
+                .filter_map(|packet| packet),
+        )
+    }
+
+    /// Returns a [Stream] of packets from the networks selected in the [RequestOptions],
+    /// that ends once all queries are done, or the client is shut down.
+    fn network_stream(
+        &self,
+        public_key: &PublicKey,
+        more_recent_than: Option<Timestamp>,
+        options: &RequestOptions,
+    ) -> ResolvedStream {
+        let clock = self.0.clock.clone();
+
+        // Packets are seen now, according to the client's clock.
         let stream = self
-            .0
-            .relays
-            .as_ref()
-            .expect("infallible")
-            .resolve_futures(&public_key, more_recent_than)
-            .filter_map(|opt| opt)
-            .filter_map(move |signed_packet| {
-                filter_incoming_signed_packet(&public_key, cache.clone(), &cache_key, signed_packet)
+            .select_network_stream(public_key, more_recent_than, options)
+            .map(move |mut resolved| {
+                resolved.packet.refresh_at(clock.now());
+
+                resolved
             });
 
-        Box::pin(stream)
+        self.until_shutdown(Box::pin(stream))
     }
 
-    #[cfg(not(wasm_browser))]
-    /// Returns a [Stream] of incoming [SignedPacket]s.
-    fn resolve_stream(
+    #[cfg(wasm_browser)]
+    /// Returns a [Stream] from the Relays client.
+    fn select_network_stream(
         &self,
-        public_key: PublicKey,
-        cache: Option<Arc<dyn Cache>>,
-        cache_key: CacheKey,
+        public_key: &PublicKey,
         more_recent_than: Option<Timestamp>,
-    ) -> Pin<Box<dyn Stream<Item = SignedPacket> + Send>> {
-        self.merged_resolve_stream(&public_key, more_recent_than)
-            .filter_map(move |signed_packet| {
-                filter_incoming_signed_packet(&public_key, cache.clone(), &cache_key, signed_packet)
-            })
-            .boxed()
+        options: &RequestOptions,
+    ) -> ResolvedStream {
+        match self.relays_for(options) {
+            Some(relays) => Box::pin(
+                relays
+                    .resolve_futures(public_key, more_recent_than)
+                    .filter_map(|opt| opt),
+            ),
+            None => Box::pin(futures_lite::stream::empty()),
+        }
     }
 
     #[cfg(not(wasm_browser))]
     /// Returns a Stream from both the DHT and Relays client.
-    fn merged_resolve_stream(
+    fn select_network_stream(
         &self,
         public_key: &PublicKey,
         more_recent_than: Option<Timestamp>,
-    ) -> Pin<Box<dyn Stream<Item = SignedPacket> + Send>> {
-        use futures::select_stream;
-
+        options: &RequestOptions,
+    ) -> ResolvedStream {
         #[cfg(dht)]
-        let dht_stream = match self.dht() {
-            Some(node) => map_dht_stream(node.as_async().get_mutable(
-                public_key.as_bytes(),
-                None,
-                more_recent_than.map(|t| t.as_u64() as i64),
-            )),
+        let dht_stream = match self.dht().filter(|_| options.use_dht()) {
+            Some(node) => map_dht_stream(
+                node.as_async().get_mutable(
+                    public_key.as_bytes(),
+                    None,
+                    more_recent_than.map(|t| t.as_u64() as i64),
+                ),
+                public_key.clone(),
+                self.0.observer.clone(),
+            ),
             None => None,
         };
 
         #[cfg(relays)]
         let relays_stream = self
-            .0
-            .relays
-            .as_ref()
+            .relays_for(options)
             .map(|relays| relays.resolve(public_key, more_recent_than));
 
         #[cfg(all(dht, not(relays)))]
-        return dht_stream.expect("infallible");
+        return dht_stream.unwrap_or_else(|| Box::pin(futures_lite::stream::empty()));
 
         #[cfg(all(relays, not(dht)))]
-        return relays_stream.expect("infallible");
+        return relays_stream.unwrap_or_else(|| Box::pin(futures_lite::stream::empty()));
 
         #[cfg(all(dht, relays))]
         match (dht_stream, relays_stream) {
             (Some(s), None) | (None, Some(s)) => s,
-            (Some(a), Some(b)) => Box::pin(select_stream(a, b)),
-            (None, None) => unreachable!("should not create a client with no network"),
+            (Some(a), Some(b)) => Box::pin(futures::select_stream(a, b)),
+            (None, None) => Box::pin(futures_lite::stream::empty()),
         }
     }
+
+    #[cfg(relays)]
+    /// Returns the Relays client if selected in the [RequestOptions] and has any relays,
+    /// with the request's timeout if any.
+    fn relays_for(&self, options: &RequestOptions) -> Option<RelaysClient> {
+        let relays = self
+            .0
+            .relays
+            .as_ref()
+            .filter(|relays| options.use_relays() && !relays.relays().is_empty())?;
+
+        Some(match options.timeout {
+            Some(timeout) => relays.with_timeout(timeout),
+            None => relays.clone(),
+        })
+    }
 }
 
-fn filter_incoming_signed_packet(
+async fn filter_incoming_signed_packet(
     public_key: &PublicKey,
-    cache: Option<Arc<dyn Cache>>,
+    cache: Option<Arc<dyn AsyncCache>>,
     cache_key: &CacheKey,
     signed_packet: SignedPacket,
 ) -> Option<SignedPacket> {
-    let new_packet: Option<SignedPacket> = if let Some(cached) = cache
-        .clone()
-        .and_then(|cache| cache.clone().get_read_only(cache_key))
-    {
+    let cached = match &cache {
+        Some(cache) => cache.get_read_only(cache_key).await,
+        None => None,
+    };
+
+    let new_packet: Option<SignedPacket> = if let Some(cached) = cached {
         if signed_packet.more_recent_than(&cached) {
             cross_debug!("Received more recent packet than in cache. public_key: {public_key}",);
 
@@ -518,7 +1048,7 @@ fn filter_incoming_signed_packet(
 
     if let Some(packet) = new_packet {
         if let Some(cache) = &cache {
-            cache.put(cache_key, &packet)
+            cache.put(cache_key, &packet).await
         };
 
         Some(packet)
@@ -530,14 +1060,34 @@ fn filter_incoming_signed_packet(
 #[cfg(dht)]
 fn map_dht_stream(
     stream: mainline::async_dht::GetStream<mainline::MutableItem>,
-) -> Option<Pin<Box<dyn Stream<Item = SignedPacket> + Send>>> {
+    public_key: PublicKey,
+    observer: Option<Arc<dyn ClientObserver>>,
+) -> Option<ResolvedStream> {
+    let started_at = Timestamp::now();
+
     Some(
         stream
             .filter_map(
                 move |mutable_item| match SignedPacket::try_from(mutable_item) {
-                    Ok(signed_packet) => Some(signed_packet),
+                    Ok(signed_packet) => {
+                        let resolved = Resolved::new(signed_packet, Source::Dht, started_at);
+
+                        observer::notify(observer.as_ref(), || ClientEvent::DhtResponse {
+                            public_key: public_key.clone(),
+                            latency: resolved.latency,
+                        });
+
+                        Some(resolved)
+                    }
                     Err(error) => {
                         cross_debug!("Got an invalid signed packet from the DHT. Error: {error}");
+
+                        observer::notify(observer.as_ref(), || ClientEvent::PacketRejected {
+                            public_key: public_key.clone(),
+                            source: Source::Dht,
+                            error,
+                        });
+
                         None
                     }
                 },
@@ -549,8 +1099,8 @@ fn map_dht_stream(
 #[derive(thiserror::Error, Debug)]
 /// Errors occurring during building a [Client]
 pub enum BuildError {
-    #[error("Client configured without Mainline node or relays.")]
-    /// Client configured without Mainline node or relays.
+    #[error("Client configured without Mainline node, relays, or static zones.")]
+    /// Client configured without Mainline node, relays, or static zones.
     NoNetwork,
 
     #[error("Failed to build the Dht client {0}")]
@@ -599,6 +1149,14 @@ pub enum QueryError {
     #[error("Most relays responded with bad request")]
     /// Most relays responded with bad request
     BadRequest,
+
+    #[error("None of the networks selected for this request are enabled in this client.")]
+    /// None of the networks selected in the [RequestOptions] are enabled in this client.
+    NoNetwork,
+
+    #[error("The request was cancelled, or the client was shut down.")]
+    /// The request was cancelled with a [CancellationToken], or the client was [shut down](Client::shutdown).
+    Cancelled,
 }
 
 #[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Hash)]
@@ -645,6 +1203,27 @@ impl From<PutMutableError> for PublishError {
     }
 }
 
+/// Runtime agnostic sleep, to be used in background loops.
+#[cfg(not(wasm_browser))]
+pub(crate) async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await
+}
+
+/// Runtime agnostic sleep, to be used in background loops.
+#[cfg(wasm_browser)]
+pub(crate) async fn sleep(duration: Duration) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let global = js_sys::global();
+
+        if let Ok(set_timeout) = js_sys::Reflect::get(&global, &"setTimeout".into()) {
+            let set_timeout: js_sys::Function = set_timeout.into();
+            let _ = set_timeout.call2(&global, &resolve, &(duration.as_millis() as f64).into());
+        }
+    });
+
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
 async fn async_compat_if_necessary<T, O>(fut: T) -> O
 where
     T: Future<Output = O>,
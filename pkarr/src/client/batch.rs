@@ -0,0 +1,236 @@
+//! Resolve and publish many [SignedPacket]s at once, see [Client::resolve_many] and [Client::publish_many].
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures_buffered::BufferedStreamExt;
+use futures_lite::{stream, Stream, StreamExt};
+
+use crate::{PublicKey, SignedPacket};
+
+use super::{async_compat_if_necessary, Client, PublishError, RequestOptions};
+
+/// Suggested maximum number of [SignedPacket]s published concurrently by [Client::publish_many].
+pub const DEFAULT_PUBLISH_CONCURRENCY: usize = 16;
+
+#[cfg(not(wasm_browser))]
+/// A [Stream] of resolved keys, see [Client::resolve_many].
+pub(crate) type ResolveManyStream =
+    Pin<Box<dyn Stream<Item = (PublicKey, Option<SignedPacket>)> + Send>>;
+#[cfg(wasm_browser)]
+/// A [Stream] of resolved keys, see [Client::resolve_many].
+pub(crate) type ResolveManyStream = Pin<Box<dyn Stream<Item = (PublicKey, Option<SignedPacket>)>>>;
+
+impl Client {
+    /// Resolve many [PublicKey]s, resolving at most `concurrency` keys at the same time
+    /// (each from both the [mainline] Dht and [Relays](https://pkarr.org/relays) if enabled).
+    ///
+    /// Returns a [Stream] of each key and the result of [Self::resolve] for that key,
+    /// in the order of completion, so keys found in the cache are yielded first.
+    ///
+    /// Duplicate keys are only resolved and yielded once. Keys that are already being
+    /// resolved by another call to this method on the same [Client] (or its clones)
+    /// wait for that resolution instead of querying the network again.
+    pub fn resolve_many(
+        &self,
+        public_keys: impl IntoIterator<Item = PublicKey>,
+        concurrency: usize,
+    ) -> ResolveManyStream {
+        let mut seen = HashSet::new();
+
+        let public_keys = public_keys
+            .into_iter()
+            .filter(|public_key| seen.insert(public_key.clone()))
+            .collect::<Vec<_>>();
+
+        let client = self.clone();
+
+        let stream = stream::iter(public_keys)
+            .map(move |public_key| {
+                let client = client.clone();
+
+                async move {
+                    let signed_packet = client.resolve_deduplicated(&public_key).await;
+
+                    (public_key, signed_packet)
+                }
+            })
+            .buffered_unordered(concurrency.max(1));
+
+        Box::pin(stream::unfold(Box::pin(stream), |mut stream| async move {
+            async_compat_if_necessary(stream.next())
+                .await
+                .map(|item| (item, stream))
+        }))
+    }
+
+    /// Publish many [SignedPacket]s, publishing at most `concurrency` packets at the same time
+    /// (see [DEFAULT_PUBLISH_CONCURRENCY]), and returns the result of [Self::publish]
+    /// for each [PublicKey].
+    ///
+    /// If more than one packet is given for the same [PublicKey],
+    /// only the most recent one is published.
+    ///
+    /// Packets are published without a `CAS`, see [Self::publish] for more details.
+    pub async fn publish_many(
+        &self,
+        signed_packets: impl IntoIterator<Item = SignedPacket>,
+        concurrency: usize,
+    ) -> Vec<(PublicKey, Result<(), PublishError>)> {
+        let mut most_recent: HashMap<PublicKey, SignedPacket> = HashMap::new();
+
+        for signed_packet in signed_packets {
+            let public_key = signed_packet.public_key();
+
+            match most_recent.get(&public_key) {
+                Some(existing) if !signed_packet.more_recent_than(existing) => {}
+                _ => {
+                    most_recent.insert(public_key, signed_packet);
+                }
+            }
+        }
+
+        async_compat_if_necessary(
+            stream::iter(most_recent)
+                .map(|(public_key, signed_packet)| async move {
+                    let result = self
+                        .publish_inner(&signed_packet, None, &RequestOptions::default())
+                        .await;
+
+                    (public_key, result)
+                })
+                .buffered_unordered(concurrency.max(1))
+                .collect(),
+        )
+        .await
+    }
+
+    /// Resolve a key, or wait for the result of an inflight [Self::resolve_deduplicated]
+    /// of the same key.
+    async fn resolve_deduplicated(&self, public_key: &PublicKey) -> Option<SignedPacket> {
+        loop {
+            match self.0.inflight_resolves.join(public_key) {
+                Join::Leader(leader) => {
+                    let signed_packet = self
+                        .resolve_inner(public_key, &RequestOptions::default())
+                        .await;
+
+                    leader.finish(signed_packet.clone());
+
+                    return signed_packet;
+                }
+                Join::Follower(wait) => {
+                    if let Some(signed_packet) = wait.await {
+                        return signed_packet;
+                    }
+
+                    // The leader was dropped before it finished, try again.
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// Resolves in progress by [Client::resolve_many], keyed by [PublicKey].
+pub(crate) struct InflightResolves {
+    requests: Arc<Mutex<HashMap<PublicKey, Arc<Mutex<InflightResolve>>>>>,
+}
+
+#[derive(Debug)]
+enum InflightResolve {
+    Pending(Vec<Waker>),
+    Done(Option<SignedPacket>),
+    /// The leader was dropped before it finished.
+    Abandoned,
+}
+
+enum Join {
+    /// No resolve is in progress for this key, the caller has to resolve it.
+    Leader(Leader),
+    /// Wait for the leader's result.
+    Follower(Wait),
+}
+
+impl InflightResolves {
+    fn join(&self, public_key: &PublicKey) -> Join {
+        let mut requests = self.requests.lock().expect("InflightResolves lock");
+
+        if let Some(state) = requests.get(public_key) {
+            return Join::Follower(Wait(state.clone()));
+        }
+
+        let state = Arc::new(Mutex::new(InflightResolve::Pending(vec![])));
+        requests.insert(public_key.clone(), state.clone());
+
+        Join::Leader(Leader {
+            public_key: public_key.clone(),
+            requests: self.requests.clone(),
+            state,
+        })
+    }
+}
+
+/// Removes the inflight resolve once it is done, or wakes the followers
+/// to try again if it is dropped before it finished.
+struct Leader {
+    public_key: PublicKey,
+    requests: Arc<Mutex<HashMap<PublicKey, Arc<Mutex<InflightResolve>>>>>,
+    state: Arc<Mutex<InflightResolve>>,
+}
+
+impl Leader {
+    fn finish(&self, signed_packet: Option<SignedPacket>) {
+        self.settle(InflightResolve::Done(signed_packet));
+    }
+
+    fn settle(&self, result: InflightResolve) {
+        let mut state = self.state.lock().expect("InflightResolve lock");
+
+        if let InflightResolve::Pending(wakers) = std::mem::replace(&mut *state, result) {
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.requests
+            .lock()
+            .expect("InflightResolves lock")
+            .remove(&self.public_key);
+
+        if matches!(
+            *self.state.lock().expect("InflightResolve lock"),
+            InflightResolve::Pending(_)
+        ) {
+            self.settle(InflightResolve::Abandoned);
+        }
+    }
+}
+
+/// Resolves to the leader's result, or `None` if the leader was dropped before it finished.
+struct Wait(Arc<Mutex<InflightResolve>>);
+
+impl Future for Wait {
+    type Output = Option<Option<SignedPacket>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().expect("InflightResolve lock");
+
+        match &mut *state {
+            InflightResolve::Pending(wakers) => {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+
+                Poll::Pending
+            }
+            InflightResolve::Done(signed_packet) => Poll::Ready(Some(signed_packet.clone())),
+            InflightResolve::Abandoned => Poll::Ready(None),
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use ntimestamp::Timestamp;
 
-use crate::{Cache, PublicKey, SignedPacket};
+use crate::{
+    Cache, CancellationToken, DiagnosticReport, Keypair, PublicKey, PublishReport, Quorum,
+    RequestOptions, ResolveOptions, SignedPacket, SignedPacketBuilder,
+};
 
-use super::{Client, PublishError};
+use super::{Client, PublishError, QuorumError, UpdateError};
 
 impl Client {
     /// Returns a blocking (synchronous ) version of [Client].
@@ -33,6 +36,11 @@ impl ClientBlocking {
         self.0.dht()
     }
 
+    /// Gracefully shuts down this client and all its clones, see [Client::shutdown].
+    pub fn shutdown(&self) {
+        self.0.shutdown()
+    }
+
     // === Publish ===
 
     /// Publishes a [SignedPacket] to the [mainline] Dht and or [Relays](https://pkarr.org/relays).
@@ -122,6 +130,48 @@ impl ClientBlocking {
         futures_lite::future::block_on(self.0.publish(signed_packet, cas))
     }
 
+    /// Same as [Self::publish] but with per-request [RequestOptions],
+    /// see [Client::publish_with_options].
+    pub fn publish_with_options(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        options: RequestOptions,
+    ) -> Result<(), PublishError> {
+        futures_lite::future::block_on(self.0.publish_with_options(signed_packet, cas, options))
+    }
+
+    /// Same as [Self::publish] but returns a detailed [PublishReport],
+    /// see [Client::publish_with_report].
+    pub fn publish_with_report(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+    ) -> PublishReport {
+        futures_lite::future::block_on(self.0.publish_with_report(signed_packet, cas))
+    }
+
+    /// Same as [Self::publish], but returns [super::QueryError::Cancelled] as soon as
+    /// the `token` is cancelled (for example from another thread),
+    /// see [Client::publish_with_cancellation].
+    pub fn publish_with_cancellation(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        token: &CancellationToken,
+    ) -> Result<(), PublishError> {
+        futures_lite::future::block_on(self.0.publish_with_cancellation(signed_packet, cas, token))
+    }
+
+    /// Resolves the most recent [SignedPacket], and publishes the one returned by `update`,
+    /// retrying on concurrency errors, see [Client::update].
+    pub fn update<F>(&self, keypair: &Keypair, update: F) -> Result<SignedPacket, UpdateError>
+    where
+        F: FnMut(Option<&SignedPacket>) -> SignedPacketBuilder,
+    {
+        futures_lite::future::block_on(self.0.update(keypair, update))
+    }
+
     // === Resolve ===
 
     /// Returns a [SignedPacket] from the cache even if it is expired.
@@ -134,6 +184,16 @@ impl ClientBlocking {
         futures_lite::future::block_on(self.0.resolve(public_key))
     }
 
+    /// Same as [Self::resolve] but with per-request [RequestOptions],
+    /// see [Client::resolve_with_options].
+    pub fn resolve_with_options(
+        &self,
+        public_key: &PublicKey,
+        options: RequestOptions,
+    ) -> Option<SignedPacket> {
+        futures_lite::future::block_on(self.0.resolve_with_options(public_key, options))
+    }
+
     /// Returns the most recent [SignedPacket] found after querying all
     /// [mainline] Dht nodes and or [Relays](https:://pkarr.org/relays).
     ///
@@ -144,4 +204,64 @@ impl ClientBlocking {
     pub fn resolve_most_recent(&self, public_key: &PublicKey) -> Option<SignedPacket> {
         futures_lite::future::block_on(self.0.resolve_most_recent(public_key))
     }
+
+    /// Same as [Self::resolve], but returns `None` as soon as the `token` is cancelled,
+    /// see [Client::resolve_with_cancellation].
+    pub fn resolve_with_cancellation(
+        &self,
+        public_key: &PublicKey,
+        token: &CancellationToken,
+    ) -> Option<SignedPacket> {
+        futures_lite::future::block_on(self.0.resolve_with_cancellation(public_key, token))
+    }
+
+    /// Same as [Self::resolve_most_recent], but returns `None` as soon as the `token` is cancelled,
+    /// see [Client::resolve_most_recent_with_cancellation].
+    pub fn resolve_most_recent_with_cancellation(
+        &self,
+        public_key: &PublicKey,
+        token: &CancellationToken,
+    ) -> Option<SignedPacket> {
+        futures_lite::future::block_on(
+            self.0
+                .resolve_most_recent_with_cancellation(public_key, token),
+        )
+    }
+
+    /// Returns the most recent [SignedPacket] along with how many sources confirmed its timestamp,
+    /// or an error if the [ResolveOptions] were not satisfied, see [Client::resolve_with].
+    pub fn resolve_with(
+        &self,
+        public_key: &PublicKey,
+        options: ResolveOptions,
+    ) -> Result<Quorum, QuorumError> {
+        futures_lite::future::block_on(self.0.resolve_with(public_key, options))
+    }
+
+    /// Resolve many [PublicKey]s, resolving at most `concurrency` keys at the same time,
+    /// and returns an [Iterator] of each key and its [SignedPacket] if found,
+    /// see [Client::resolve_many].
+    pub fn resolve_many(
+        &self,
+        public_keys: impl IntoIterator<Item = PublicKey>,
+        concurrency: usize,
+    ) -> impl Iterator<Item = (PublicKey, Option<SignedPacket>)> {
+        futures_lite::stream::block_on(self.0.resolve_many(public_keys, concurrency))
+    }
+
+    /// Publish many [SignedPacket]s, publishing at most `concurrency` packets at the same time,
+    /// and returns the result for each [PublicKey], see [Client::publish_many].
+    pub fn publish_many(
+        &self,
+        signed_packets: impl IntoIterator<Item = SignedPacket>,
+        concurrency: usize,
+    ) -> Vec<(PublicKey, Result<(), PublishError>)> {
+        futures_lite::future::block_on(self.0.publish_many(signed_packets, concurrency))
+    }
+
+    /// Query every configured source for a [PublicKey] and return a [DiagnosticReport]
+    /// of what each one returned, see [Client::diagnose].
+    pub fn diagnose(&self, public_key: &PublicKey) -> DiagnosticReport {
+        futures_lite::future::block_on(self.0.diagnose(public_key))
+    }
 }
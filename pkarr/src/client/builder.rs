@@ -1,13 +1,30 @@
 #[cfg(dht)]
 use std::net::ToSocketAddrs;
-use std::{sync::Arc, time::Duration};
+#[cfg(not(wasm_browser))]
+use std::path::Path;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 #[cfg(feature = "relays")]
 use url::Url;
 
-use crate::{Cache, DEFAULT_CACHE_SIZE, DEFAULT_MAXIMUM_TTL, DEFAULT_MINIMUM_TTL};
+use crate::{
+    AsyncCache, Cache, DEFAULT_CACHE_SIZE, DEFAULT_MAXIMUM_TTL, DEFAULT_MAX_CONCURRENT_PREFETCHES,
+    DEFAULT_MINIMUM_TTL,
+};
 
-use crate::{errors::BuildError, Client};
+use crate::{
+    errors::BuildError, Client, ClientObserver, Clock, PublicKey, RetryPolicy, SignedPacket,
+    SystemClock,
+};
+
+#[cfg(not(wasm_browser))]
+use super::static_zones::load_dir;
+use super::static_zones::StaticZonesError;
+
+#[cfg(feature = "relays")]
+use super::health::HealthConfig;
+#[cfg(feature = "relays")]
+use super::transport::RelayTransport;
 
 #[cfg(feature = "endpoints")]
 pub const DEFAULT_MAX_RECURSION_DEPTH: u8 = 7;
@@ -32,8 +49,36 @@ pub(crate) struct Config {
     ///
     /// Defaults to [DEFAULT_MAXIMUM_TTL]
     pub maximum_ttl: u32,
+    /// How long to remember that no [crate::SignedPacket] was found for a key, in seconds.
+    ///
+    /// Defaults to `0`, meaning: misses are not cached.
+    pub negative_ttl: u32,
+    /// Fraction of a [crate::SignedPacket]'s TTL before expiry, within which resolving it
+    /// refreshes it in the background.
+    ///
+    /// Defaults to `0.0`, meaning: no prefetching.
+    pub prefetch_threshold: f32,
+    /// Maximum number of concurrent background prefetches.
+    ///
+    /// Defaults to [DEFAULT_MAX_CONCURRENT_PREFETCHES]
+    pub max_concurrent_prefetches: usize,
+    /// Number of times a cached packet has to be resolved within [Self::prefetch_threshold]
+    /// before it is prefetched.
+    ///
+    /// Defaults to `1`, meaning: a single access is enough.
+    pub prefetch_min_hits: u32,
     /// Custom [Cache] implementation, defaults to [crate::InMemoryCache]
     pub cache: Option<Arc<dyn Cache>>,
+    /// Custom [AsyncCache] implementation, takes the place of [Self::cache] if set.
+    pub async_cache: Option<Arc<dyn AsyncCache>>,
+    /// Receives [crate::ClientEvent]s, defaults to `None`.
+    pub observer: Option<Arc<dyn ClientObserver>>,
+    /// Retry policy for transient publish failures, defaults to `None`.
+    pub retry_policy: Option<RetryPolicy>,
+    /// Locally configured packets, that take precedence over the cache and the network.
+    pub static_zones: HashMap<PublicKey, SignedPacket>,
+    /// Source of the current time for TTL and expiry, defaults to [SystemClock].
+    pub clock: Arc<dyn Clock>,
 
     #[cfg(dht)]
     pub dht: Option<mainline::DhtBuilder>,
@@ -42,6 +87,29 @@ pub(crate) struct Config {
     #[cfg(feature = "relays")]
     pub relays: Option<Vec<Url>>,
 
+    /// Circuit breaker and hedging configuration for [Self::relays].
+    #[cfg(feature = "relays")]
+    pub relay_health: HealthConfig,
+
+    /// Proxy for requests to [Self::relays], see [ClientBuilder::relay_proxy].
+    #[cfg(all(feature = "relays", not(wasm_browser)))]
+    pub relay_proxy: Option<reqwest::Proxy>,
+    /// Custom [reqwest::Client] for requests to [Self::relays], see [ClientBuilder::relay_http_client].
+    #[cfg(feature = "relays")]
+    pub relay_http_client: Option<reqwest::Client>,
+    /// Custom [RelayTransport] for requests to [Self::relays], see [ClientBuilder::relay_transport].
+    #[cfg(feature = "relays")]
+    pub relay_transport: Option<Arc<dyn RelayTransport>>,
+
+    /// Keys of relays lists to discover relays from, see [ClientBuilder::relays_from_key].
+    #[cfg(all(dht, feature = "relays"))]
+    pub relays_from_keys: Vec<PublicKey>,
+    /// Interval between refreshing [Self::relays_from_keys].
+    ///
+    /// Defaults to [crate::DEFAULT_RELAYS_REFRESH_INTERVAL]
+    #[cfg(all(dht, feature = "relays"))]
+    pub relays_refresh_interval: Duration,
+
     /// Timeout for both Dht and Relays requests.
     ///
     /// The longer this timeout the longer resolve queries will take before consider failed.
@@ -59,7 +127,16 @@ impl Default for Config {
             cache_size: DEFAULT_CACHE_SIZE,
             minimum_ttl: DEFAULT_MINIMUM_TTL,
             maximum_ttl: DEFAULT_MAXIMUM_TTL,
+            negative_ttl: 0,
+            prefetch_threshold: 0.0,
+            max_concurrent_prefetches: DEFAULT_MAX_CONCURRENT_PREFETCHES,
+            prefetch_min_hits: 1,
             cache: None,
+            async_cache: None,
+            observer: None,
+            retry_policy: None,
+            static_zones: HashMap::new(),
+            clock: Arc::new(SystemClock),
 
             #[cfg(dht)]
             dht: Some(mainline::Dht::builder()),
@@ -73,6 +150,18 @@ impl Default for Config {
                     })
                     .collect(),
             ),
+            #[cfg(feature = "relays")]
+            relay_health: HealthConfig::default(),
+            #[cfg(all(feature = "relays", not(wasm_browser)))]
+            relay_proxy: None,
+            #[cfg(feature = "relays")]
+            relay_http_client: None,
+            #[cfg(feature = "relays")]
+            relay_transport: None,
+            #[cfg(all(dht, feature = "relays"))]
+            relays_from_keys: vec![],
+            #[cfg(all(dht, feature = "relays"))]
+            relays_refresh_interval: crate::DEFAULT_RELAYS_REFRESH_INTERVAL,
 
             request_timeout: DEFAULT_REQUEST_TIMEOUT,
 
@@ -89,7 +178,19 @@ impl std::fmt::Debug for Config {
         debug_struct.field("cache_size", &self.cache_size);
         debug_struct.field("minimum_ttl", &self.minimum_ttl);
         debug_struct.field("maximum_ttl", &self.maximum_ttl);
+        debug_struct.field("negative_ttl", &self.negative_ttl);
+        debug_struct.field("prefetch_threshold", &self.prefetch_threshold);
+        debug_struct.field("max_concurrent_prefetches", &self.max_concurrent_prefetches);
+        debug_struct.field("prefetch_min_hits", &self.prefetch_min_hits);
         debug_struct.field("cache", &self.cache);
+        debug_struct.field("async_cache", &self.async_cache);
+        debug_struct.field("observer", &self.observer);
+        debug_struct.field("retry_policy", &self.retry_policy);
+        debug_struct.field(
+            "static_zones",
+            &self.static_zones.keys().collect::<Vec<_>>(),
+        );
+        debug_struct.field("clock", &self.clock);
 
         #[cfg(dht)]
         debug_struct.field("dht", &self.dht);
@@ -103,6 +204,19 @@ impl std::fmt::Debug for Config {
                 .map(|urls| urls.iter().map(|url| url.as_str()).collect::<Vec<_>>()),
         );
 
+        #[cfg(feature = "relays")]
+        debug_struct.field("relay_health", &self.relay_health);
+        #[cfg(all(feature = "relays", not(wasm_browser)))]
+        debug_struct.field("relay_proxy", &self.relay_proxy);
+        #[cfg(feature = "relays")]
+        debug_struct.field("relay_http_client", &self.relay_http_client);
+        #[cfg(feature = "relays")]
+        debug_struct.field("relay_transport", &self.relay_transport);
+        #[cfg(all(dht, feature = "relays"))]
+        debug_struct.field("relays_from_keys", &self.relays_from_keys);
+        #[cfg(all(dht, feature = "relays"))]
+        debug_struct.field("relays_refresh_interval", &self.relays_refresh_interval);
+
         debug_struct.field("request_timeout", &self.request_timeout);
 
         debug_struct.finish()
@@ -220,6 +334,47 @@ impl ClientBuilder {
         Ok(self)
     }
 
+    #[cfg(all(feature = "relays", not(wasm_browser)))]
+    /// Send all requests to [Self::relays] through a proxy, for example
+    /// `http://proxy.example.com:8080`, or `socks5h://127.0.0.1:9050` for Tor.
+    ///
+    /// SOCKS proxies require enabling the `socks` feature of [reqwest] in your own `Cargo.toml`.
+    ///
+    /// Ignored if [Self::relay_http_client] or [Self::relay_transport] is set,
+    /// configure the proxy on that client instead.
+    pub fn relay_proxy<T: reqwest::IntoUrl>(
+        &mut self,
+        url: T,
+    ) -> Result<&mut Self, reqwest::Error> {
+        self.0.relay_proxy = Some(reqwest::Proxy::all(url)?);
+
+        Ok(self)
+    }
+
+    #[cfg(feature = "relays")]
+    /// Use a fully configured [reqwest::Client] for requests to [Self::relays],
+    /// for example with custom root certificates, user agent, or proxy.
+    ///
+    /// The [Self::request_timeout] is still applied to each request.
+    ///
+    /// Ignored if [Self::relay_transport] is set.
+    pub fn relay_http_client(&mut self, http_client: reqwest::Client) -> &mut Self {
+        self.0.relay_http_client = Some(http_client);
+
+        self
+    }
+
+    #[cfg(feature = "relays")]
+    /// Use a custom [RelayTransport] for requests to [Self::relays], for example
+    /// another HTTP client, a custom mTLS stack, or an in-process mock for tests.
+    ///
+    /// Defaults to [crate::ReqwestTransport].
+    pub fn relay_transport(&mut self, transport: Arc<dyn RelayTransport>) -> &mut Self {
+        self.0.relay_transport = Some(transport);
+
+        self
+    }
+
     /// Set the size of the capacity of the [Self::cache] implementation.
     ///
     /// If set to `0` cache will be disabled.
@@ -249,9 +404,233 @@ impl ClientBuilder {
         self
     }
 
+    /// Set the negative TTL value in seconds.
+    ///
+    /// When set, a [Client::resolve] that finds no [crate::SignedPacket] is recorded
+    /// in the [Cache] (see [Cache::put_miss]), and resolving the same key again returns `None`
+    /// without querying the network, until this TTL passes or a packet is published or found.
+    ///
+    /// Defaults to `0`, meaning: misses are not cached.
+    pub fn negative_ttl(&mut self, ttl: u32) -> &mut Self {
+        self.0.negative_ttl = ttl;
+
+        self
+    }
+
+    /// Set the prefetch threshold, as a fraction of a [crate::SignedPacket]'s TTL.
+    ///
+    /// When [Client::resolve] returns a cached packet that expires within this fraction
+    /// of its TTL, the packet is refreshed in the background, so the next caller after
+    /// the original expiry doesn't get a stale packet.
+    ///
+    /// For example, `0.1` refreshes a packet with a TTL of 300 seconds, if it is resolved
+    /// within the last 30 seconds before it expires.
+    ///
+    /// By default a single access within the threshold triggers a prefetch,
+    /// see [Self::prefetch_min_hits] to only prefetch frequently resolved packets.
+    ///
+    /// See [Client::prefetch_stats] for monitoring.
+    ///
+    /// Defaults to `0.0`, meaning: no prefetching.
+    pub fn prefetch_threshold(&mut self, fraction: f32) -> &mut Self {
+        self.0.prefetch_threshold = fraction;
+
+        self
+    }
+
+    /// Set the maximum number of concurrent background prefetches,
+    /// see [Self::prefetch_threshold].
+    ///
+    /// Defaults to [DEFAULT_MAX_CONCURRENT_PREFETCHES].
+    pub fn max_concurrent_prefetches(&mut self, max: usize) -> &mut Self {
+        self.0.max_concurrent_prefetches = max;
+
+        self
+    }
+
+    /// Set how many times a cached packet has to be resolved within the
+    /// [prefetch threshold](Self::prefetch_threshold) before it is prefetched.
+    ///
+    /// Hits are counted per key, and reset when the packet is refreshed or prefetched,
+    /// so packets that are rarely resolved just expire instead of costing a network query.
+    ///
+    /// Defaults to `1`, meaning: a single access is enough. `0` is treated as `1`.
+    pub fn prefetch_min_hits(&mut self, hits: u32) -> &mut Self {
+        self.0.prefetch_min_hits = hits;
+
+        self
+    }
+
+    #[cfg(all(dht, feature = "relays"))]
+    /// Discover extra [Relays](https://pkarr.org/relays) from a relays list published
+    /// as a [crate::SignedPacket] by this `public_key`.
+    ///
+    /// The relays list is resolved from the [mainline] Dht in the background, retried with
+    /// a backoff until it is first resolved, then refreshed every
+    /// [Self::relays_refresh_interval]. Its relays are listed under the
+    /// [crate::RELAYS_RECORD_NAME] name, either as `TXT` records containing the relay's Url,
+    /// or as `HTTPS` records pointing to the relay's host (and port).
+    ///
+    /// Discovered relays are added after the static [Self::relays], and until a relays list
+    /// is resolved, only the static relays are used (if any). See [Client::relays].
+    ///
+    /// Can be called multiple times to discover relays from multiple lists,
+    /// and is ignored if the Dht is disabled.
+    pub fn relays_from_key(&mut self, public_key: PublicKey) -> &mut Self {
+        if !self.0.relays_from_keys.contains(&public_key) {
+            self.0.relays_from_keys.push(public_key);
+        }
+
+        self
+    }
+
+    #[cfg(all(dht, feature = "relays"))]
+    /// Set the interval between refreshing relays lists, see [Self::relays_from_key].
+    ///
+    /// Defaults to [crate::DEFAULT_RELAYS_REFRESH_INTERVAL].
+    pub fn relays_refresh_interval(&mut self, interval: Duration) -> &mut Self {
+        self.0.relays_refresh_interval = interval;
+
+        self
+    }
+
+    #[cfg(feature = "relays")]
+    /// Configure the circuit breaker for [Relays](https://pkarr.org/relays).
+    ///
+    /// A relay that fails `failures` consecutive requests (errors, timeouts, invalid packets,
+    /// or `5xx` responses to publish requests) is skipped when resolving, until `cooldown` passes,
+    /// or until all relays are skipped. See [Client::relays_health] for monitoring.
+    ///
+    /// Setting `failures` to `0` disables the circuit breaker.
+    ///
+    /// Defaults to [crate::DEFAULT_RELAY_FAILURE_THRESHOLD] and [crate::DEFAULT_RELAY_COOLDOWN].
+    pub fn relay_circuit_breaker(&mut self, failures: u32, cooldown: Duration) -> &mut Self {
+        self.0.relay_health.failure_threshold = failures;
+        self.0.relay_health.cooldown = cooldown;
+
+        self
+    }
+
+    #[cfg(feature = "relays")]
+    /// Resolve from the `fastest` relays first (by their average latency), and only query
+    /// the rest of the relays if none of the fastest ones responded within `delay`.
+    ///
+    /// Relays that didn't respond successfully yet are considered the fastest.
+    /// A `fastest` of `0` is treated as `1`, since at least one relay is always queried first.
+    ///
+    /// Defaults to querying all relays at once.
+    pub fn relay_hedging(&mut self, fastest: usize, delay: Duration) -> &mut Self {
+        self.0.relay_health.hedging = Some((fastest, delay));
+
+        self
+    }
+
     /// Set a custom implementation of [Cache].
+    ///
+    /// Replaces any [Self::async_cache].
     pub fn cache(&mut self, cache: Arc<dyn Cache>) -> &mut Self {
         self.0.cache = Some(cache);
+        self.0.async_cache = None;
+
+        self
+    }
+
+    /// Set a custom implementation of [AsyncCache], for caches that can't answer
+    /// synchronously, like remote caches.
+    ///
+    /// [Client::cache] returns `None` for such caches, use [Client::async_cache] instead.
+    ///
+    /// Replaces any [Self::cache].
+    pub fn async_cache(&mut self, cache: Arc<dyn AsyncCache>) -> &mut Self {
+        self.0.async_cache = Some(cache);
+        self.0.cache = None;
+
+        self
+    }
+
+    /// Use a custom [Clock] for TTL, expiry and the relays circuit breaker cooldown,
+    /// for example a [crate::TestClock] to test expiry without waiting.
+    ///
+    /// Also used by the default [InMemoryCache](crate::InMemoryCache), but a custom [Self::cache]
+    /// needs to be given the same clock, for example with [crate::InMemoryCache::with_clock].
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.0.clock = clock;
+
+        self
+    }
+
+    /// Set a [ClientObserver] to receive structured [crate::ClientEvent]s,
+    /// like cache hits and misses, relay responses, and publish results.
+    ///
+    /// Useful for exporting metrics, see [crate::extra::metrics::MetricsObserver]
+    /// (requires the `metrics` feature).
+    pub fn observer(&mut self, observer: Arc<dyn ClientObserver>) -> &mut Self {
+        self.0.observer = Some(observer);
+
+        self
+    }
+
+    /// Resolve this [PublicKey] to a locally configured [SignedPacket], without touching
+    /// the network, useful for development and air-gapped deployments.
+    ///
+    /// Static zones take precedence over the cache and the network, in [Client::resolve],
+    /// [Client::resolve_most_recent] and the rest of the resolve methods, including
+    /// endpoints resolution, and [Client::watch]. The packet is never considered expired.
+    ///
+    /// Returns [StaticZonesError::KeyMismatch] if the packet isn't signed by the `public_key`.
+    pub fn static_zone(
+        &mut self,
+        public_key: PublicKey,
+        signed_packet: SignedPacket,
+    ) -> Result<&mut Self, StaticZonesError> {
+        if signed_packet.public_key() != public_key {
+            return Err(StaticZonesError::KeyMismatch);
+        }
+
+        self.0.static_zones.insert(public_key, signed_packet);
+
+        Ok(self)
+    }
+
+    #[cfg(not(wasm_browser))]
+    /// Load [Self::static_zone]s from a directory of zone files and packet files.
+    ///
+    /// A zone file has the `.zone` extension, and is signed with the keypair in the file
+    /// with the same name and the `.key` extension, as written by
+    /// [Keypair::write_secret_key_file](crate::Keypair::write_secret_key_file).
+    /// It lists one record per line, as `<name> <ttl> <type> <value>`, where the name `@`
+    /// is the apex, and the type is one of `A`, `AAAA`, `CNAME` or `TXT`.
+    /// Empty lines and lines starting with `;` or `#` are ignored. For example:
+    ///
+    /// ```text
+    /// @    300 A    192.0.2.1
+    /// www  300 AAAA 2001:db8::1
+    /// api  300 TXT  "hello world"
+    /// ```
+    ///
+    /// Any other file named after a [PublicKey], with an optional extension
+    /// (for example `<public-key>.pkarr`), contains a packet in the
+    /// [relay payload](SignedPacket::to_relay_payload) format, which is what
+    /// a [Relay](https://pkarr.org/relays) returns for `GET /<public-key>`.
+    ///
+    /// Other files are ignored.
+    pub fn static_zones_dir(&mut self, dir: &Path) -> Result<&mut Self, StaticZonesError> {
+        for signed_packet in load_dir(dir)? {
+            self.static_zone(signed_packet.public_key(), signed_packet)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Retry transient publish failures according to a [RetryPolicy],
+    /// see [RetryPolicy] for which failures are retried.
+    ///
+    /// Each retry waits for an exponential backoff with jitter,
+    /// or longer if a relay asks for it with the `Retry-After` header.
+    ///
+    /// Defaults to `None`, meaning: transient failures are returned to the caller.
+    pub fn retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.0.retry_policy = Some(policy);
 
         self
     }
@@ -2,11 +2,15 @@
 
 use dyn_clone::DynClone;
 use lru::LruCache;
+use ntimestamp::Timestamp;
 use std::fmt::Debug;
+use std::future::Future;
 use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
-use crate::SignedPacket;
+use crate::{Clock, SignedPacket, SystemClock};
 
 /// The sha1 hash of the [crate::PublicKey] used as the key in [Cache].
 pub type CacheKey = [u8; 20];
@@ -29,6 +33,20 @@ impl From<crate::PublicKey> for CacheKey {
     }
 }
 
+/// Iterator over the `(CacheKey, SignedPacket)` entries of a [Cache], see [Cache::iter].
+pub type CacheIter<'a> = Box<dyn Iterator<Item = (CacheKey, SignedPacket)> + 'a>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Counters of a [Cache], see [Cache::stats].
+pub struct CacheStats {
+    /// Number of [Cache::get] calls that found a [SignedPacket].
+    pub hits: u64,
+    /// Number of [Cache::get] calls that didn't find a [SignedPacket].
+    pub misses: u64,
+    /// Number of [SignedPacket]s dropped to make room for new ones, once the capacity was reached.
+    pub evictions: u64,
+}
+
 /// A trait for a [SignedPacket]s cache for Pkarr [Client][crate::Client].
 pub trait Cache: Debug + Send + Sync + DynClone {
     /// Returns the maximum capacity of [SignedPacket]s allowed in this cache.
@@ -58,23 +76,356 @@ pub trait Cache: Debug + Send + Sync + DynClone {
     fn get_read_only(&self, key: &CacheKey) -> Option<SignedPacket> {
         self.get(key)
     }
+    /// Records that no [SignedPacket] was found for this key.
+    ///
+    /// Used for negative caching, see [crate::ClientBuilder::negative_ttl].
+    /// Implementations should forget the miss once a [SignedPacket] is [put][Cache::put] for the same key.
+    ///
+    /// The default implementation doesn't record anything.
+    fn put_miss(&self, _key: &CacheKey) {}
+    /// Returns the time a miss was last recorded for this key by [Cache::put_miss], if any.
+    ///
+    /// The default implementation always returns `None`.
+    fn get_miss(&self, _key: &CacheKey) -> Option<Timestamp> {
+        None
+    }
+    /// Returns true if this cache blocks the current thread on every operation,
+    /// for example by doing disk IO.
+    ///
+    /// Operations on blocking caches are moved to a blocking thread pool,
+    /// when used as an [AsyncCache] within a Tokio runtime.
+    ///
+    /// The default implementation returns `false`.
+    fn is_blocking(&self) -> bool {
+        false
+    }
+    /// Removes the [SignedPacket] and the recorded miss for this key,
+    /// and returns the [SignedPacket] if it was in the cache.
+    ///
+    /// The default implementation does nothing, and always returns `None`.
+    fn remove(&self, _key: &CacheKey) -> Option<SignedPacket> {
+        None
+    }
+    /// Removes all [SignedPacket]s and recorded misses from this cache.
+    ///
+    /// The default implementation [removes][Cache::remove] every key returned by [Cache::iter],
+    /// so it does nothing unless both are implemented, and it doesn't remove misses recorded
+    /// for keys without a [SignedPacket].
+    fn clear(&self) {
+        let keys: Vec<CacheKey> = self.iter().map(|(key, _)| key).collect();
+
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+    /// Returns an iterator over the `(CacheKey, SignedPacket)` entries in this cache,
+    /// without changing the LRU list.
+    ///
+    /// The default implementation returns an empty iterator, even if the cache isn't empty.
+    fn iter(&self) -> CacheIter<'_> {
+        Box::new(std::iter::empty())
+    }
+    /// Returns the hits, misses and evictions counted by this cache.
+    ///
+    /// The default implementation returns zeros.
+    fn stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
 }
 
 dyn_clone::clone_trait_object!(Cache);
 
+#[derive(Debug, Default)]
+/// Atomic counters behind [CacheStats].
+pub(crate) struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl CacheCounters {
+    /// Count a [Cache::get] as a hit or a miss.
+    pub(crate) fn record_get(&self, found: bool) {
+        if found {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_eviction(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<C: Cache + ?Sized> Cache for Arc<C> {
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn is_empty(&self) -> bool {
+        (**self).is_empty()
+    }
+
+    fn put(&self, key: &CacheKey, signed_packet: &SignedPacket) {
+        (**self).put(key, signed_packet)
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<SignedPacket> {
+        (**self).get(key)
+    }
+
+    fn get_read_only(&self, key: &CacheKey) -> Option<SignedPacket> {
+        (**self).get_read_only(key)
+    }
+
+    fn put_miss(&self, key: &CacheKey) {
+        (**self).put_miss(key)
+    }
+
+    fn get_miss(&self, key: &CacheKey) -> Option<Timestamp> {
+        (**self).get_miss(key)
+    }
+
+    fn is_blocking(&self) -> bool {
+        (**self).is_blocking()
+    }
+
+    fn remove(&self, key: &CacheKey) -> Option<SignedPacket> {
+        (**self).remove(key)
+    }
+
+    fn clear(&self) {
+        (**self).clear()
+    }
+
+    fn iter(&self) -> CacheIter<'_> {
+        (**self).iter()
+    }
+
+    fn stats(&self) -> CacheStats {
+        (**self).stats()
+    }
+}
+
+#[cfg(not(wasm_browser))]
+/// Future returned by [AsyncCache] methods.
+pub type CacheFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+#[cfg(wasm_browser)]
+/// Future returned by [AsyncCache] methods.
+pub type CacheFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// An asynchronous version of [Cache], used internally by the Pkarr [Client][crate::Client].
+///
+/// Every [Cache] is an [AsyncCache], so you only need to implement this trait
+/// for caches that can't answer synchronously, like remote caches.
+///
+/// See [Cache] for the semantics of each method.
+pub trait AsyncCache: Debug + Send + Sync {
+    /// Returns the maximum capacity of [SignedPacket]s allowed in this cache.
+    fn capacity(&self) -> usize {
+        0
+    }
+    /// Returns the number of [SignedPacket]s in this cache.
+    fn len(&self) -> CacheFuture<'_, usize>;
+    /// Returns true if this cache is empty.
+    fn is_empty(&self) -> CacheFuture<'_, bool> {
+        Box::pin(async move { self.len().await == 0 })
+    }
+    /// Puts [SignedPacket] into cache.
+    fn put<'a>(&'a self, key: &'a CacheKey, signed_packet: &'a SignedPacket)
+        -> CacheFuture<'a, ()>;
+    /// Reads [SignedPacket] from cache, while moving it to the head of the LRU list.
+    fn get<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, Option<SignedPacket>>;
+    /// Reads [SignedPacket] from cache, without changing the LRU list.
+    ///
+    /// Otherwise it will just use [AsyncCache::get].
+    fn get_read_only<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, Option<SignedPacket>> {
+        self.get(key)
+    }
+    /// Records that no [SignedPacket] was found for this key.
+    ///
+    /// The default implementation doesn't record anything.
+    fn put_miss<'a>(&'a self, _key: &'a CacheKey) -> CacheFuture<'a, ()> {
+        Box::pin(std::future::ready(()))
+    }
+    /// Returns the time a miss was last recorded for this key by [AsyncCache::put_miss], if any.
+    ///
+    /// The default implementation always returns `None`.
+    fn get_miss<'a>(&'a self, _key: &'a CacheKey) -> CacheFuture<'a, Option<Timestamp>> {
+        Box::pin(std::future::ready(None))
+    }
+    /// Removes the [SignedPacket] and the recorded miss for this key,
+    /// and returns the [SignedPacket] if it was in the cache.
+    ///
+    /// The default implementation does nothing, and always returns `None`.
+    fn remove<'a>(&'a self, _key: &'a CacheKey) -> CacheFuture<'a, Option<SignedPacket>> {
+        Box::pin(std::future::ready(None))
+    }
+    /// Removes all [SignedPacket]s and recorded misses from this cache.
+    ///
+    /// The default implementation does nothing.
+    fn clear(&self) -> CacheFuture<'_, ()> {
+        Box::pin(std::future::ready(()))
+    }
+    /// Returns the hits, misses and evictions counted by this cache.
+    ///
+    /// The default implementation returns zeros.
+    fn stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
+}
+
+impl<C: Cache + ?Sized + 'static> AsyncCache for C {
+    fn capacity(&self) -> usize {
+        Cache::capacity(self)
+    }
+
+    fn len(&self) -> CacheFuture<'_, usize> {
+        run(self, |cache| Cache::len(cache))
+    }
+
+    fn is_empty(&self) -> CacheFuture<'_, bool> {
+        run(self, |cache| Cache::is_empty(cache))
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        signed_packet: &'a SignedPacket,
+    ) -> CacheFuture<'a, ()> {
+        let (key, signed_packet) = (*key, signed_packet.clone());
+
+        run(self, move |cache| Cache::put(cache, &key, &signed_packet))
+    }
+
+    fn get<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, Option<SignedPacket>> {
+        let key = *key;
+
+        run(self, move |cache| Cache::get(cache, &key))
+    }
+
+    fn get_read_only<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, Option<SignedPacket>> {
+        let key = *key;
+
+        run(self, move |cache| Cache::get_read_only(cache, &key))
+    }
+
+    fn put_miss<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, ()> {
+        let key = *key;
+
+        run(self, move |cache| Cache::put_miss(cache, &key))
+    }
+
+    fn get_miss<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, Option<Timestamp>> {
+        let key = *key;
+
+        run(self, move |cache| Cache::get_miss(cache, &key))
+    }
+
+    fn remove<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, Option<SignedPacket>> {
+        let key = *key;
+
+        run(self, move |cache| Cache::remove(cache, &key))
+    }
+
+    fn clear(&self) -> CacheFuture<'_, ()> {
+        run(self, |cache| Cache::clear(cache))
+    }
+
+    fn stats(&self) -> CacheStats {
+        Cache::stats(self)
+    }
+}
+
+/// Run a [Cache] operation on the blocking thread pool if the cache [is blocking](Cache::is_blocking),
+/// otherwise run it on the current thread.
+///
+/// If the blocking task is cancelled before it runs (for example, because the runtime is
+/// shutting down), the operation runs on the current thread instead.
+fn run<C, T, F>(cache: &C, operation: F) -> CacheFuture<'_, T>
+where
+    C: Cache + ?Sized + 'static,
+    T: Send + 'static,
+    F: FnOnce(&C) -> T + Send + 'static,
+{
+    #[cfg(not(wasm_browser))]
+    if cache.is_blocking() {
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let operation = Arc::new(std::sync::Mutex::new(Some(operation)));
+
+            let task = handle.spawn_blocking({
+                let cache = dyn_clone::clone_box(cache);
+                let operation = operation.clone();
+
+                move || take(&operation).map(|operation| operation(&cache))
+            });
+
+            return Box::pin(async move {
+                match task.await {
+                    Ok(Some(output)) => output,
+                    Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+                    _ => {
+                        let operation = take(&operation)
+                            .expect("a cancelled blocking task never took the cache operation");
+
+                        operation(cache)
+                    }
+                }
+            });
+        }
+    }
+
+    Box::pin(std::future::ready(operation(cache)))
+}
+
+#[cfg(not(wasm_browser))]
+fn take<F>(operation: &std::sync::Mutex<Option<F>>) -> Option<F> {
+    operation
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .take()
+}
+
 /// A thread safe wrapper around [lru::LruCache]
 #[derive(Debug, Clone)]
 pub struct InMemoryCache {
     inner: Arc<RwLock<LruCache<CacheKey, SignedPacket>>>,
+    misses: Arc<RwLock<LruCache<CacheKey, Timestamp>>>,
+    clock: Arc<dyn Clock>,
+    counters: Arc<CacheCounters>,
 }
 
 impl InMemoryCache {
-    /// Creates a new `LRU` cache that holds at most `cap` items.
+    /// Creates a new `LRU` cache that holds at most `cap` items,
+    /// and at most `cap` recorded misses.
     pub fn new(capacity: NonZeroUsize) -> Self {
         Self {
             inner: Arc::new(RwLock::new(LruCache::new(capacity))),
+            misses: Arc::new(RwLock::new(LruCache::new(capacity))),
+            clock: Arc::new(SystemClock),
+            counters: Arc::new(CacheCounters::default()),
         }
     }
+
+    /// Use a custom [Clock] for the time of recorded misses, see [Cache::put_miss].
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
 }
 
 impl Cache for InMemoryCache {
@@ -97,26 +448,35 @@ impl Cache for InMemoryCache {
         let mut lock = self.inner.write().expect("InMemoryCache RwLock");
 
         match lock.get_mut(key) {
-            Some(existing) => {
-                if existing.as_bytes() == signed_packet.as_bytes() {
-                    // just refresh the last_seen
-                    existing.set_last_seen(signed_packet.last_seen())
-                } else {
-                    lock.put(*key, signed_packet.clone());
-                }
+            Some(existing) if existing.as_bytes() == signed_packet.as_bytes() => {
+                // just refresh the last_seen
+                existing.set_last_seen(signed_packet.last_seen())
             }
-            None => {
-                lock.put(*key, signed_packet.clone());
+            _ => {
+                if let Some((evicted, _)) = lock.push(*key, signed_packet.clone()) {
+                    if evicted != *key {
+                        self.counters.record_eviction();
+                    }
+                }
             }
         }
+
+        drop(lock);
+
+        self.misses.write().expect("InMemoryCache RwLock").pop(key);
     }
 
     fn get(&self, key: &CacheKey) -> Option<SignedPacket> {
-        self.inner
+        let signed_packet = self
+            .inner
             .write()
             .expect("InMemoryCache RwLock")
             .get(key)
-            .cloned()
+            .cloned();
+
+        self.counters.record_get(signed_packet.is_some());
+
+        signed_packet
     }
 
     fn get_read_only(&self, key: &CacheKey) -> Option<SignedPacket> {
@@ -126,4 +486,47 @@ impl Cache for InMemoryCache {
             .peek(key)
             .cloned()
     }
+
+    fn put_miss(&self, key: &CacheKey) {
+        self.misses
+            .write()
+            .expect("InMemoryCache RwLock")
+            .put(*key, self.clock.now());
+    }
+
+    fn get_miss(&self, key: &CacheKey) -> Option<Timestamp> {
+        self.misses
+            .read()
+            .expect("InMemoryCache RwLock")
+            .peek(key)
+            .copied()
+    }
+
+    fn remove(&self, key: &CacheKey) -> Option<SignedPacket> {
+        self.misses.write().expect("InMemoryCache RwLock").pop(key);
+
+        self.inner.write().expect("InMemoryCache RwLock").pop(key)
+    }
+
+    fn clear(&self) {
+        self.inner.write().expect("InMemoryCache RwLock").clear();
+        self.misses.write().expect("InMemoryCache RwLock").clear();
+    }
+
+    /// Returns a snapshot of the entries, from the most to the least recently used.
+    fn iter(&self) -> CacheIter<'_> {
+        let entries: Vec<(CacheKey, SignedPacket)> = self
+            .inner
+            .read()
+            .expect("InMemoryCache RwLock")
+            .iter()
+            .map(|(key, signed_packet)| (*key, signed_packet.clone()))
+            .collect();
+
+        Box::new(entries.into_iter())
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.counters.stats()
+    }
 }
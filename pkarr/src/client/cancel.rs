@@ -0,0 +1,232 @@
+//! Cancelling requests with a [CancellationToken], and shutting down the [Client],
+//! see [Client::shutdown].
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures_lite::StreamExt;
+use ntimestamp::Timestamp;
+
+use crate::{PublicKey, SignedPacket};
+
+use super::{async_compat_if_necessary, Client, PublishError, QueryError, ResolvedStream};
+
+#[derive(Debug, Clone, Default)]
+/// A token for cancelling one or more in-flight requests from another task or thread,
+/// see [Client::resolve_with_cancellation] and [Client::publish_with_cancellation].
+///
+/// Clones share the same state, so cancelling any clone cancels all of them.
+pub struct CancellationToken(Arc<State>);
+
+#[derive(Debug, Default)]
+struct State {
+    cancelled: AtomicBool,
+    wakers: Mutex<Wakers>,
+}
+
+#[derive(Debug, Default)]
+struct Wakers {
+    next_id: u64,
+    waiting: HashMap<u64, Waker>,
+}
+
+impl CancellationToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all requests using this token (or any of its clones).
+    pub fn cancel(&self) {
+        if self.0.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let waiting = std::mem::take(
+            &mut self
+                .0
+                .wakers
+                .lock()
+                .expect("CancellationToken lock")
+                .waiting,
+        );
+
+        for waker in waiting.into_values() {
+            waker.wake();
+        }
+    }
+
+    /// Returns `true` if [Self::cancel] was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future that completes once this token is cancelled.
+    pub fn cancelled(&self) -> impl Future<Output = ()> + Send + '_ {
+        Cancelled {
+            state: &self.0,
+            id: None,
+        }
+    }
+}
+
+/// A future that completes once a [CancellationToken] is cancelled,
+/// unregistering its waker when dropped.
+struct Cancelled<'a> {
+    state: &'a State,
+    id: Option<u64>,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.state.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+
+        let mut wakers = self.state.wakers.lock().expect("CancellationToken lock");
+
+        // Check again while holding the lock, to not miss a concurrent `cancel`.
+        if self.state.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+
+        let id = match self.id {
+            Some(id) => id,
+            None => {
+                let id = wakers.next_id;
+                wakers.next_id += 1;
+                id
+            }
+        };
+
+        wakers.waiting.insert(id, cx.waker().clone());
+        drop(wakers);
+
+        self.id = Some(id);
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancelled<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            if let Ok(mut wakers) = self.state.wakers.lock() {
+                wakers.waiting.remove(&id);
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Gracefully shuts down this client and all its clones.
+    ///
+    /// - In-flight resolve and publish requests are cancelled, including requests to
+    ///   [Relays](https://pkarr.org/relays), and publish requests return [QueryError::Cancelled].
+    /// - Background tasks (refreshing expired packets, prefetching, and relays discovery)
+    ///   and any `Republisher` using this client, are stopped.
+    /// - The [mainline] Dht node is shut down once in-flight queries are dropped.
+    ///
+    /// Subsequent requests only use the cache, if any.
+    pub fn shutdown(&self) {
+        cross_debug!("Shutting down Pkarr Client");
+
+        self.0.shutdown.cancel();
+
+        #[cfg(dht)]
+        self.0.dht.write().expect("Client::dht lock").take();
+    }
+
+    /// Returns `true` if [Self::shutdown] was called.
+    pub fn is_shutdown(&self) -> bool {
+        self.0.shutdown.is_cancelled()
+    }
+
+    /// Same as [Self::publish], but returns [QueryError::Cancelled] as soon as
+    /// the `token` is cancelled.
+    pub async fn publish_with_cancellation(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        token: &CancellationToken,
+    ) -> Result<(), PublishError> {
+        if token.is_cancelled() {
+            return Err(QueryError::Cancelled.into());
+        }
+
+        async_compat_if_necessary(futures_lite::future::or(
+            self.publish(signed_packet, cas),
+            async {
+                token.cancelled().await;
+
+                Err(QueryError::Cancelled.into())
+            },
+        ))
+        .await
+    }
+
+    /// Same as [Self::resolve], but returns `None` as soon as the `token` is cancelled.
+    pub async fn resolve_with_cancellation(
+        &self,
+        public_key: &PublicKey,
+        token: &CancellationToken,
+    ) -> Option<SignedPacket> {
+        if token.is_cancelled() {
+            return None;
+        }
+
+        futures_lite::future::or(self.resolve(public_key), async {
+            token.cancelled().await;
+
+            None
+        })
+        .await
+    }
+
+    /// Same as [Self::resolve_most_recent], but returns `None` as soon as the `token` is cancelled.
+    pub async fn resolve_most_recent_with_cancellation(
+        &self,
+        public_key: &PublicKey,
+        token: &CancellationToken,
+    ) -> Option<SignedPacket> {
+        if token.is_cancelled() {
+            return None;
+        }
+
+        futures_lite::future::or(self.resolve_most_recent(public_key), async {
+            token.cancelled().await;
+
+            None
+        })
+        .await
+    }
+
+    /// End the stream once the client is shut down, dropping any in-flight queries.
+    pub(crate) fn until_shutdown(&self, stream: ResolvedStream) -> ResolvedStream {
+        if self.is_shutdown() {
+            return Box::pin(futures_lite::stream::empty());
+        }
+
+        let token = self.0.shutdown.clone();
+
+        Box::pin(futures_lite::stream::unfold(
+            (stream, token),
+            |(mut stream, token)| async move {
+                let next = futures_lite::future::or(stream.next(), async {
+                    token.cancelled().await;
+
+                    None
+                })
+                .await?;
+
+                Some((next, (stream, token)))
+            },
+        ))
+    }
+}
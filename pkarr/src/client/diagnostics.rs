@@ -0,0 +1,230 @@
+//! Structured report of a resolution attempt, see [Client::diagnose].
+
+use std::time::Duration;
+
+#[cfg(relays)]
+use url::Url;
+
+use crate::{errors::SignedPacketVerifyError, CacheKey, PublicKey, SignedPacket};
+
+use super::Client;
+
+#[derive(Debug)]
+/// A report of what every source returned for a [PublicKey], see [Client::diagnose].
+pub struct DiagnosticReport {
+    /// The [PublicKey] that was resolved.
+    pub public_key: PublicKey,
+    /// State of the client's cache for this key.
+    pub cache: CacheDiagnostic,
+    #[cfg(dht)]
+    /// Responses from the [mainline] Dht, or `None` if the Dht is disabled.
+    pub dht: Option<DhtDiagnostic>,
+    #[cfg(relays)]
+    /// Responses from each [Relay](https://pkarr.org/relays), empty if relays are disabled.
+    pub relays: Vec<RelayDiagnostic>,
+}
+
+impl DiagnosticReport {
+    /// Returns the most recent valid [SignedPacket] seen from any source.
+    pub fn most_recent(&self) -> Option<&SignedPacket> {
+        let mut most_recent: Option<&SignedPacket> = None;
+
+        let cached = match &self.cache {
+            CacheDiagnostic::Hit { packet, .. } => Some(packet),
+            _ => None,
+        };
+
+        #[cfg(dht)]
+        let dht = self.dht.iter().flat_map(|dht| dht.packets.iter());
+        #[cfg(not(dht))]
+        let dht = std::iter::empty();
+
+        #[cfg(relays)]
+        let relays = self.relays.iter().filter_map(|relay| match &relay.outcome {
+            RelayOutcome::Found(packet) => Some(packet),
+            _ => None,
+        });
+        #[cfg(not(relays))]
+        let relays = std::iter::empty();
+
+        for packet in cached.into_iter().chain(dht).chain(relays) {
+            if most_recent.is_none_or(|most_recent| packet.more_recent_than(most_recent)) {
+                most_recent = Some(packet);
+            }
+        }
+
+        most_recent
+    }
+}
+
+#[derive(Debug)]
+/// State of the client's [crate::Cache] for a given key.
+pub enum CacheDiagnostic {
+    /// The client has no cache.
+    Disabled,
+    /// No packet was found in the cache.
+    Miss,
+    /// A packet was found in the cache.
+    Hit {
+        /// The cached packet.
+        packet: SignedPacket,
+        /// Whether the packet is expired according to the client's minimum and maximum TTL.
+        expired: bool,
+        /// Seconds until the packet expires, see [SignedPacket::expires_in].
+        expires_in: u32,
+    },
+}
+
+#[cfg(dht)]
+#[derive(Debug)]
+/// Responses from the [mainline] Dht.
+pub struct DhtDiagnostic {
+    /// Whether the Dht node was bootstrapped when the query was made.
+    pub bootstrapped: bool,
+    /// The closest nodes to the key that responded to the query.
+    pub closest_nodes: Vec<mainline::Node>,
+    /// Valid packets received, one for each node that returned a value.
+    pub packets: Vec<SignedPacket>,
+    /// Items that were received but could not be parsed as a [SignedPacket].
+    pub rejected: Vec<SignedPacketVerifyError>,
+    /// Time it took for the query to complete.
+    pub duration: Duration,
+}
+
+#[cfg(relays)]
+#[derive(Debug)]
+/// Response from a single [Relay](https://pkarr.org/relays).
+pub struct RelayDiagnostic {
+    /// The base url of the relay.
+    pub url: Url,
+    /// HTTP status code, or `None` if no response was received.
+    pub status: Option<u16>,
+    /// Time it took to receive the response (or fail).
+    pub latency: Duration,
+    /// What the relay returned.
+    pub outcome: RelayOutcome,
+}
+
+#[cfg(relays)]
+#[derive(Debug)]
+/// What a [Relay](https://pkarr.org/relays) returned for a GET request.
+pub enum RelayOutcome {
+    /// A valid [SignedPacket].
+    Found(SignedPacket),
+    /// The relay responded with `404 Not Found`.
+    NotFound,
+    /// The relay responded with `304 Not Modified`.
+    NotModified,
+    /// The relay responded with a payload that failed verification.
+    Rejected(SignedPacketVerifyError),
+    /// The request failed, or the relay responded with an unexpected error.
+    Error(String),
+}
+
+impl Client {
+    /// Query every configured source for a [PublicKey] and return a [DiagnosticReport]
+    /// of what each one returned, including rejected packets and the state of the cache.
+    ///
+    /// Unlike [Self::resolve], this method doesn't update the cache, and always
+    /// waits for all queries to complete.
+    ///
+    /// Useful to debug why [Self::resolve] returns `None`.
+    pub async fn diagnose(&self, public_key: &PublicKey) -> DiagnosticReport {
+        super::async_compat_if_necessary(self.diagnose_inner(public_key)).await
+    }
+
+    async fn diagnose_inner(&self, public_key: &PublicKey) -> DiagnosticReport {
+        let cache_key: CacheKey = public_key.into();
+
+        let cache = match self.async_cache() {
+            None => CacheDiagnostic::Disabled,
+            Some(cache) => match cache.get_read_only(&cache_key).await {
+                None => CacheDiagnostic::Miss,
+                Some(packet) => {
+                    let now = self.0.clock.now();
+
+                    CacheDiagnostic::Hit {
+                        expired: packet.is_expired_at(self.0.minimum_ttl, self.0.maximum_ttl, now),
+                        expires_in: packet.expires_in_at(
+                            self.0.minimum_ttl,
+                            self.0.maximum_ttl,
+                            now,
+                        ),
+                        packet,
+                    }
+                }
+            },
+        };
+
+        #[cfg(dht)]
+        let dht_future = async {
+            match self.dht() {
+                Some(dht) => Some(diagnose_dht(dht.as_async(), public_key).await),
+                None => None,
+            }
+        };
+
+        #[cfg(relays)]
+        let relays_future = async {
+            match &self.0.relays {
+                Some(relays) => relays.diagnose(public_key).await,
+                None => vec![],
+            }
+        };
+
+        #[cfg(all(dht, relays))]
+        let (dht, relays) = futures_lite::future::zip(dht_future, relays_future).await;
+        #[cfg(all(dht, not(relays)))]
+        let dht = dht_future.await;
+        #[cfg(all(relays, not(dht)))]
+        let relays = relays_future.await;
+
+        DiagnosticReport {
+            public_key: public_key.clone(),
+            cache,
+            #[cfg(dht)]
+            dht,
+            #[cfg(relays)]
+            relays,
+        }
+    }
+}
+
+#[cfg(dht)]
+async fn diagnose_dht(dht: mainline::async_dht::AsyncDht, public_key: &PublicKey) -> DhtDiagnostic {
+    use futures_lite::StreamExt;
+
+    let started_at = ntimestamp::Timestamp::now();
+
+    let target = mainline::MutableItem::target_from_key(public_key.as_bytes(), None);
+
+    // Both requests share the same iterative query in mainline.
+    let items = dht
+        .get_mutable(public_key.as_bytes(), None, None)
+        .collect::<Vec<_>>();
+    let closest_nodes = dht.get_closest_nodes(target);
+
+    let (bootstrapped, (items, closest_nodes)) = futures_lite::future::zip(
+        dht.bootstrapped(),
+        futures_lite::future::zip(items, closest_nodes),
+    )
+    .await;
+
+    let mut packets = vec![];
+    let mut rejected = vec![];
+
+    for item in items {
+        match SignedPacket::try_from(item) {
+            Ok(packet) => packets.push(packet),
+            Err(error) => rejected.push(error),
+        }
+    }
+
+    DhtDiagnostic {
+        bootstrapped,
+        closest_nodes: closest_nodes.into_vec(),
+        packets,
+        rejected,
+        duration: super::resolved::elapsed_since(started_at),
+    }
+}
@@ -0,0 +1,223 @@
+//! Discover [Relays](https://pkarr.org/relays) from relay lists published as [SignedPacket]s,
+//! see [crate::ClientBuilder::relays_from_key].
+
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+use simple_dns::rdata::{RData, SVCB};
+use url::Url;
+
+use crate::{PublicKey, SignedPacket};
+
+use super::{Client, Inner};
+
+/// The name of the records listing relays in a relay list [SignedPacket],
+/// see [crate::ClientBuilder::relays_from_key].
+pub const RELAYS_RECORD_NAME: &str = "_pkarr-relays";
+
+/// Default interval between refreshing relay lists,
+/// see [crate::ClientBuilder::relays_refresh_interval].
+pub const DEFAULT_RELAYS_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum number of relays accepted from a single relay list.
+const MAX_RELAYS_PER_LIST: usize = 20;
+
+/// How often the discovery thread checks if the client was dropped or shut down while waiting.
+const DROPPED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delay before retrying to resolve relay lists that were never resolved yet,
+/// doubled after each attempt up to the refresh interval.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Returns the relays listed in a relay list [SignedPacket].
+///
+/// `TXT` records contain a relay's Url, while `HTTPS` and `SVCB` records point to
+/// `https://<target>[:<port>]`. Invalid and duplicate entries are ignored.
+pub(crate) fn relays_from_packet(signed_packet: &SignedPacket) -> Vec<Url> {
+    let mut relays: Vec<Url> = vec![];
+
+    for record in signed_packet.resource_records(RELAYS_RECORD_NAME) {
+        let url = match &record.rdata {
+            RData::TXT(txt) => txt
+                .clone()
+                .try_into()
+                .ok()
+                .and_then(|text: String| Url::parse(&text).ok()),
+            RData::HTTPS(https) => svcb_url(&https.0),
+            RData::SVCB(svcb) => svcb_url(svcb),
+            _ => None,
+        };
+
+        let Some(url) = url else {
+            continue;
+        };
+
+        if !matches!(url.scheme(), "http" | "https") || url.cannot_be_a_base() {
+            cross_debug!("Ignoring invalid relay {url} in relays list");
+
+            continue;
+        }
+
+        if !relays.contains(&url) {
+            relays.push(url);
+        }
+
+        if relays.len() >= MAX_RELAYS_PER_LIST {
+            break;
+        }
+    }
+
+    relays
+}
+
+fn svcb_url(svcb: &SVCB) -> Option<Url> {
+    let target = svcb.target.to_string();
+
+    let port = svcb
+        .get_param(SVCB::PORT)
+        .and_then(|port| port.try_into().ok())
+        .map(u16::from_be_bytes);
+
+    match port {
+        Some(port) => Url::parse(&format!("https://{target}:{port}")).ok(),
+        None => Url::parse(&format!("https://{target}")).ok(),
+    }
+}
+
+/// Merge the static relays with the discovered ones, keeping the order and removing duplicates.
+fn merge(static_relays: &[Url], discovered: &[Vec<Url>]) -> Vec<Url> {
+    let mut relays = static_relays.to_vec();
+
+    for url in discovered.iter().flatten() {
+        if !relays.contains(url) {
+            relays.push(url.clone());
+        }
+    }
+
+    relays
+}
+
+/// Periodically resolve the relay lists from the Dht, and update the client's relays,
+/// until the client is dropped.
+///
+/// Until every relay list was resolved at least once, retries with a backoff
+/// instead of waiting for the whole interval.
+pub(crate) fn spawn(
+    client: &Client,
+    keys: Vec<PublicKey>,
+    static_relays: Vec<Url>,
+    interval: Duration,
+) {
+    let weak = Arc::downgrade(&client.0);
+
+    let result = thread::Builder::new()
+        .name("pkarr-relays-discovery".to_string())
+        .spawn(move || {
+            // Keep the last known list of each key, in case it can't be resolved later.
+            let mut discovered = vec![vec![]; keys.len()];
+            let mut resolved = vec![false; keys.len()];
+            let mut retry_delay = INITIAL_RETRY_DELAY;
+
+            loop {
+                let Some(client) = weak.upgrade().map(Client) else {
+                    break;
+                };
+
+                if client.is_shutdown() {
+                    break;
+                }
+
+                refresh(
+                    &client,
+                    &keys,
+                    &static_relays,
+                    &mut discovered,
+                    &mut resolved,
+                );
+
+                drop(client);
+
+                let delay = if resolved.iter().all(|resolved| *resolved) {
+                    interval
+                } else {
+                    let delay = retry_delay.min(interval);
+                    retry_delay = retry_delay.saturating_mul(2);
+
+                    delay
+                };
+
+                if !wait(&weak, delay) {
+                    break;
+                }
+            }
+        });
+
+    if let Err(error) = result {
+        cross_debug!("Failed to spawn relays discovery thread {error}");
+    }
+}
+
+/// Sleep for the interval, returns false if the client was dropped or shut down in the meantime.
+fn wait(inner: &Weak<Inner>, interval: Duration) -> bool {
+    let mut remaining = interval;
+
+    while !remaining.is_zero() {
+        match inner.upgrade() {
+            Some(inner) if !inner.shutdown.is_cancelled() => {}
+            _ => return false,
+        }
+
+        let step = remaining.min(DROPPED_CHECK_INTERVAL);
+        thread::sleep(step);
+        remaining -= step;
+    }
+
+    inner.strong_count() > 0
+}
+
+fn refresh(
+    client: &Client,
+    keys: &[PublicKey],
+    static_relays: &[Url],
+    discovered: &mut [Vec<Url>],
+    resolved: &mut [bool],
+) {
+    let (Some(dht), Some(relays_client)) = (client.dht(), &client.0.relays) else {
+        return;
+    };
+
+    for ((public_key, discovered), resolved) in keys
+        .iter()
+        .zip(discovered.iter_mut())
+        .zip(resolved.iter_mut())
+    {
+        let signed_packet = dht
+            .get_mutable_most_recent(public_key.as_bytes(), None)
+            .and_then(|item| SignedPacket::try_from(item).ok());
+
+        let Some(signed_packet) = signed_packet else {
+            cross_debug!("Couldn't resolve relays list from {public_key}");
+
+            continue;
+        };
+
+        *resolved = true;
+
+        let relays = relays_from_packet(&signed_packet);
+
+        if relays.is_empty() {
+            cross_debug!("Relays list from {public_key} is empty, keeping the previous one");
+        } else {
+            *discovered = relays;
+        }
+    }
+
+    let relays = merge(static_relays, discovered);
+
+    if relays.as_slice() != &*relays_client.relays() {
+        cross_debug!("Updating relays from discovered relays lists {relays:?}");
+
+        relays_client.set_relays(relays);
+    }
+}
@@ -4,7 +4,7 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use crate::client::ConcurrencyError;
-use crate::SignedPacket;
+use crate::Resolved;
 
 use super::PublishError;
 
@@ -37,14 +37,8 @@ impl SelectFuture {
         network: Network,
         result: Result<(), PublishError>,
     ) -> Poll<Result<(), PublishError>> {
-        match self.first_result {
-            // We already have a success, ignore CAS failures
-            Some((_, Ok(()))) => Poll::Ready(match result {
-                Err(PublishError::Concurrency(ConcurrencyError::CasFailed)) => Ok(()),
-                _ => result,
-            }),
-            // We already have a failure, return the later network's result
-            Some(_) => Poll::Ready(result),
+        match &self.first_result {
+            Some((_, first)) => Poll::Ready(combine_results(first.clone(), result)),
             // This is our first result, store it and continue polling
             None => {
                 self.first_result = Some((network, result));
@@ -55,6 +49,23 @@ impl SelectFuture {
     }
 }
 
+/// Returns the result of publishing to both networks, given the result of the network
+/// that completed first, and the result of the one that completed second.
+pub fn combine_results(
+    first: Result<(), PublishError>,
+    second: Result<(), PublishError>,
+) -> Result<(), PublishError> {
+    match first {
+        // We already have a success, ignore CAS failures
+        Ok(()) => match second {
+            Err(PublishError::Concurrency(ConcurrencyError::CasFailed)) => Ok(()),
+            _ => second,
+        },
+        // We already have a failure, return the later network's result
+        Err(_) => second,
+    }
+}
+
 impl Future for SelectFuture {
     type Output = Result<(), PublishError>;
 
@@ -90,8 +101,8 @@ impl Future for SelectFuture {
 /// - Continue polling a stream even after the other is exhausted.
 /// - Only terminate when **both** streams have returned `None`.
 pub fn select_stream(
-    dht_stream: Pin<Box<dyn Stream<Item = SignedPacket> + Send>>,
-    relays_stream: Pin<Box<dyn Stream<Item = SignedPacket> + Send>>,
+    dht_stream: Pin<Box<dyn Stream<Item = Resolved> + Send>>,
+    relays_stream: Pin<Box<dyn Stream<Item = Resolved> + Send>>,
 ) -> SelectStream {
     SelectStream {
         mode: Mode::RoundRobin(Network::Dht),
@@ -102,8 +113,8 @@ pub fn select_stream(
 
 pub struct SelectStream {
     mode: Mode,
-    dht_stream: Pin<Box<dyn Stream<Item = SignedPacket> + Send>>,
-    relays_stream: Pin<Box<dyn Stream<Item = SignedPacket> + Send>>,
+    dht_stream: Pin<Box<dyn Stream<Item = Resolved> + Send>>,
+    relays_stream: Pin<Box<dyn Stream<Item = Resolved> + Send>>,
 }
 
 #[derive(Clone, Debug)]
@@ -119,7 +130,7 @@ enum Network {
 }
 
 impl Stream for SelectStream {
-    type Item = SignedPacket;
+    type Item = Resolved;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
@@ -0,0 +1,224 @@
+//! Health tracking and circuit breaking of [Relays](https://pkarr.org/relays),
+//! see [crate::ClientBuilder::relay_circuit_breaker] and [crate::ClientBuilder::relay_hedging].
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ntimestamp::Timestamp;
+use url::Url;
+
+use super::Client;
+use crate::Clock;
+
+/// Default number of consecutive failures before a relay is skipped,
+/// see [crate::ClientBuilder::relay_circuit_breaker].
+pub const DEFAULT_RELAY_FAILURE_THRESHOLD: u32 = 5;
+/// Default duration a failing relay is skipped for,
+/// see [crate::ClientBuilder::relay_circuit_breaker].
+pub const DEFAULT_RELAY_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Weight of the most recent sample in the latency and error rate moving averages.
+const EWMA_WEIGHT: f32 = 0.3;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A snapshot of a relay's health, see [Client::relays_health].
+pub struct RelayHealth {
+    /// The relay's Url.
+    pub url: Url,
+    /// Exponentially weighted moving average of successful requests' latency,
+    /// or `None` if no request succeeded yet.
+    pub latency: Option<Duration>,
+    /// Exponentially weighted moving average of failed requests, between `0.0` and `1.0`.
+    pub error_rate: f32,
+    /// Number of failed requests since the last successful one.
+    pub consecutive_failures: u32,
+    /// Whether the circuit breaker is open, and this relay is skipped when resolving.
+    pub skipped: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HealthConfig {
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+    pub hedging: Option<(usize, Duration)>,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: DEFAULT_RELAY_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_RELAY_COOLDOWN,
+            hedging: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Entry {
+    latency: Option<Duration>,
+    error_rate: f32,
+    consecutive_failures: u32,
+    open_until: Option<Timestamp>,
+}
+
+impl Entry {
+    fn is_open(&self, now: Timestamp) -> bool {
+        self.open_until.is_some_and(|open_until| open_until > now)
+    }
+}
+
+/// Which relays to query immediately, and which to query after the hedging delay.
+pub(crate) struct Plan {
+    pub first: Vec<usize>,
+    pub hedged: Vec<usize>,
+    pub delay: Duration,
+}
+
+#[derive(Debug, Clone)]
+/// Health of each relay, indexed in the same order as the relays.
+pub(crate) struct RelaysHealth {
+    config: HealthConfig,
+    entries: Arc<Mutex<Vec<Entry>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RelaysHealth {
+    pub fn new(relays_count: usize, config: HealthConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            config,
+            entries: Arc::new(Mutex::new(vec![Entry::default(); relays_count])),
+            clock,
+        }
+    }
+
+    pub fn record_success(&self, index: usize, latency: Duration) {
+        let mut entries = self.entries.lock().expect("RelaysHealth lock");
+        let entry = &mut entries[index];
+
+        entry.latency = Some(match entry.latency {
+            Some(average) => average.mul_f32(1.0 - EWMA_WEIGHT) + latency.mul_f32(EWMA_WEIGHT),
+            None => latency,
+        });
+        entry.error_rate *= 1.0 - EWMA_WEIGHT;
+        entry.consecutive_failures = 0;
+        entry.open_until = None;
+    }
+
+    pub fn record_failure(&self, index: usize) {
+        let mut entries = self.entries.lock().expect("RelaysHealth lock");
+        let entry = &mut entries[index];
+
+        entry.error_rate = entry.error_rate * (1.0 - EWMA_WEIGHT) + EWMA_WEIGHT;
+        entry.consecutive_failures += 1;
+
+        if self.config.failure_threshold > 0
+            && entry.consecutive_failures >= self.config.failure_threshold
+        {
+            cross_debug!(
+                "Relay failed {} times in a row, skipping it for {:?}",
+                entry.consecutive_failures,
+                self.config.cooldown
+            );
+
+            entry.open_until = Some(self.clock.now() + self.config.cooldown.as_micros() as u64);
+        }
+    }
+
+    /// Returns the relays to resolve from, skipping relays with an open circuit breaker
+    /// (unless all of them are), sorted by their latency with unknown latencies first.
+    pub fn plan(&self) -> Plan {
+        let entries = self.entries.lock().expect("RelaysHealth lock");
+        let now = self.clock.now();
+
+        let mut available = (0..entries.len())
+            .filter(|index| !entries[*index].is_open(now))
+            .collect::<Vec<_>>();
+
+        if available.is_empty() {
+            available = (0..entries.len()).collect();
+        }
+
+        match self.config.hedging {
+            Some((fastest, delay)) => {
+                // Stable sort keeps the configured order for relays with the same latency.
+                available.sort_by_key(|index| entries[*index].latency);
+
+                let hedged = available.split_off(fastest.max(1).min(available.len()));
+
+                Plan {
+                    first: available,
+                    hedged,
+                    delay,
+                }
+            }
+            None => Plan {
+                first: available,
+                hedged: vec![],
+                delay: Duration::ZERO,
+            },
+        }
+    }
+
+    /// Returns every relay to resolve from at once, regardless of its health or hedging.
+    pub fn plan_every(&self) -> Plan {
+        let entries = self.entries.lock().expect("RelaysHealth lock");
+
+        Plan {
+            first: (0..entries.len()).collect(),
+            hedged: vec![],
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[cfg(dht)]
+    /// Returns a new health tracker for the `new` relays, keeping the entries
+    /// of relays that are also in the `old` relays.
+    pub fn retain(&self, old: &[Url], new: &[Url]) -> Self {
+        let entries = self.entries.lock().expect("RelaysHealth lock");
+
+        let entries = new
+            .iter()
+            .map(|url| {
+                old.iter()
+                    .position(|old| old == url)
+                    .map(|index| entries[index])
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        Self {
+            config: self.config,
+            entries: Arc::new(Mutex::new(entries)),
+            clock: self.clock.clone(),
+        }
+    }
+
+    pub fn report(&self, relays: &[Url]) -> Vec<RelayHealth> {
+        let entries = self.entries.lock().expect("RelaysHealth lock");
+        let now = self.clock.now();
+
+        relays
+            .iter()
+            .zip(entries.iter())
+            .map(|(url, entry)| RelayHealth {
+                url: url.clone(),
+                latency: entry.latency,
+                error_rate: entry.error_rate,
+                consecutive_failures: entry.consecutive_failures,
+                skipped: entry.is_open(now),
+            })
+            .collect()
+    }
+}
+
+impl Client {
+    /// Returns the health of each configured [Relay](https://pkarr.org/relays),
+    /// or an empty list if relays are disabled.
+    pub fn relays_health(&self) -> Vec<RelayHealth> {
+        self.0
+            .relays
+            .as_ref()
+            .map(|relays| relays.health())
+            .unwrap_or_default()
+    }
+}
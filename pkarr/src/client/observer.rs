@@ -0,0 +1,106 @@
+//! Structured events emitted by the [Client], see [crate::ClientBuilder::observer].
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(relays)]
+use url::Url;
+
+use crate::errors::{PublishError, SignedPacketVerifyError};
+use crate::{PublicKey, Source};
+
+use super::Client;
+
+/// A trait for receiving [ClientEvent]s from a [Client], registered with
+/// [crate::ClientBuilder::observer].
+///
+/// Events are emitted synchronously from within the client's queries,
+/// so implementations should be cheap and must not block.
+pub trait ClientObserver: Debug + Send + Sync {
+    /// Called for every [ClientEvent].
+    fn on_event(&self, event: &ClientEvent);
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// A structured event emitted by the [Client] to its [ClientObserver].
+pub enum ClientEvent {
+    /// A [Client::resolve] found a packet in the cache.
+    CacheHit {
+        /// The resolved [PublicKey].
+        public_key: PublicKey,
+        /// Whether the cached packet is expired, and will be refreshed in the background.
+        expired: bool,
+    },
+    /// A [Client::resolve] didn't find a packet in the cache.
+    CacheMiss {
+        /// The resolved [PublicKey].
+        public_key: PublicKey,
+    },
+    #[cfg(relays)]
+    /// A request to a [Relay](https://pkarr.org/relays) completed.
+    RelayResponse {
+        /// The relay's Url.
+        url: Url,
+        /// The [PublicKey] in the request.
+        public_key: PublicKey,
+        /// Whether the request was resolving or publishing a packet.
+        request: RelayRequest,
+        /// The HTTP status code, if a response was received.
+        status: Option<u16>,
+        /// Time elapsed between sending the request and receiving the response (or error).
+        latency: Duration,
+    },
+    #[cfg(dht)]
+    /// A valid packet was received from a [mainline] Dht node.
+    DhtResponse {
+        /// The resolved [PublicKey].
+        public_key: PublicKey,
+        /// Time elapsed between starting the query and receiving this response.
+        latency: Duration,
+    },
+    /// An invalid packet was received and discarded.
+    PacketRejected {
+        /// The resolved [PublicKey].
+        public_key: PublicKey,
+        /// Where the invalid packet came from.
+        source: Source,
+        /// Why the packet was rejected.
+        error: SignedPacketVerifyError,
+    },
+    /// Publishing a packet completed.
+    Published {
+        /// The published packet's [PublicKey].
+        public_key: PublicKey,
+        /// The result returned to the caller.
+        result: Result<(), PublishError>,
+    },
+}
+
+#[cfg(relays)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The kind of a request to a [Relay](https://pkarr.org/relays), see [ClientEvent::RelayResponse].
+pub enum RelayRequest {
+    /// A `GET` request resolving a packet.
+    Resolve,
+    /// A `PUT` request publishing a packet.
+    Publish,
+}
+
+impl Client {
+    /// Emit an event to the observer if any, without building the event otherwise.
+    pub(crate) fn notify(&self, event: impl FnOnce() -> ClientEvent) {
+        notify(self.0.observer.as_ref(), event)
+    }
+}
+
+/// Emit an event to the observer if any, without building the event otherwise.
+pub(crate) fn notify(
+    observer: Option<&Arc<dyn ClientObserver>>,
+    event: impl FnOnce() -> ClientEvent,
+) {
+    if let Some(observer) = observer {
+        observer.on_event(&event());
+    }
+}
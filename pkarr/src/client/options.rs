@@ -0,0 +1,130 @@
+//! Per-request [RequestOptions], see [Client::resolve_with_options] and [Client::publish_with_options].
+
+use std::time::Duration;
+
+use ntimestamp::Timestamp;
+
+use crate::{PublicKey, SignedPacket};
+
+use super::{async_compat_if_necessary, sleep, Client, PublishError, QueryError};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// Which of the client's networks to use for a single request.
+pub enum NetworkSelection {
+    #[default]
+    /// Use all networks enabled in the [Client].
+    All,
+    /// Use the [mainline] Dht only.
+    Dht,
+    /// Use the [Relays](https://pkarr.org/relays) only.
+    Relays,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// How a single request uses the client's [crate::Cache].
+pub enum CachePolicy {
+    #[default]
+    /// Read from and write to the cache, same as [Client::resolve] and [Client::publish].
+    Use,
+    /// Ignore the cached packet and query the network, but still store more recent packets in the cache.
+    ///
+    /// When publishing, skips checking the cache for conflicts, but doesn't replace
+    /// a more recent cached packet.
+    Bypass,
+    /// Only read from the cache, without querying the network.
+    ///
+    /// When publishing, only stores the packet in the cache, without publishing it to the network.
+    Only,
+    /// Read from the cache, but don't store any packets in it.
+    ///
+    /// When resolving an expired cached packet, waits for a more recent packet from the network,
+    /// falling back to the cached packet, instead of refreshing the cache in the background.
+    NoStore,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+/// Options for a single resolve or publish request, overriding the [Client]'s configuration
+/// without rebuilding it.
+pub struct RequestOptions {
+    /// Maximum time to wait for the request to complete.
+    ///
+    /// Applies to each HTTP request to [Relays](https://pkarr.org/relays), and to the request as a whole.
+    ///
+    /// Defaults to `None`, meaning: use [crate::ClientBuilder::request_timeout].
+    pub timeout: Option<Duration>,
+    /// Which networks to use, defaults to [NetworkSelection::All].
+    pub networks: NetworkSelection,
+    /// How to use the cache, defaults to [CachePolicy::Use].
+    pub cache: CachePolicy,
+}
+
+impl RequestOptions {
+    #[cfg(dht)]
+    pub(crate) fn use_dht(&self) -> bool {
+        matches!(self.networks, NetworkSelection::All | NetworkSelection::Dht)
+    }
+
+    #[cfg(relays)]
+    pub(crate) fn use_relays(&self) -> bool {
+        matches!(
+            self.networks,
+            NetworkSelection::All | NetworkSelection::Relays
+        )
+    }
+}
+
+impl Client {
+    /// Same as [Self::publish] but with per-request [RequestOptions].
+    ///
+    /// Returns [QueryError::NoNetwork] if none of the selected networks are enabled in this client.
+    pub async fn publish_with_options(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        options: RequestOptions,
+    ) -> Result<(), PublishError> {
+        let future = self.publish_inner(signed_packet, cas, &options);
+
+        async_compat_if_necessary(async {
+            match options.timeout {
+                Some(timeout) => {
+                    futures_lite::future::or(future, async {
+                        sleep(timeout).await;
+
+                        Err(QueryError::Timeout.into())
+                    })
+                    .await
+                }
+                None => future.await,
+            }
+        })
+        .await
+    }
+
+    /// Same as [Self::resolve] but with per-request [RequestOptions].
+    ///
+    /// Returns `None` if none of the selected networks are enabled in this client,
+    /// unless the packet is found in the cache.
+    pub async fn resolve_with_options(
+        &self,
+        public_key: &PublicKey,
+        options: RequestOptions,
+    ) -> Option<SignedPacket> {
+        let future = self.resolve_inner(public_key, &options);
+
+        async_compat_if_necessary(async {
+            match options.timeout {
+                Some(timeout) => {
+                    futures_lite::future::or(future, async {
+                        sleep(timeout).await;
+
+                        None
+                    })
+                    .await
+                }
+                None => future.await,
+            }
+        })
+        .await
+    }
+}
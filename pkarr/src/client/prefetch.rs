@@ -0,0 +1,255 @@
+//! Refresh hot cache entries before they expire, see [crate::ClientBuilder::prefetch_threshold].
+
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_lite::StreamExt;
+use lru::LruCache;
+use ntimestamp::Timestamp;
+
+use crate::{CacheKey, PublicKey, SignedPacket};
+
+use super::{Client, RequestOptions};
+
+/// Default maximum number of concurrent background prefetches,
+/// see [crate::ClientBuilder::max_concurrent_prefetches].
+pub const DEFAULT_MAX_CONCURRENT_PREFETCHES: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Prefetch counters since the [Client] was built, see [Client::prefetch_stats].
+pub struct PrefetchStats {
+    /// Number of background prefetches started.
+    pub started: u64,
+    /// Number of prefetches skipped because [crate::ClientBuilder::max_concurrent_prefetches]
+    /// was reached.
+    pub skipped: u64,
+    /// Number of prefetches that found a more recent [SignedPacket].
+    pub updated: u64,
+    /// Number of prefetches that didn't receive any [SignedPacket].
+    pub not_found: u64,
+    /// Number of prefetches currently running.
+    pub inflight: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Prefetcher {
+    threshold: f32,
+    max_concurrency: usize,
+    min_hits: u32,
+    /// Hits of due packets, `None` if `min_hits` is `1`.
+    hits: Option<Arc<Mutex<Hits>>>,
+    inflight: Arc<Mutex<HashSet<CacheKey>>>,
+    counters: Arc<Counters>,
+}
+
+/// Number of hits per key, and the [SignedPacket::last_seen] they were counted for.
+type Hits = LruCache<CacheKey, (Timestamp, u32)>;
+
+#[derive(Debug, Default)]
+struct Counters {
+    started: AtomicU64,
+    skipped: AtomicU64,
+    updated: AtomicU64,
+    not_found: AtomicU64,
+}
+
+impl Prefetcher {
+    pub(crate) fn new(
+        threshold: f32,
+        max_concurrency: usize,
+        min_hits: u32,
+        capacity: usize,
+    ) -> Option<Self> {
+        if threshold <= 0.0 {
+            return None;
+        }
+
+        let min_hits = min_hits.max(1);
+        let hits = (min_hits > 1).then(|| {
+            Arc::new(Mutex::new(LruCache::new(
+                NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+            )))
+        });
+
+        Some(Self {
+            threshold,
+            max_concurrency,
+            min_hits,
+            hits,
+            inflight: Default::default(),
+            counters: Default::default(),
+        })
+    }
+
+    /// Returns true if the packet is not expired yet, but within the threshold of its TTL.
+    fn is_due(&self, signed_packet: &SignedPacket, min: u32, max: u32, now: Timestamp) -> bool {
+        let expires_in = signed_packet.expires_in_at(min, max, now);
+        let ttl = signed_packet.ttl(min, max);
+
+        expires_in > 0 && expires_in as f32 <= ttl as f32 * self.threshold
+    }
+
+    /// Count a hit of a due packet, and return true once it was hit at least `min_hits` times.
+    ///
+    /// Hits counted for an older version of the packet (a different [SignedPacket::last_seen])
+    /// are discarded.
+    fn hit(&self, cache_key: &CacheKey, last_seen: Timestamp) -> bool {
+        let Some(hits) = &self.hits else {
+            return true;
+        };
+
+        let mut hits = hits.lock().expect("Prefetcher::hits lock");
+
+        let count = match hits.get_mut(cache_key) {
+            Some((seen, count)) if *seen == last_seen => {
+                *count += 1;
+                *count
+            }
+            _ => {
+                hits.put(*cache_key, (last_seen, 1));
+                1
+            }
+        };
+
+        count >= self.min_hits
+    }
+
+    /// Reserve a prefetch slot for this key, unless it is already being prefetched,
+    /// or the maximum concurrency is reached.
+    fn start(&self, cache_key: &CacheKey) -> Option<InflightGuard> {
+        let mut inflight = self.inflight.lock().expect("Prefetcher::inflight lock");
+
+        if inflight.contains(cache_key) {
+            return None;
+        }
+
+        if inflight.len() >= self.max_concurrency {
+            self.counters.skipped.fetch_add(1, Ordering::Relaxed);
+
+            return None;
+        }
+
+        inflight.insert(*cache_key);
+        self.counters.started.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(hits) = &self.hits {
+            hits.lock().expect("Prefetcher::hits lock").pop(cache_key);
+        }
+
+        Some(InflightGuard {
+            cache_key: *cache_key,
+            inflight: self.inflight.clone(),
+        })
+    }
+
+    fn stats(&self) -> PrefetchStats {
+        PrefetchStats {
+            started: self.counters.started.load(Ordering::Relaxed),
+            skipped: self.counters.skipped.load(Ordering::Relaxed),
+            updated: self.counters.updated.load(Ordering::Relaxed),
+            not_found: self.counters.not_found.load(Ordering::Relaxed),
+            inflight: self
+                .inflight
+                .lock()
+                .expect("Prefetcher::inflight lock")
+                .len(),
+        }
+    }
+}
+
+/// Frees the prefetch slot once the prefetch is done or dropped.
+struct InflightGuard {
+    cache_key: CacheKey,
+    inflight: Arc<Mutex<HashSet<CacheKey>>>,
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.inflight
+            .lock()
+            .expect("Prefetcher::inflight lock")
+            .remove(&self.cache_key);
+    }
+}
+
+impl Client {
+    /// Returns the prefetch counters since this client was built,
+    /// or `None` if prefetching is disabled, see [crate::ClientBuilder::prefetch_threshold].
+    pub fn prefetch_stats(&self) -> Option<PrefetchStats> {
+        self.0
+            .prefetcher
+            .as_ref()
+            .map(|prefetcher| prefetcher.stats())
+    }
+
+    /// Refresh the cached packet in the background, if it was resolved close to its expiry.
+    pub(crate) fn maybe_prefetch(&self, public_key: &PublicKey, cached_packet: &SignedPacket) {
+        let (Some(prefetcher), Some(cache)) = (&self.0.prefetcher, self.0.async_cache.clone())
+        else {
+            return;
+        };
+
+        if !prefetcher.is_due(
+            cached_packet,
+            self.0.minimum_ttl,
+            self.0.maximum_ttl,
+            self.0.clock.now(),
+        ) {
+            return;
+        }
+
+        let cache_key: CacheKey = public_key.into();
+
+        if !prefetcher.hit(&cache_key, *cached_packet.last_seen()) {
+            return;
+        }
+
+        let Some(guard) = prefetcher.start(&cache_key) else {
+            return;
+        };
+
+        cross_debug!("Prefetching cached packet before it expires. public_key: {public_key}");
+
+        let counters = prefetcher.counters.clone();
+
+        // Query without `more_recent_than`, to refresh the `last_seen` of an unchanged packet.
+        let mut stream = self.network_stream(public_key, None, &RequestOptions::default());
+
+        let prefetch = async move {
+            let _guard = guard;
+
+            let mut found = false;
+            let mut updated = false;
+
+            while let Some(resolved) = stream.next().await {
+                found = true;
+
+                let packet = resolved.packet;
+
+                match cache.get_read_only(&cache_key).await {
+                    Some(cached) if cached.as_bytes() == packet.as_bytes() => {
+                        cache.put(&cache_key, &packet).await;
+                    }
+                    Some(cached) if !packet.more_recent_than(&cached) => {}
+                    _ => {
+                        cache.put(&cache_key, &packet).await;
+                        updated = true;
+                    }
+                }
+            }
+
+            if updated {
+                counters.updated.fetch_add(1, Ordering::Relaxed);
+            } else if !found {
+                counters.not_found.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+
+        #[cfg(not(wasm_browser))]
+        tokio::spawn(prefetch);
+        #[cfg(wasm_browser)]
+        wasm_bindgen_futures::spawn_local(prefetch);
+    }
+}
@@ -0,0 +1,267 @@
+//! Resolve with consistency requirements, see [Client::resolve_with].
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures_lite::StreamExt;
+use ntimestamp::Timestamp;
+
+use crate::{CacheKey, PublicKey, SignedPacket};
+
+use super::{
+    async_compat_if_necessary, filter_incoming_signed_packet, sleep, Client, RequestOptions,
+};
+#[cfg(relays)]
+use super::{NetworkSelection, ResolvedStream, Source};
+
+#[derive(Debug, Clone, Default)]
+/// Consistency requirements for [Client::resolve_with].
+///
+/// The default options wait for all responses and accept any result,
+/// same as [Client::resolve_most_recent].
+pub struct ResolveOptions {
+    /// Minimum number of sources (Dht nodes or relays) that must return a packet
+    /// with the same timestamp as the most recent packet.
+    ///
+    /// Resolving returns as soon as this is reached, unless [Self::require_relays_agree] is set.
+    ///
+    /// Defaults to `0`, meaning: wait for all responses, and accept any number of confirmations.
+    pub min_responses: usize,
+    /// Stop waiting for more responses after this duration, and evaluate the responses so far.
+    ///
+    /// Defaults to `None`, meaning: wait until all queries are done.
+    pub deadline: Option<Duration>,
+    /// Require every configured [Relay](https://pkarr.org/relays) to return a packet
+    /// with the same timestamp as the most recent packet.
+    ///
+    /// Resolving waits for all responses when this is set, and queries every relay,
+    /// including relays with an open circuit breaker and
+    /// [hedged](crate::ClientBuilder::relay_hedging) relays.
+    pub require_relays_agree: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The most recent [SignedPacket] found by [Client::resolve_with],
+/// and how many sources confirmed its timestamp.
+pub struct Quorum {
+    /// The most recent [SignedPacket] received.
+    pub packet: SignedPacket,
+    /// Number of sources that returned a packet with the same timestamp as [Self::packet].
+    pub confirmations: usize,
+    /// Total number of sources that returned a valid packet.
+    pub responses: usize,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+/// Errors returned by [Client::resolve_with] when the [ResolveOptions] were not satisfied.
+pub enum QuorumError {
+    #[error("No SignedPacket was found")]
+    /// No [SignedPacket] was found.
+    NotFound,
+
+    #[error("Only {} of the required {required} sources confirmed the most recent SignedPacket", .quorum.confirmations)]
+    /// The most recent [SignedPacket] was not confirmed by [ResolveOptions::min_responses] sources
+    /// before all queries were done or the deadline was reached.
+    InsufficientConfirmations {
+        /// The most recent [SignedPacket] found so far.
+        quorum: Box<Quorum>,
+        /// The required number of confirmations.
+        required: usize,
+    },
+
+    #[error("Not all relays returned the most recent SignedPacket")]
+    /// Not all relays returned a packet with the same timestamp as the most recent [SignedPacket],
+    /// see [ResolveOptions::require_relays_agree].
+    RelaysDisagree(Box<Quorum>),
+}
+
+impl Client {
+    /// Returns the most recent [SignedPacket] found on the [mainline] Dht and/or
+    /// [Relays](https://pkarr.org/relays), along with how many sources confirmed its timestamp,
+    /// or an error if the [ResolveOptions] were not satisfied.
+    ///
+    /// Useful to know the confidence of a [SignedPacket::timestamp] before using it
+    /// as a `CAS` in [Client::publish].
+    ///
+    /// Unlike [Self::resolve], this method doesn't return the cached packet,
+    /// but it still stores more recent packets in the cache.
+    ///
+    /// A [static zone](crate::ClientBuilder::static_zone) is returned as a single confirmation,
+    /// regardless of the [ResolveOptions].
+    pub async fn resolve_with(
+        &self,
+        public_key: &PublicKey,
+        options: ResolveOptions,
+    ) -> Result<Quorum, QuorumError> {
+        if let Some(packet) = self.static_zone(public_key) {
+            return Ok(Quorum {
+                packet,
+                confirmations: 1,
+                responses: 1,
+            });
+        }
+
+        async_compat_if_necessary(self.resolve_with_inner(public_key, options)).await
+    }
+
+    async fn resolve_with_inner(
+        &self,
+        public_key: &PublicKey,
+        options: ResolveOptions,
+    ) -> Result<Quorum, QuorumError> {
+        let mut tally = Tally::default();
+
+        let collect = async {
+            let cache_key: CacheKey = public_key.into();
+            #[cfg(relays)]
+            let mut stream = if options.require_relays_agree {
+                self.every_relay_stream(public_key)
+            } else {
+                self.network_stream(public_key, None, &RequestOptions::default())
+            };
+            #[cfg(not(relays))]
+            let mut stream = self.network_stream(public_key, None, &RequestOptions::default());
+
+            while let Some(resolved) = stream.next().await {
+                filter_incoming_signed_packet(
+                    public_key,
+                    self.0.async_cache.clone(),
+                    &cache_key,
+                    resolved.packet.clone(),
+                )
+                .await;
+
+                #[cfg(relays)]
+                if let Source::Relay { url } = &resolved.source {
+                    tally
+                        .relays
+                        .insert(url.clone(), resolved.packet.timestamp());
+                }
+
+                tally.add(resolved.packet);
+
+                if options.min_responses > 0
+                    && !options.require_relays_agree
+                    && tally.confirmations() >= options.min_responses
+                {
+                    break;
+                }
+            }
+        };
+
+        match options.deadline {
+            Some(deadline) => futures_lite::future::or(collect, sleep(deadline)).await,
+            None => collect.await,
+        };
+
+        let quorum = tally.into_quorum().ok_or(QuorumError::NotFound)?;
+
+        #[cfg(relays)]
+        if options.require_relays_agree {
+            if let Some(relays) = &self.0.relays {
+                if quorum.relays_confirmations < relays.relays().len() {
+                    return Err(QuorumError::RelaysDisagree(Box::new(quorum.quorum)));
+                }
+            }
+        }
+
+        let quorum = quorum.quorum;
+
+        if quorum.confirmations < options.min_responses {
+            return Err(QuorumError::InsufficientConfirmations {
+                quorum: Box::new(quorum),
+                required: options.min_responses,
+            });
+        }
+
+        Ok(quorum)
+    }
+
+    #[cfg(relays)]
+    /// Same as [Self::network_stream], but queries every relay at once, so that all of them
+    /// get a chance to confirm the most recent packet.
+    fn every_relay_stream(&self, public_key: &PublicKey) -> ResolvedStream {
+        let dht = self.network_stream(
+            public_key,
+            None,
+            &RequestOptions {
+                networks: NetworkSelection::Dht,
+                ..Default::default()
+            },
+        );
+
+        let Some(relays) = self.relays_for(&RequestOptions::default()) else {
+            return dht;
+        };
+
+        let clock = self.0.clock.clone();
+
+        let relays = relays
+            .resolve_every_futures(public_key)
+            .filter_map(|resolved| resolved)
+            .map(move |mut resolved| {
+                resolved.packet.refresh_at(clock.now());
+
+                resolved
+            });
+
+        self.until_shutdown(Box::pin(dht.fuse().or(relays.fuse())))
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    most_recent: Option<SignedPacket>,
+    timestamps: HashMap<Timestamp, usize>,
+    responses: usize,
+    #[cfg(relays)]
+    relays: HashMap<url::Url, Timestamp>,
+}
+
+struct TallyResult {
+    quorum: Quorum,
+    #[cfg(relays)]
+    relays_confirmations: usize,
+}
+
+impl Tally {
+    fn add(&mut self, packet: SignedPacket) {
+        self.responses += 1;
+        *self.timestamps.entry(packet.timestamp()).or_default() += 1;
+
+        if self
+            .most_recent
+            .as_ref()
+            .is_none_or(|most_recent| packet.more_recent_than(most_recent))
+        {
+            self.most_recent = Some(packet);
+        }
+    }
+
+    fn confirmations(&self) -> usize {
+        self.most_recent
+            .as_ref()
+            .and_then(|packet| self.timestamps.get(&packet.timestamp()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn into_quorum(self) -> Option<TallyResult> {
+        let confirmations = self.confirmations();
+        let packet = self.most_recent?;
+
+        Some(TallyResult {
+            #[cfg(relays)]
+            relays_confirmations: self
+                .relays
+                .values()
+                .filter(|timestamp| **timestamp == packet.timestamp())
+                .count(),
+            quorum: Quorum {
+                packet,
+                confirmations,
+                responses: self.responses,
+            },
+        })
+    }
+}
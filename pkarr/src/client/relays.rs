@@ -2,7 +2,8 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 #[cfg(not(wasm_browser))]
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
 use bytes::Bytes;
@@ -11,23 +12,38 @@ use futures_buffered::FuturesUnorderedBounded;
 use futures_lite::Stream;
 use futures_lite::StreamExt;
 use ntimestamp::Timestamp;
-use reqwest::Method;
 use url::Url;
 
 use reqwest::{
-    header::{self, HeaderValue},
-    Client, StatusCode,
+    header::{self, HeaderMap, HeaderValue},
+    StatusCode,
 };
 
-use super::{ConcurrencyError, PublishError, QueryError};
-use crate::{PublicKey, SignedPacket};
+use super::diagnostics::{RelayDiagnostic, RelayOutcome};
+use super::health::{HealthConfig, Plan, RelayHealth, RelaysHealth};
+use super::observer::{notify, ClientEvent, ClientObserver, RelayRequest};
+use super::report::RelayPublishReport;
+use super::resolved::elapsed_since;
+use super::retry::{retry_after, RetryPolicy};
+use super::transport::{RelayTransport, RelayTransportError};
+use super::{sleep, ConcurrencyError, PublishError, QueryError};
+use crate::{Clock, PublicKey, Resolved, SignedPacket, Source};
 
 #[derive(Clone)]
 pub struct RelaysClient {
-    relays: Box<[Url]>,
-    http_client: Client,
+    list: Arc<RwLock<RelaysList>>,
+    transport: Arc<dyn RelayTransport>,
     timeout: Duration,
     pub(crate) inflight_publish: InflightPublishRequests,
+    observer: Option<Arc<dyn ClientObserver>>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+#[derive(Clone)]
+/// The current relays, and their health indexed in the same order.
+struct RelaysList {
+    urls: Arc<[Url]>,
+    health: RelaysHealth,
 }
 
 impl Debug for RelaysClient {
@@ -37,8 +53,7 @@ impl Debug for RelaysClient {
         debug_struct.field(
             "relays",
             &self
-                .relays
-                .as_ref()
+                .relays()
                 .iter()
                 .map(|url| url.as_str())
                 .collect::<Vec<_>>(),
@@ -49,17 +64,27 @@ impl Debug for RelaysClient {
 }
 
 impl RelaysClient {
-    pub fn new(relays: Box<[Url]>, timeout: Duration) -> Self {
-        let inflight_publish = InflightPublishRequests::new(relays.len());
+    pub fn new(
+        relays: Box<[Url]>,
+        transport: Arc<dyn RelayTransport>,
+        timeout: Duration,
+        health: HealthConfig,
+        retry_policy: Option<RetryPolicy>,
+        observer: Option<Arc<dyn ClientObserver>>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let list = RelaysList {
+            health: RelaysHealth::new(relays.len(), health, clock),
+            urls: relays.into(),
+        };
 
         Self {
-            relays,
-            http_client: Client::builder()
-                .build()
-                .expect("Client building should be infallible"),
-
+            list: Arc::new(RwLock::new(list)),
+            transport,
             timeout,
-            inflight_publish,
+            inflight_publish: InflightPublishRequests::new(),
+            observer,
+            retry_policy,
         }
     }
 
@@ -68,18 +93,80 @@ impl RelaysClient {
         signed_packet: &SignedPacket,
         cas: Option<Timestamp>,
     ) -> Result<(), PublishError> {
+        self.publish_futures(signed_packet, cas)?
+            .filter_map(|(_, done)| match done {
+                Ok(true) => Some(Ok(())),
+                Ok(false) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .next()
+            .await
+            .expect("relays inflight publish requests done with no success or error!")
+    }
+
+    /// Same as [Self::publish], but waits for all relays to respond,
+    /// and returns the response of each relay in the configured order.
+    pub async fn publish_with_report(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+    ) -> (Result<(), PublishError>, Vec<RelayPublishReport>) {
+        let mut futures = match self.publish_futures(signed_packet, cas) {
+            Ok(futures) => futures,
+            Err(error) => return (Err(error), vec![]),
+        };
+
+        let mut result = None;
+        let mut reports = vec![];
+
+        while let Some((report, done)) = futures.next().await {
+            if result.is_none() {
+                result = match done {
+                    Ok(true) => Some(Ok(())),
+                    Ok(false) => None,
+                    Err(err) => Some(Err(err)),
+                };
+            }
+
+            reports.push(report);
+        }
+
+        reports.sort_by_key(|(index, _)| *index);
+
+        (
+            result.expect("relays inflight publish requests done with no success or error!"),
+            reports.into_iter().map(|(_, report)| report).collect(),
+        )
+    }
+
+    /// Returns a future for each relay, resolving to its response (and its index),
+    /// and whether the inflight publish request is done.
+    #[allow(clippy::type_complexity)]
+    fn publish_futures(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+    ) -> Result<
+        FuturesUnorderedBounded<
+            impl futures_lite::Future<
+                Output = ((usize, RelayPublishReport), Result<bool, PublishError>),
+            >,
+        >,
+        PublishError,
+    > {
         let public_key = signed_packet.public_key();
+        let list = self.list();
 
         self.inflight_publish
-            .start_request(&public_key, signed_packet, cas)?;
+            .start_request(&public_key, signed_packet, cas, list.urls.len())?;
 
-        let mut futures = futures_buffered::FuturesUnorderedBounded::new(self.relays.len());
+        let mut futures = FuturesUnorderedBounded::new(list.urls.len());
 
         let body = signed_packet.to_relay_payload();
         let cas = cas.map(|timestamp| timestamp.as_u64().to_string());
 
-        for relay in &self.relays {
-            let http_client = self.http_client.clone();
+        for (index, relay) in list.urls.iter().enumerate() {
+            let transport = self.transport.clone();
             let timeout = self.timeout;
 
             let cas = cas.clone();
@@ -89,28 +176,101 @@ impl RelaysClient {
             let relay = relay.clone();
 
             let mut inflight = self.inflight_publish.clone();
+            let observer = self.observer.clone();
+            let health = list.health.clone();
+            let retry_policy = self.retry_policy;
 
             futures.push(async move {
-                let result = publish_to_relay(http_client, relay, &public_key, body, cas, timeout)
-                    .await
-                    .map_err(map_reqwest_error);
+                let started_at = Timestamp::now();
+
+                let result = publish_to_relay(
+                    transport.as_ref(),
+                    relay.clone(),
+                    &public_key,
+                    body,
+                    cas,
+                    timeout,
+                    retry_policy.as_ref(),
+                )
+                .await;
+
+                let status = match &result {
+                    Ok(status) => Some(*status),
+                    Err(error) => error.status(),
+                };
+                let latency = elapsed_since(started_at);
+
+                if status.is_some_and(is_healthy_publish_status) {
+                    health.record_success(index, latency);
+                } else {
+                    health.record_failure(index);
+                }
 
-                inflight.add_result(&public_key, result)
+                let status = status.map(|status| status.as_u16());
+
+                notify(observer.as_ref(), || ClientEvent::RelayResponse {
+                    url: relay.clone(),
+                    public_key: public_key.clone(),
+                    request: RelayRequest::Publish,
+                    status,
+                    latency,
+                });
+
+                let result = result.map(|_| ()).map_err(map_relay_error);
+
+                let report = RelayPublishReport {
+                    url: relay,
+                    status,
+                    latency,
+                    result: result.clone(),
+                };
+
+                ((index, report), inflight.add_result(&public_key, result))
             });
         }
 
-        futures
-            .filter_map(|result| match result {
-                Ok(true) => Some(Ok(())),
-                Ok(false) => None,
-                Err(err) => Some(Err(err)),
-            })
-            .next()
-            .await
-            .expect("relays inflight publish requests done with no success or error!")
+        Ok(futures)
     }
 
-    #[cfg(not(wasm_browser))]
+    /// Returns a clone of this client with a different timeout for each request,
+    /// sharing the same inflight publish requests.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout,
+            ..self.clone()
+        }
+    }
+
+    fn list(&self) -> RelaysList {
+        self.list.read().expect("RelaysClient::list lock").clone()
+    }
+
+    /// Returns the current relays.
+    pub fn relays(&self) -> Arc<[Url]> {
+        self.list().urls
+    }
+
+    #[cfg(dht)]
+    /// Replace the current relays, keeping the health of relays that are still in the list.
+    pub fn set_relays(&self, relays: Vec<Url>) {
+        let mut list = self.list.write().expect("RelaysClient::list lock");
+
+        let health = list.health.retain(&list.urls, &relays);
+
+        *list = RelaysList {
+            urls: relays.into(),
+            health,
+        };
+    }
+
+    /// Returns the health of each relay.
+    pub fn health(&self) -> Vec<RelayHealth> {
+        let list = self.list();
+
+        list.health.report(&list.urls)
+    }
+
+    #[cfg(dht)]
     /// Cancel an inflight publish request.
     pub fn cancel_publish(&self, public_key: &PublicKey) {
         self.inflight_publish.cancel_request(public_key);
@@ -121,7 +281,7 @@ impl RelaysClient {
         &self,
         public_key: &PublicKey,
         more_recent_than: Option<Timestamp>,
-    ) -> Pin<Box<dyn Stream<Item = SignedPacket> + Send>> {
+    ) -> Pin<Box<dyn Stream<Item = Resolved> + Send>> {
         Box::pin(
             self.resolve_futures(public_key, more_recent_than)
                 .filter_map(|opt| opt),
@@ -132,48 +292,154 @@ impl RelaysClient {
         &self,
         public_key: &PublicKey,
         more_recent_than: Option<Timestamp>,
-    ) -> FuturesUnorderedBounded<impl futures_lite::Future<Output = Option<SignedPacket>>> {
-        let mut futures = FuturesUnorderedBounded::new(self.relays.len());
+    ) -> FuturesUnorderedBounded<impl futures_lite::Future<Output = Option<Resolved>>> {
+        let list = self.list();
+        let plan = list.health.plan();
+
+        self.resolve_planned(list, plan, public_key, more_recent_than)
+    }
+
+    /// Same as [Self::resolve_futures], but queries every relay at once,
+    /// skipping neither relays with an open circuit breaker, nor hedged relays.
+    pub fn resolve_every_futures(
+        &self,
+        public_key: &PublicKey,
+    ) -> FuturesUnorderedBounded<impl futures_lite::Future<Output = Option<Resolved>>> {
+        let list = self.list();
+        let plan = list.health.plan_every();
+
+        self.resolve_planned(list, plan, public_key, None)
+    }
+
+    fn resolve_planned(
+        &self,
+        list: RelaysList,
+        plan: Plan,
+        public_key: &PublicKey,
+        more_recent_than: Option<Timestamp>,
+    ) -> FuturesUnorderedBounded<impl futures_lite::Future<Output = Option<Resolved>>> {
+        let mut futures = FuturesUnorderedBounded::new(list.urls.len());
 
         let if_modified_since = more_recent_than.map(|t| t.format_http_date());
+        let started_at = Timestamp::now();
 
-        self.relays.iter().for_each(|relay| {
-            let http_client = self.http_client.clone();
-            let relay = relay.clone();
+        // Set once any of the first relays responds, so hedged requests can be skipped.
+        let answered = Arc::new(AtomicBool::new(false));
+
+        let first = plan.first.into_iter().map(|index| (index, None));
+        let hedged = plan
+            .hedged
+            .into_iter()
+            .map(|index| (index, Some(plan.delay)));
+
+        first.chain(hedged).for_each(|(index, delay)| {
+            let transport = self.transport.clone();
+            let relay = list.urls[index].clone();
             let public_key = public_key.clone();
             let if_modified_since = if_modified_since.clone();
             let timeout = self.timeout;
+            let observer = self.observer.clone();
+            let health = list.health.clone();
+            let answered = answered.clone();
 
-            futures.push(resolve_from_relay(
-                http_client,
-                relay,
-                public_key,
-                if_modified_since,
-                timeout,
-            ));
+            futures.push(async move {
+                if let Some(delay) = delay {
+                    sleep(delay).await;
+
+                    if answered.load(Ordering::Relaxed) {
+                        return None;
+                    }
+                }
+
+                let diagnostic = fetch_from_relay(
+                    transport.as_ref(),
+                    relay.clone(),
+                    &public_key,
+                    if_modified_since,
+                    timeout,
+                )
+                .await;
+
+                notify(observer.as_ref(), || ClientEvent::RelayResponse {
+                    url: relay.clone(),
+                    public_key: public_key.clone(),
+                    request: RelayRequest::Resolve,
+                    status: diagnostic.status,
+                    latency: diagnostic.latency,
+                });
+
+                match diagnostic.outcome {
+                    RelayOutcome::Error(_) | RelayOutcome::Rejected(_) => {
+                        health.record_failure(index);
+                    }
+                    _ => {
+                        health.record_success(index, diagnostic.latency);
+
+                        if delay.is_none() {
+                            answered.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+
+                match diagnostic.outcome {
+                    RelayOutcome::Found(packet) => Some(Resolved::new(
+                        packet,
+                        Source::Relay { url: relay },
+                        started_at,
+                    )),
+                    RelayOutcome::Rejected(error) => {
+                        notify(observer.as_ref(), || ClientEvent::PacketRejected {
+                            public_key,
+                            source: Source::Relay { url: relay },
+                            error,
+                        });
+
+                        None
+                    }
+                    _ => None,
+                }
+            });
         });
 
         futures
     }
+
+    /// Query every relay for the given [PublicKey] without `If-Modified-Since`,
+    /// and return the detailed response of each one.
+    pub async fn diagnose(&self, public_key: &PublicKey) -> Vec<RelayDiagnostic> {
+        let relays = self.relays();
+        let mut futures = FuturesUnorderedBounded::new(relays.len());
+
+        for relay in relays.iter() {
+            futures.push(fetch_from_relay(
+                self.transport.as_ref(),
+                relay.clone(),
+                public_key,
+                None,
+                self.timeout,
+            ));
+        }
+
+        futures.collect().await
+    }
 }
 
 #[derive(Debug)]
 struct InflightPublishRequest {
     signed_packet: SignedPacket,
+    relays_count: usize,
     success_count: usize,
     errors: HashMap<PublishError, usize>,
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct InflightPublishRequests {
-    relays_count: usize,
     requests: Arc<Mutex<HashMap<PublicKey, InflightPublishRequest>>>,
 }
 
 impl InflightPublishRequests {
-    fn new(relays_count: usize) -> Self {
+    fn new() -> Self {
         Self {
-            relays_count,
             requests: Arc::new(Mutex::new(HashMap::new())),
         }
     }
@@ -183,6 +449,7 @@ impl InflightPublishRequests {
         public_key: &PublicKey,
         signed_packet: &SignedPacket,
         cas: Option<Timestamp>,
+        relays_count: usize,
     ) -> Result<(), PublishError> {
         let mut requests = self.requests.lock().expect("InflightPublishRequests lock");
 
@@ -203,6 +470,7 @@ impl InflightPublishRequests {
                 public_key.clone(),
                 InflightPublishRequest {
                     signed_packet: signed_packet.clone(),
+                    relays_count,
                     success_count: 0,
                     errors: Default::default(),
                 },
@@ -212,7 +480,7 @@ impl InflightPublishRequests {
         Ok(())
     }
 
-    #[cfg(not(wasm_browser))]
+    #[cfg(dht)]
     pub fn cancel_request(&self, public_key: &PublicKey) {
         let mut inflight = self.requests.lock().expect("InflightPublishRequests lock");
 
@@ -235,7 +503,7 @@ impl InflightPublishRequests {
         let mut inflight = self.requests.lock().expect("InflightPublishRequests lock");
 
         if let Some(request) = inflight.get_mut(public_key) {
-            let majority = self.relays_count / 2 + self.relays_count % 2;
+            let majority = request.relays_count / 2 + request.relays_count % 2;
 
             request.success_count += 1;
 
@@ -260,7 +528,7 @@ impl InflightPublishRequests {
         let mut inflight = self.requests.lock().expect("InflightPublishRequests lock");
 
         if let Some(request) = inflight.get_mut(public_key) {
-            let majority = self.relays_count / 2 + self.relays_count % 2;
+            let majority = request.relays_count / 2 + request.relays_count % 2;
 
             // Add error, and return early error if necessary.
             {
@@ -308,43 +576,149 @@ impl InflightPublishRequests {
 
     fn done(&self, request: &InflightPublishRequest) -> bool {
         let total_errors: usize = request.errors.values().sum();
-        (total_errors + request.success_count) >= self.relays_count
+        (total_errors + request.success_count) >= request.relays_count
+    }
+}
+
+/// A failed request to a relay, either with an error status code, or without a response.
+#[derive(Debug)]
+pub enum RelayError {
+    Status(StatusCode),
+    Transport(RelayTransportError),
+}
+
+impl RelayError {
+    /// Returns the status code of the relay's response, if any.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            RelayError::Status(status) => Some(*status),
+            RelayError::Transport(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RelayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RelayError::Status(status) => write!(f, "Error status code {status}"),
+            RelayError::Transport(error) => write!(f, "{error}"),
+        }
     }
 }
 
+/// Publish a [SignedPacket] to a relay, retrying transient failures according to the [RetryPolicy].
 pub async fn publish_to_relay(
-    http_client: reqwest::Client,
+    transport: &dyn RelayTransport,
     relay: Url,
     public_key: &PublicKey,
     body: Bytes,
     cas: Option<String>,
     timeout: Duration,
-) -> Result<(), reqwest::Error> {
-    let url = format_url(&relay, public_key);
+    retry_policy: Option<&RetryPolicy>,
+) -> Result<StatusCode, RelayError> {
+    let mut attempt = 0;
+    // Whether a previous attempt may have been stored by the relay, despite failing.
+    let mut ambiguous = false;
+
+    loop {
+        attempt += 1;
+
+        let (result, retry_after) = publish_to_relay_once(
+            transport,
+            &relay,
+            public_key,
+            body.clone(),
+            cas.clone(),
+            timeout,
+        )
+        .await;
+
+        let Err(error) = &result else {
+            return result;
+        };
+
+        // If a previous attempt landed, retrying with the same `If-Match` fails,
+        // so check if the relay already has the packet that was just sent.
+        if ambiguous
+            && cas.is_some()
+            && error.status() == Some(StatusCode::PRECONDITION_FAILED)
+            && is_stored(transport, &relay, public_key, &body, timeout).await
+        {
+            cross_debug!("PUT to {relay} was stored by a previous attempt");
+
+            return Ok(StatusCode::NO_CONTENT);
+        }
+
+        ambiguous |= error.status().is_none_or(|status| status.is_server_error());
+
+        let backoff = retry_policy
+            .and_then(|policy| policy.relay_backoff(attempt, error.status(), retry_after));
+
+        match backoff {
+            Some(backoff) => {
+                cross_debug!("Retrying PUT to {relay} after {error}, in {backoff:?}");
+
+                sleep(backoff).await;
+            }
+            None => return result,
+        }
+    }
+}
+
+/// Returns true if the relay's packet for this [PublicKey] is the relay payload `body`.
+async fn is_stored(
+    transport: &dyn RelayTransport,
+    relay: &Url,
+    public_key: &PublicKey,
+    body: &Bytes,
+    timeout: Duration,
+) -> bool {
+    match fetch_from_relay_inner(transport, relay, public_key, None, timeout).await {
+        (_, RelayOutcome::Found(signed_packet)) => signed_packet.to_relay_payload() == body,
+        _ => false,
+    }
+}
 
-    let mut request = http_client.put(url.clone());
+/// Returns the result of a single publish request, and the relay's `Retry-After` if any.
+async fn publish_to_relay_once(
+    transport: &dyn RelayTransport,
+    relay: &Url,
+    public_key: &PublicKey,
+    body: Bytes,
+    cas: Option<String>,
+    timeout: Duration,
+) -> (Result<StatusCode, RelayError>, Option<Duration>) {
+    let url = format_url(relay, public_key);
 
-    request = request
-        // Publish combines the http latency with the PUT query to the dht
-        // on the relay side, so we should be as generous as possible
-        .timeout(timeout * 3);
+    let mut headers = HeaderMap::new();
 
     if let Some(cas) = cas {
-        request = request.header(header::IF_MATCH, cas);
+        headers.insert(
+            header::IF_MATCH,
+            HeaderValue::from_str(&cas).expect("timestamp to be valid header value"),
+        );
     }
 
-    let response = request.body(body).send().await.inspect_err(|error| {
-        cross_debug!("PUT {:?}", error);
-    })?;
+    // Publish combines the http latency with the PUT query to the dht
+    // on the relay side, so we should be as generous as possible
+    let response = match transport.put(url.clone(), headers, body, timeout * 3).await {
+        Ok(response) => response,
+        Err(error) => {
+            cross_debug!("PUT {:?}", error);
 
-    let status = response.status();
+            return (Err(RelayError::Transport(error)), None);
+        }
+    };
 
-    if let Err(error) = response.error_for_status_ref() {
-        let text = response.text().await.unwrap_or("".to_string());
+    let status = response.status;
+
+    if is_error_status(status) {
+        let retry_after = retry_after(&response.headers);
+        let text = String::from_utf8_lossy(&response.body);
 
         cross_debug!("Got error response for PUT {url} {status} {text}");
 
-        return Err(error);
+        return (Err(RelayError::Status(status)), retry_after);
     };
 
     if status.is_success() {
@@ -353,34 +727,46 @@ pub async fn publish_to_relay(
         cross_debug!("Got neither 2xx nor >=400 status code {status} for PUT {url}",);
     }
 
-    Ok(())
+    (Ok(status), None)
 }
 
-fn map_reqwest_error(error: reqwest::Error) -> PublishError {
-    if error.is_timeout() {
-        PublishError::Query(QueryError::Timeout)
-    } else if error.is_status() {
-        match error
-            .status()
-            .expect("previously verified that it is a status error")
-        {
-            StatusCode::BAD_REQUEST => {
-                // This should be very unlikely unless relays are misbehaving, still worth
-                // returning to the user to know that relays are misbehaving, and not just a
-                // network issue.
-                PublishError::Query(QueryError::BadRequest)
-            }
-            StatusCode::CONFLICT => PublishError::Concurrency(ConcurrencyError::NotMostRecent),
-            StatusCode::PRECONDITION_FAILED => {
-                PublishError::Concurrency(ConcurrencyError::CasFailed)
-            }
-            StatusCode::PRECONDITION_REQUIRED => {
-                PublishError::Concurrency(ConcurrencyError::ConflictRisk)
-            }
-            _ => PublishError::UnexpectedResponses,
+fn is_error_status(status: StatusCode) -> bool {
+    status.is_client_error() || status.is_server_error()
+}
+
+/// Whether a relay responding to a publish request with this status is healthy:
+/// a success, or a concurrency conflict caused by the publisher, not the relay.
+fn is_healthy_publish_status(status: StatusCode) -> bool {
+    status.is_success()
+        || matches!(
+            status,
+            StatusCode::CONFLICT
+                | StatusCode::PRECONDITION_FAILED
+                | StatusCode::PRECONDITION_REQUIRED
+        )
+}
+
+fn map_relay_error(error: RelayError) -> PublishError {
+    match error {
+        RelayError::Transport(RelayTransportError::Timeout) => {
+            PublishError::Query(QueryError::Timeout)
         }
-    } else {
-        PublishError::UnexpectedResponses
+        RelayError::Status(StatusCode::BAD_REQUEST) => {
+            // This should be very unlikely unless relays are misbehaving, still worth
+            // returning to the user to know that relays are misbehaving, and not just a
+            // network issue.
+            PublishError::Query(QueryError::BadRequest)
+        }
+        RelayError::Status(StatusCode::CONFLICT) => {
+            PublishError::Concurrency(ConcurrencyError::NotMostRecent)
+        }
+        RelayError::Status(StatusCode::PRECONDITION_FAILED) => {
+            PublishError::Concurrency(ConcurrencyError::CasFailed)
+        }
+        RelayError::Status(StatusCode::PRECONDITION_REQUIRED) => {
+            PublishError::Concurrency(ConcurrencyError::ConflictRisk)
+        }
+        _ => PublishError::UnexpectedResponses,
     }
 }
 
@@ -398,54 +784,74 @@ pub fn format_url(relay: &Url, public_key: &PublicKey) -> Url {
     url
 }
 
-pub async fn resolve_from_relay(
-    http_client: reqwest::Client,
+/// Resolve a [SignedPacket] from a relay, and return the status, latency and outcome of the request.
+pub async fn fetch_from_relay(
+    transport: &dyn RelayTransport,
     relay: Url,
-    public_key: PublicKey,
+    public_key: &PublicKey,
     if_modified_since: Option<String>,
     timeout: Duration,
-) -> Option<SignedPacket> {
-    let url = format_url(&relay, &public_key);
+) -> RelayDiagnostic {
+    let started_at = Timestamp::now();
 
-    let mut request = reqwest::Request::new(Method::GET, url.clone());
+    let (status, outcome) =
+        fetch_from_relay_inner(transport, &relay, public_key, if_modified_since, timeout).await;
 
-    *request.timeout_mut() = Some(timeout);
+    RelayDiagnostic {
+        url: relay,
+        status: status.map(|status| status.as_u16()),
+        latency: elapsed_since(started_at),
+        outcome,
+    }
+}
+
+async fn fetch_from_relay_inner(
+    transport: &dyn RelayTransport,
+    relay: &Url,
+    public_key: &PublicKey,
+    if_modified_since: Option<String>,
+    timeout: Duration,
+) -> (Option<StatusCode>, RelayOutcome) {
+    let url = format_url(relay, public_key);
+
+    let mut headers = HeaderMap::new();
 
     if let Some(ref httpdate) = if_modified_since {
-        request.headers_mut().insert(
+        headers.insert(
             header::IF_MODIFIED_SINCE,
             HeaderValue::from_str(httpdate).expect("httpdate to be valid header value"),
         );
     }
 
     let response = loop {
-        let response = match http_client
-            .execute(request.try_clone().expect("infallible"))
-            .await
-        {
+        let response = match transport.get(url.clone(), headers.clone(), timeout).await {
             Ok(response) => response,
             Err(error) => {
                 cross_debug!("GET {:?}", error);
 
-                return None;
+                return (None, RelayOutcome::Error(error.to_string()));
             }
         };
 
-        let status = response.status();
+        let status = response.status;
 
-        if response.error_for_status_ref().is_err() {
-            let text = response.text().await.unwrap_or("".to_string());
+        if is_error_status(status) {
+            let text = String::from_utf8_lossy(&response.body).to_string();
 
             cross_debug!("Got error response for GET {url} {status} {text}");
 
-            return None;
+            return if status == StatusCode::NOT_FOUND {
+                (Some(status), RelayOutcome::NotFound)
+            } else {
+                (Some(status), RelayOutcome::Error(text))
+            };
         };
 
         if should_retry_with_cache_disabled(
-            &mut request,
+            &mut headers,
             &if_modified_since,
             &status,
-            response.content_length(),
+            response.body.len(),
         ) {
             continue;
         } else {
@@ -453,56 +859,57 @@ pub async fn resolve_from_relay(
         }
     };
 
-    if response.content_length().unwrap_or_default() > SignedPacket::MAX_BYTES {
-        cross_debug!("Response too large for GET {url}");
+    let status = response.status;
 
-        return None;
+    if status == StatusCode::NOT_MODIFIED {
+        return (Some(status), RelayOutcome::NotModified);
     }
 
-    let payload = match response.bytes().await {
-        Ok(payload) => payload,
-        Err(error) => {
-            cross_debug!("Failed to read relay response from GET {url} {error}");
+    if response.body.len() as u64 > SignedPacket::MAX_BYTES {
+        cross_debug!("Response too large for GET {url}");
 
-            return None;
-        }
-    };
+        return (
+            Some(status),
+            RelayOutcome::Error("Response too large".to_string()),
+        );
+    }
 
-    match SignedPacket::from_relay_payload(&public_key, &payload) {
-        Ok(signed_packet) => Some(signed_packet),
+    match SignedPacket::from_relay_payload(public_key, &response.body) {
+        Ok(signed_packet) => (Some(status), RelayOutcome::Found(signed_packet)),
         Err(error) => {
             cross_debug!("Invalid signed_packet {url}:{error}");
 
-            None
+            (Some(status), RelayOutcome::Rejected(error))
         }
     }
 }
 
 fn should_retry_with_cache_disabled(
-    request: &mut reqwest::Request,
+    headers: &mut HeaderMap,
     if_modified_since: &Option<String>,
     status: &StatusCode,
-    content_length: Option<u64>,
+    content_length: usize,
 ) -> bool {
     // We got a 304 not modified, even though we don't have any packet in cache.
     let unexpected_not_modified =
         (*status == StatusCode::NOT_MODIFIED) && if_modified_since.is_none();
 
     // We got a success response, but the packet is empty.
-    let broken_cache = status.is_success() && (content_length.unwrap_or_default() == 0);
+    let broken_cache = status.is_success() && (content_length == 0);
 
     let needs_retry_with_cache_disabled = unexpected_not_modified || broken_cache;
 
-    let havent_retried_with_cache_disabled_already =
-        request.headers().get(header::CACHE_CONTROL).is_none();
+    let havent_retried_with_cache_disabled_already = headers.get(header::CACHE_CONTROL).is_none();
 
     if needs_retry_with_cache_disabled && havent_retried_with_cache_disabled_already {
-        request.headers_mut().insert(
+        headers.insert(
             header::CACHE_CONTROL,
             "no-cache, no-store, must-revalidate"
                 .try_into()
                 .expect("cache control is valid http header value"),
         );
+        // Older relays respond to a modified `If-Modified-Since` with an empty body.
+        headers.remove(header::IF_MODIFIED_SINCE);
 
         return true;
     }
@@ -0,0 +1,284 @@
+//! Detailed [PublishReport] of publishing a [SignedPacket], see [Client::publish_with_report].
+
+#[cfg(all(dht, relays))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[cfg(dht)]
+use futures_lite::StreamExt;
+use ntimestamp::Timestamp;
+#[cfg(relays)]
+use url::Url;
+
+#[cfg(dht)]
+use super::elapsed_since;
+#[cfg(all(dht, relays))]
+use super::futures::combine_results;
+#[cfg(dht)]
+use super::retry::{put_mutable, RetryPolicy};
+use super::{
+    async_compat_if_necessary, CachePolicy, Client, ClientEvent, PublishError, QueryError,
+    RequestOptions,
+};
+use crate::{PublicKey, SignedPacket};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A report of publishing a [SignedPacket] to each network, see [Client::publish_with_report].
+pub struct PublishReport {
+    /// The published packet's [PublicKey].
+    pub public_key: PublicKey,
+    /// The same result [Client::publish] would have returned.
+    pub result: Result<(), PublishError>,
+    #[cfg(dht)]
+    /// The result of publishing to the [mainline] Dht, or `None` if the Dht wasn't used.
+    pub dht: Option<DhtPublishReport>,
+    #[cfg(relays)]
+    /// The response of each [Relay](https://pkarr.org/relays), in the configured order,
+    /// empty if relays weren't used.
+    pub relays: Vec<RelayPublishReport>,
+    /// Errors from one network, that were ignored in [Self::result] because of the other network.
+    ///
+    /// For example, a `CasFailed` error from the Dht after the relays already succeeded.
+    pub swallowed_errors: Vec<PublishError>,
+}
+
+impl PublishReport {
+    #[cfg(relays)]
+    /// Returns the number of relays that accepted the packet.
+    pub fn relays_accepted(&self) -> usize {
+        self.relays
+            .iter()
+            .filter(|relay| relay.result.is_ok())
+            .count()
+    }
+}
+
+#[cfg(dht)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The result of publishing a [SignedPacket] to the [mainline] Dht.
+pub struct DhtPublishReport {
+    /// The result of the `PUT` query.
+    pub result: Result<(), PublishError>,
+    /// Time it took for the `PUT` query to complete.
+    pub latency: Duration,
+    /// Number of nodes that returned the published packet, in a follow-up `GET` query
+    /// after a successful `PUT` query, `0` otherwise.
+    pub stored_at: usize,
+}
+
+#[cfg(relays)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The response of a single [Relay](https://pkarr.org/relays) to a publish request.
+pub struct RelayPublishReport {
+    /// The base url of the relay.
+    pub url: Url,
+    /// HTTP status code, or `None` if no response was received.
+    pub status: Option<u16>,
+    /// Time it took to receive the response (or fail).
+    pub latency: Duration,
+    /// Whether the relay accepted the packet.
+    pub result: Result<(), PublishError>,
+}
+
+impl Client {
+    /// Same as [Self::publish], but waits for every network and relay to respond,
+    /// and returns a [PublishReport] of each response, instead of the first decisive result.
+    ///
+    /// Useful for telling users how far their update reached,
+    /// for example: 2 of 3 relays, and 20 Dht nodes.
+    ///
+    /// Since it waits for all relays, and verifies how many Dht nodes stored the packet,
+    /// this method takes longer than [Self::publish].
+    pub async fn publish_with_report(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+    ) -> PublishReport {
+        async_compat_if_necessary(self.publish_with_report_inner(
+            signed_packet,
+            cas,
+            &RequestOptions::default(),
+        ))
+        .await
+    }
+
+    async fn publish_with_report_inner(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        options: &RequestOptions,
+    ) -> PublishReport {
+        let mut report = PublishReport {
+            public_key: signed_packet.public_key(),
+            result: Ok(()),
+            #[cfg(dht)]
+            dht: None,
+            #[cfg(relays)]
+            relays: vec![],
+            swallowed_errors: vec![],
+        };
+
+        report.result = match self.check_and_cache(signed_packet, cas, options).await {
+            _ if self.is_shutdown() => Err(QueryError::Cancelled.into()),
+            Err(error) => Err(error),
+            Ok(()) if options.cache == CachePolicy::Only => Ok(()),
+            Ok(()) => {
+                self.publish_networks_with_report(signed_packet, cas, options, &mut report)
+                    .await
+            }
+        };
+
+        self.notify(|| ClientEvent::Published {
+            public_key: signed_packet.public_key(),
+            result: report.result.clone(),
+        });
+
+        report
+    }
+
+    async fn publish_networks_with_report(
+        &self,
+        signed_packet: &SignedPacket,
+        cas: Option<Timestamp>,
+        options: &RequestOptions,
+        report: &mut PublishReport,
+    ) -> Result<(), PublishError> {
+        // The order in which networks complete, to apply the same logic as `publish_both_networks`.
+        #[cfg(all(dht, relays))]
+        let order = &AtomicUsize::new(0);
+
+        #[cfg(dht)]
+        let dht_future = self
+            .dht()
+            .filter(|_| options.use_dht())
+            .map(|node| async move {
+                let dht_report =
+                    publish_to_dht(node, signed_packet, cas, self.0.retry_policy.as_ref()).await;
+
+                #[cfg(relays)]
+                let dht_report = (order.fetch_add(1, Ordering::Relaxed), dht_report);
+
+                dht_report
+            });
+
+        #[cfg(relays)]
+        let relays_future = self.relays_for(options).map(|relays| async move {
+            let relays_report = relays.publish_with_report(signed_packet, cas).await;
+
+            #[cfg(dht)]
+            let relays_report = (order.fetch_add(1, Ordering::Relaxed), relays_report);
+
+            relays_report
+        });
+
+        #[cfg(all(dht, not(relays)))]
+        return match dht_future {
+            Some(dht_future) => {
+                let dht = dht_future.await;
+                let result = dht.result.clone();
+
+                report.dht = Some(dht);
+
+                result
+            }
+            None => Err(QueryError::NoNetwork.into()),
+        };
+
+        #[cfg(all(relays, not(dht)))]
+        return match relays_future {
+            Some(relays_future) => {
+                let (result, relays) = relays_future.await;
+
+                report.relays = relays;
+
+                result
+            }
+            None => Err(QueryError::NoNetwork.into()),
+        };
+
+        #[cfg(all(dht, relays))]
+        return match (dht_future, relays_future) {
+            (Some(dht_future), Some(relays_future)) => {
+                let ((dht_order, dht), (_, (relays_result, relays))) =
+                    futures_lite::future::zip(dht_future, relays_future).await;
+
+                self.0
+                    .relays
+                    .as_ref()
+                    .expect("infallible")
+                    .cancel_publish(&signed_packet.public_key());
+
+                let result = if dht_order == 0 {
+                    combine_results(dht.result.clone(), relays_result.clone())
+                } else {
+                    combine_results(relays_result.clone(), dht.result.clone())
+                };
+
+                report.swallowed_errors = [dht.result.clone(), relays_result]
+                    .into_iter()
+                    .filter_map(Result::err)
+                    .filter(|error| result.as_ref().err() != Some(error))
+                    .collect();
+                report.dht = Some(dht);
+                report.relays = relays;
+
+                result
+            }
+            (Some(dht_future), None) => {
+                let (_, dht) = dht_future.await;
+                let result = dht.result.clone();
+
+                report.dht = Some(dht);
+
+                result
+            }
+            (None, Some(relays_future)) => {
+                let (_, (result, relays)) = relays_future.await;
+
+                report.relays = relays;
+
+                result
+            }
+            (None, None) => Err(QueryError::NoNetwork.into()),
+        };
+    }
+}
+
+#[cfg(dht)]
+/// Put the packet to the Dht, then count the nodes that return it.
+async fn publish_to_dht(
+    node: mainline::Dht,
+    signed_packet: &SignedPacket,
+    cas: Option<Timestamp>,
+    retry_policy: Option<&RetryPolicy>,
+) -> DhtPublishReport {
+    let node = node.as_async();
+    let started_at = Timestamp::now();
+
+    let result = put_mutable(
+        &node,
+        signed_packet.into(),
+        cas.map(|t| t.as_u64() as i64),
+        retry_policy,
+    )
+    .await;
+
+    let latency = elapsed_since(started_at);
+
+    let stored_at = if result.is_ok() {
+        let seq = signed_packet.timestamp().as_u64() as i64;
+
+        node.get_mutable(signed_packet.public_key().as_bytes(), None, None)
+            .filter(|item| item.seq() == seq)
+            .count()
+            .await
+    } else {
+        0
+    };
+
+    DhtPublishReport {
+        result,
+        latency,
+        stored_at,
+    }
+}
@@ -0,0 +1,516 @@
+//! Background [Republisher] that keeps [SignedPacket]s alive on the network.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use ntimestamp::Timestamp;
+
+use crate::{PublicKey, SignedPacket};
+
+use super::{Client, PublishError};
+
+/// Default interval between republishing the same [SignedPacket]: 1 hour.
+pub const DEFAULT_REPUBLISH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Default maximum random delay added to each scheduled republish: 5 minutes.
+pub const DEFAULT_REPUBLISH_JITTER: Duration = Duration::from_secs(5 * 60);
+/// Default delay before retrying a failed republish: 30 seconds.
+pub const DEFAULT_REPUBLISH_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often the background thread checks if the client was shut down while waiting.
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Client {
+    /// Returns a [RepublisherBuilder] for a [Republisher] that uses this client.
+    pub fn republisher(&self) -> RepublisherBuilder {
+        RepublisherBuilder {
+            client: self.clone(),
+            interval: DEFAULT_REPUBLISH_INTERVAL,
+            jitter: DEFAULT_REPUBLISH_JITTER,
+            retry_backoff: DEFAULT_REPUBLISH_RETRY_BACKOFF,
+            storage: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A builder for [Republisher], see [Client::republisher].
+pub struct RepublisherBuilder {
+    client: Client,
+    interval: Duration,
+    jitter: Duration,
+    retry_backoff: Duration,
+    storage: Option<PathBuf>,
+}
+
+impl RepublisherBuilder {
+    /// Set the interval between republishing the same [SignedPacket].
+    ///
+    /// Defaults to [DEFAULT_REPUBLISH_INTERVAL].
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+
+        self
+    }
+
+    /// Set the maximum random delay added to each scheduled republish,
+    /// to avoid republishing all packets at the same time.
+    ///
+    /// Defaults to [DEFAULT_REPUBLISH_JITTER].
+    pub fn jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.jitter = jitter;
+
+        self
+    }
+
+    /// Set the delay before retrying a failed republish, doubled after each consecutive
+    /// failure, up to the [Self::interval].
+    ///
+    /// Defaults to [DEFAULT_REPUBLISH_RETRY_BACKOFF].
+    pub fn retry_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.retry_backoff = backoff;
+
+        self
+    }
+
+    /// Persist the set of [SignedPacket]s to a file at this path,
+    /// and load any packets already stored there on [Self::build].
+    pub fn storage(&mut self, path: &Path) -> &mut Self {
+        self.storage = Some(path.to_path_buf());
+
+        self
+    }
+
+    /// Load the persisted set of packets (if any) and start
+    /// republishing in a background thread.
+    pub fn build(&self) -> std::io::Result<Republisher> {
+        let packets = match &self.storage {
+            Some(path) => load(path)?,
+            None => vec![],
+        };
+
+        let now = Timestamp::now();
+
+        let entries = packets
+            .into_iter()
+            .map(|packet| {
+                (
+                    packet.public_key(),
+                    Entry::new(packet, now + random_delay(self.jitter)),
+                )
+            })
+            .collect();
+
+        let shared = Arc::new(Shared {
+            interval: self.interval,
+            jitter: self.jitter,
+            retry_backoff: self.retry_backoff,
+            storage: self.storage.clone(),
+            persist_lock: Mutex::new(()),
+            entries: RwLock::new(entries),
+        });
+
+        let (sender, receiver) = mpsc::channel();
+
+        let client = self.client.clone();
+        let thread_shared = shared.clone();
+
+        std::thread::Builder::new()
+            .name("pkarr-republisher".to_string())
+            .spawn(move || run(client, thread_shared, receiver))?;
+
+        Ok(Republisher { shared, sender })
+    }
+}
+
+#[derive(Debug)]
+/// Republishes a set of [SignedPacket]s on a schedule, in a background thread,
+/// to keep them alive on the [mainline] Dht (which drops records after a few hours)
+/// and the [Relays](https://pkarr.org/relays).
+///
+/// The packets may be signed by any key, not just keys you own.
+///
+/// Before republishing a packet, it resolves the most recent packet for the same key,
+/// and if that is more recent, it replaces the held packet and republishes that instead,
+/// so it never overwrites newer packets.
+///
+/// The background thread stops once the [Republisher] is dropped.
+pub struct Republisher {
+    shared: Arc<Shared>,
+    sender: Sender<()>,
+}
+
+impl Republisher {
+    /// Add a [SignedPacket] to be republished, unless the held packet
+    /// for the same key is more recent.
+    ///
+    /// The packet is republished as soon as possible (after a random delay up to the jitter).
+    pub fn add(&self, signed_packet: SignedPacket) -> std::io::Result<()> {
+        {
+            let mut entries = self.shared.write();
+
+            let public_key = signed_packet.public_key();
+
+            if let Some(existing) = entries.get(&public_key) {
+                if !signed_packet.more_recent_than(&existing.packet) {
+                    return Ok(());
+                }
+            }
+
+            entries.insert(
+                public_key,
+                Entry::new(
+                    signed_packet,
+                    Timestamp::now() + random_delay(self.shared.jitter),
+                ),
+            );
+        }
+
+        self.wake();
+
+        self.shared.persist()
+    }
+
+    /// Stop republishing packets for this [PublicKey], returning the held packet if any.
+    pub fn remove(&self, public_key: &PublicKey) -> std::io::Result<Option<SignedPacket>> {
+        let removed = self.shared.write().remove(public_key);
+
+        if removed.is_some() {
+            self.shared.persist()?;
+        }
+
+        Ok(removed.map(|entry| entry.packet))
+    }
+
+    /// Returns the held [SignedPacket] for this [PublicKey], which might be a more
+    /// recent packet found on the network than the one that was added.
+    pub fn get(&self, public_key: &PublicKey) -> Option<SignedPacket> {
+        self.shared
+            .read()
+            .get(public_key)
+            .map(|entry| entry.packet.clone())
+    }
+
+    /// Returns all held [SignedPacket]s.
+    pub fn packets(&self) -> Vec<SignedPacket> {
+        self.shared
+            .read()
+            .values()
+            .map(|entry| entry.packet.clone())
+            .collect()
+    }
+
+    /// Returns the number of held [SignedPacket]s.
+    pub fn len(&self) -> usize {
+        self.shared.read().len()
+    }
+
+    /// Returns true if there are no held [SignedPacket]s.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the [RepublishStatus] for this [PublicKey], if it is held.
+    pub fn status(&self, public_key: &PublicKey) -> Option<RepublishStatus> {
+        self.shared
+            .read()
+            .get(public_key)
+            .map(|entry| entry.status.clone())
+    }
+
+    /// Returns the [RepublishStatus] of every held [PublicKey].
+    pub fn statuses(&self) -> Vec<(PublicKey, RepublishStatus)> {
+        self.shared
+            .read()
+            .iter()
+            .map(|(public_key, entry)| (public_key.clone(), entry.status.clone()))
+            .collect()
+    }
+
+    /// Schedule all held packets to be republished immediately.
+    pub fn republish_now(&self) {
+        let now = Timestamp::now();
+
+        for entry in self.shared.write().values_mut() {
+            entry.status.next_attempt = now;
+        }
+
+        self.wake();
+    }
+
+    fn wake(&self) {
+        // Only fails if the thread is gone, nothing to wake then.
+        let _ = self.sender.send(());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Republishing status of a [PublicKey] held by a [Republisher].
+pub struct RepublishStatus {
+    /// Result of the last attempt.
+    pub state: RepublishState,
+    /// When was the last attempt made, if any.
+    pub last_attempt: Option<Timestamp>,
+    /// When is the next attempt scheduled.
+    pub next_attempt: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Result of the last attempt to republish a [SignedPacket], see [RepublishStatus].
+pub enum RepublishState {
+    /// Not attempted yet.
+    Pending,
+    /// The held packet was republished successfully.
+    Republished,
+    /// A more recent packet was found on the network, it replaced
+    /// the held packet and was republished successfully.
+    Superseded,
+    /// Republishing failed, it will be retried at the next attempt,
+    /// after a backoff, see [RepublisherBuilder::retry_backoff].
+    Failed(PublishError),
+}
+
+#[derive(Debug)]
+struct Entry {
+    packet: SignedPacket,
+    status: RepublishStatus,
+    /// Number of failed attempts since the last successful one.
+    failures: u32,
+}
+
+impl Entry {
+    fn new(packet: SignedPacket, next_attempt: Timestamp) -> Self {
+        Self {
+            packet,
+            status: RepublishStatus {
+                state: RepublishState::Pending,
+                last_attempt: None,
+                next_attempt,
+            },
+            failures: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    interval: Duration,
+    jitter: Duration,
+    retry_backoff: Duration,
+    storage: Option<PathBuf>,
+    /// Serializes writing to the [Self::storage], so concurrent writes can't
+    /// interleave on the temporary file or rename an older snapshot last.
+    persist_lock: Mutex<()>,
+    entries: RwLock<HashMap<PublicKey, Entry>>,
+}
+
+impl Shared {
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<PublicKey, Entry>> {
+        self.entries.read().expect("Republisher RwLock")
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<PublicKey, Entry>> {
+        self.entries.write().expect("Republisher RwLock")
+    }
+
+    /// Returns the packets due for republishing, and how long until the next one is due.
+    fn due(&self) -> (Vec<SignedPacket>, Option<Duration>) {
+        let now = Timestamp::now();
+
+        let mut due = vec![];
+        let mut next: Option<Timestamp> = None;
+
+        for entry in self.read().values() {
+            if entry.status.next_attempt <= now {
+                due.push(entry.packet.clone());
+            } else if next.is_none_or(|next| entry.status.next_attempt < next) {
+                next = Some(entry.status.next_attempt);
+            }
+        }
+
+        let wait = next.map(|next| Duration::from_micros(next.as_u64() - now.as_u64()));
+
+        (due, wait)
+    }
+
+    /// Returns the delay in microseconds before retrying after `failures` consecutive failures.
+    fn retry_delay(&self, failures: u32) -> u64 {
+        let backoff = self
+            .retry_backoff
+            .saturating_mul(2_u32.saturating_pow(failures.saturating_sub(1)))
+            .min(self.interval);
+
+        backoff.as_micros() as u64
+    }
+
+    /// Write the held packets to [Self::storage] if any.
+    fn persist(&self) -> std::io::Result<()> {
+        let path = match &self.storage {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let _guard = self.persist_lock.lock().expect("Republisher persist Mutex");
+
+        let mut bytes = vec![];
+
+        for entry in self.read().values() {
+            let serialized = entry.packet.serialize();
+
+            bytes.extend_from_slice(&(serialized.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&serialized);
+        }
+
+        // Write to a temporary file first, to avoid corrupting the storage on crashes.
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+fn load(path: &Path) -> std::io::Result<Vec<SignedPacket>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error),
+    };
+
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "corrupted storage");
+
+    let mut packets = vec![];
+    let mut remaining = bytes.as_slice();
+
+    while !remaining.is_empty() {
+        let (len, rest) = remaining.split_first_chunk::<4>().ok_or_else(invalid)?;
+        let len = u32::from_be_bytes(*len) as usize;
+
+        if rest.len() < len {
+            return Err(invalid());
+        }
+
+        let (serialized, rest) = rest.split_at(len);
+
+        packets.push(SignedPacket::deserialize(serialized).map_err(|_| invalid())?);
+
+        remaining = rest;
+    }
+
+    Ok(packets)
+}
+
+/// The background loop, sleeps until the next packet is due or
+/// until woken up, and exits once the [Republisher] is dropped.
+fn run(client: Client, shared: Arc<Shared>, receiver: mpsc::Receiver<()>) {
+    loop {
+        if client.is_shutdown() {
+            break;
+        }
+
+        let (due, wait) = shared.due();
+
+        if !due.is_empty() {
+            for packet in due {
+                republish(&client, &shared, packet);
+            }
+
+            continue;
+        }
+
+        // Wake up regularly to check if the client was shut down.
+        let wait = wait
+            .unwrap_or(SHUTDOWN_CHECK_INTERVAL)
+            .min(SHUTDOWN_CHECK_INTERVAL);
+
+        let result = receiver.recv_timeout(wait);
+
+        if let Err(RecvTimeoutError::Disconnected) = result {
+            break;
+        }
+    }
+}
+
+fn republish(client: &Client, shared: &Shared, mut packet: SignedPacket) {
+    let public_key = packet.public_key();
+
+    let most_recent = futures_lite::future::block_on(client.resolve_most_recent(&public_key));
+
+    let mut superseded = false;
+
+    if let Some(most_recent) = &most_recent {
+        if most_recent.more_recent_than(&packet) {
+            cross_debug!("Found a more recent packet for {public_key}, republishing it instead");
+
+            packet = most_recent.clone();
+            superseded = true;
+        }
+    }
+
+    let result = futures_lite::future::block_on(client.publish(
+        &packet,
+        most_recent.map(|most_recent| most_recent.timestamp()),
+    ));
+
+    let now = Timestamp::now();
+
+    {
+        let mut entries = shared.write();
+
+        let entry = match entries.get_mut(&public_key) {
+            Some(entry) => entry,
+            // Removed while republishing.
+            None => return,
+        };
+
+        if packet.more_recent_than(&entry.packet) {
+            entry.packet = packet;
+        } else if entry.packet.more_recent_than(&packet) {
+            // A more recent packet was added while republishing, republish it asap.
+            entry.status.next_attempt = now;
+            return;
+        }
+
+        entry.status.last_attempt = Some(now);
+
+        match result {
+            Ok(()) => {
+                entry.status.state = if superseded {
+                    RepublishState::Superseded
+                } else {
+                    RepublishState::Republished
+                };
+                entry.status.next_attempt =
+                    now + shared.interval.as_micros() as u64 + random_delay(shared.jitter);
+                entry.failures = 0;
+            }
+            Err(error) => {
+                cross_debug!("Failed to republish packet for {public_key}: {error}");
+
+                entry.failures += 1;
+                entry.status.state = RepublishState::Failed(error);
+                entry.status.next_attempt = now + shared.retry_delay(entry.failures);
+            }
+        }
+    }
+
+    if superseded {
+        if let Err(error) = shared.persist() {
+            cross_debug!("Failed to persist republisher storage: {error}");
+        }
+    }
+}
+
+/// Returns a random delay in microseconds between zero and `max`.
+fn random_delay(max: Duration) -> u64 {
+    let max = max.as_micros() as u64;
+
+    if max == 0 {
+        return 0;
+    }
+
+    let mut bytes = [0; 8];
+    getrandom::fill(&mut bytes).expect("getrandom failed");
+
+    u64::from_be_bytes(bytes) % max
+}
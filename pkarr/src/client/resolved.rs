@@ -0,0 +1,61 @@
+//! [Resolved] packets and their [Source]
+
+use std::time::Duration;
+
+use ntimestamp::Timestamp;
+#[cfg(relays)]
+use url::Url;
+
+use crate::SignedPacket;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A [SignedPacket] received while resolving a [crate::PublicKey], and where it came from.
+pub struct Resolved {
+    /// The received [SignedPacket].
+    pub packet: SignedPacket,
+    /// Where the [Self::packet] came from.
+    pub source: Source,
+    /// Time elapsed between starting the query and receiving this packet.
+    pub latency: Duration,
+}
+
+impl Resolved {
+    pub(crate) fn new(packet: SignedPacket, source: Source, started_at: Timestamp) -> Self {
+        Self {
+            packet,
+            source,
+            latency: elapsed_since(started_at),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// The source of a [Resolved] packet.
+pub enum Source {
+    /// Received from a node on the [mainline] Dht.
+    ///
+    /// Mainline does not expose which node responded with a given item,
+    /// so this variant carries no node information.
+    Dht,
+    #[cfg(relays)]
+    /// Received from a [Relay](https://pkarr.org/relays).
+    Relay {
+        /// The base url of the relay.
+        url: Url,
+    },
+    /// Read from the client's [crate::Cache].
+    Cache,
+    /// Configured locally with [crate::ClientBuilder::static_zone].
+    Static,
+}
+
+/// Time elapsed since `started_at`, or zero if the clock went backwards.
+///
+/// Uses [Timestamp] instead of [std::time::Instant] to work in browsers as well.
+pub(crate) fn elapsed_since(started_at: Timestamp) -> Duration {
+    Duration::from_micros(
+        Timestamp::now()
+            .as_u64()
+            .saturating_sub(started_at.as_u64()),
+    )
+}
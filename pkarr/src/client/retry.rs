@@ -0,0 +1,163 @@
+//! Retrying transient publish failures, see [crate::ClientBuilder::retry_policy].
+
+use std::time::Duration;
+
+#[cfg(relays)]
+use ntimestamp::Timestamp;
+#[cfg(relays)]
+use reqwest::{header, header::HeaderMap, StatusCode};
+
+#[cfg(dht)]
+use super::sleep;
+#[cfg(dht)]
+use super::{PublishError, QueryError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A policy for retrying transient publish failures, see [crate::ClientBuilder::retry_policy].
+///
+/// Retries Dht queries that timed out or found no closest nodes, and relay requests that
+/// failed, timed out, or got a `429 Too Many Requests` or `5xx` response.
+///
+/// Concurrency errors (conflicts and CAS failures) are never retried.
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one.
+    ///
+    /// Defaults to `3`.
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled after each attempt.
+    ///
+    /// Defaults to 500 milliseconds.
+    pub initial_backoff: Duration,
+    /// The maximum delay between attempts.
+    ///
+    /// A relay asking to retry after longer than this (using the `Retry-After` header)
+    /// is not retried.
+    ///
+    /// Defaults to 10 seconds.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before the next attempt, after `attempt` failed attempts,
+    /// or `None` if there are no more attempts left.
+    ///
+    /// Uses exponential backoff with "equal jitter", a random delay between half
+    /// and all of the exponential backoff.
+    pub(crate) fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        let mut bytes = [0_u8; 4];
+        getrandom::fill(&mut bytes).expect("getrandom failed");
+        let random = u32::from_le_bytes(bytes) as f64 / u32::MAX as f64;
+
+        Some(exponential / 2 + (exponential / 2).mul_f64(random))
+    }
+
+    #[cfg(relays)]
+    /// Returns the delay before retrying a relay request, after `attempt` failed attempts,
+    /// no sooner than the relay's `Retry-After` if any, or `None` if it shouldn't be retried.
+    pub(crate) fn relay_backoff(
+        &self,
+        attempt: u32,
+        status: Option<StatusCode>,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        let retryable = match status {
+            None => true,
+            Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+        };
+
+        if !retryable {
+            return None;
+        }
+
+        let backoff = self.backoff(attempt)?;
+
+        match retry_after {
+            Some(retry_after) if retry_after > self.max_backoff => None,
+            // `Retry-After` is rounded down to seconds, so it is only a lower bound.
+            Some(retry_after) => Some(retry_after.max(backoff)),
+            None => Some(backoff),
+        }
+    }
+}
+
+#[cfg(relays)]
+/// Parse the `Retry-After` header, either in seconds or as an HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = Timestamp::parse_http_date(value).ok()?;
+
+    Some(Duration::from_micros(
+        date.as_u64().saturating_sub(Timestamp::now().as_u64()),
+    ))
+}
+
+#[cfg(dht)]
+/// Returns true if a Dht publish error is transient and worth retrying.
+fn is_retryable_dht_error(error: &PublishError) -> bool {
+    matches!(
+        error,
+        PublishError::Query(QueryError::Timeout | QueryError::NoClosestNodes)
+    )
+}
+
+#[cfg(dht)]
+/// Put a [mainline::MutableItem] to the Dht, retrying transient failures according to the policy.
+pub(crate) async fn put_mutable(
+    node: &mainline::async_dht::AsyncDht,
+    item: mainline::MutableItem,
+    cas: Option<i64>,
+    policy: Option<&RetryPolicy>,
+) -> Result<(), PublishError> {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let result = node
+            .put_mutable(item.clone(), cas)
+            .await
+            .map(|_| ())
+            .map_err(PublishError::from);
+
+        let Err(error) = &result else {
+            return result;
+        };
+
+        let backoff = policy
+            .filter(|_| is_retryable_dht_error(error))
+            .and_then(|policy| policy.backoff(attempt));
+
+        match backoff {
+            Some(backoff) => {
+                cross_debug!("Retrying Dht put after {error}, in {backoff:?}");
+
+                sleep(backoff).await;
+            }
+            None => return result,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Locally configured [SignedPacket]s that take precedence over the cache and the network,
+//! see [crate::ClientBuilder::static_zone].
+
+#[cfg(not(wasm_browser))]
+use std::net::{Ipv4Addr, Ipv6Addr};
+#[cfg(not(wasm_browser))]
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(not(wasm_browser))]
+use simple_dns::{rdata::TXT, Name};
+
+#[cfg(not(wasm_browser))]
+use crate::Keypair;
+use crate::{PublicKey, SignedPacket};
+
+use super::Client;
+
+#[cfg(not(wasm_browser))]
+/// Extension of zone files, see [crate::ClientBuilder::static_zones_dir].
+const ZONE_EXTENSION: &str = "zone";
+#[cfg(not(wasm_browser))]
+/// Extension of the keypair files signing zone files, see [crate::ClientBuilder::static_zones_dir].
+const KEY_EXTENSION: &str = "key";
+
+#[derive(thiserror::Error, Debug)]
+/// Errors configuring static zones, see [crate::ClientBuilder::static_zone]
+/// and [crate::ClientBuilder::static_zones_dir].
+pub enum StaticZonesError {
+    #[error(transparent)]
+    /// Failed to read the directory or one of its files.
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid packet file {path:?}: {error}")]
+    /// A packet file is not a valid [SignedPacket] for the [PublicKey] in its name.
+    InvalidPacket {
+        /// The path of the invalid file.
+        path: PathBuf,
+        /// Why the packet is invalid.
+        error: crate::errors::SignedPacketVerifyError,
+    },
+
+    #[error("Invalid zone file {path:?}: {reason}")]
+    /// A zone file has an invalid record, or its records can't be signed into a [SignedPacket].
+    InvalidZone {
+        /// The path of the invalid file.
+        path: PathBuf,
+        /// Why the zone is invalid.
+        reason: String,
+    },
+
+    #[error("Static zone packet is signed by a different key")]
+    /// The [SignedPacket] of a static zone isn't signed by its [PublicKey].
+    KeyMismatch,
+}
+
+#[cfg(not(wasm_browser))]
+/// Load a [SignedPacket] from each zone file and packet file in the directory,
+/// see [crate::ClientBuilder::static_zones_dir] for the formats.
+pub(crate) fn load_dir(dir: &Path) -> Result<Vec<SignedPacket>, StaticZonesError> {
+    let mut packets = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if !path.is_file() {
+            continue;
+        }
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(ZONE_EXTENSION) => {
+                packets.push(load_zone_file(&path)?);
+
+                continue;
+            }
+            Some(KEY_EXTENSION) => continue,
+            _ => {}
+        }
+
+        let public_key = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| PublicKey::try_from(stem).ok());
+
+        let Some(public_key) = public_key else {
+            cross_debug!("Ignoring file not named after a public key in static zones {path:?}");
+
+            continue;
+        };
+
+        let bytes = std::fs::read(&path)?;
+
+        let signed_packet =
+            SignedPacket::from_relay_payload(&public_key, &bytes.into()).map_err(|error| {
+                StaticZonesError::InvalidPacket {
+                    path: path.clone(),
+                    error,
+                }
+            })?;
+
+        packets.push(signed_packet);
+    }
+
+    Ok(packets)
+}
+
+#[cfg(not(wasm_browser))]
+/// Parse the records of a zone file, and sign them with the keypair
+/// from the `.key` file next to it.
+fn load_zone_file(path: &Path) -> Result<SignedPacket, StaticZonesError> {
+    let invalid = |reason: String| StaticZonesError::InvalidZone {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let keypair = Keypair::from_secret_key_file(&path.with_extension(KEY_EXTENSION))?;
+    let text = std::fs::read_to_string(path)?;
+
+    let mut builder = SignedPacket::builder();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid_line = |reason: &str| invalid(format!("line {}: {reason}", index + 1));
+
+        let mut parts = line.split_whitespace();
+
+        let (Some(name), Some(ttl), Some(record_type)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid_line("expected <name> <ttl> <type> <value>"));
+        };
+        let value = parts.collect::<Vec<_>>().join(" ");
+
+        let name = Name::new(if name == "@" { "." } else { name })
+            .map_err(|_| invalid_line("invalid name"))?;
+        let ttl = ttl.parse().map_err(|_| invalid_line("invalid ttl"))?;
+
+        builder = match record_type.to_ascii_uppercase().as_str() {
+            "A" => builder.a(
+                name,
+                value
+                    .parse::<Ipv4Addr>()
+                    .map_err(|_| invalid_line("invalid IPv4 address"))?,
+                ttl,
+            ),
+            "AAAA" => builder.aaaa(
+                name,
+                value
+                    .parse::<Ipv6Addr>()
+                    .map_err(|_| invalid_line("invalid IPv6 address"))?,
+                ttl,
+            ),
+            "CNAME" => builder.cname(
+                name,
+                Name::new(&value).map_err(|_| invalid_line("invalid CNAME target"))?,
+                ttl,
+            ),
+            "TXT" => builder.txt(
+                name,
+                TXT::try_from(value.trim_matches('"'))
+                    .map_err(|_| invalid_line("invalid TXT value"))?,
+                ttl,
+            ),
+            _ => return Err(invalid_line("unsupported record type")),
+        };
+    }
+
+    builder
+        .sign(&keypair)
+        .map_err(|error| invalid(error.to_string()))
+}
+
+impl Client {
+    /// Returns the locally configured [SignedPacket] for this [PublicKey] if any,
+    /// see [crate::ClientBuilder::static_zone].
+    pub fn static_zone(&self, public_key: &PublicKey) -> Option<SignedPacket> {
+        self.0.static_zones.get(public_key).cloned()
+    }
+}
@@ -1,14 +1,19 @@
 //! Client native tests
 
-use std::{thread, time::Duration};
+use std::{sync::Arc, thread, time::Duration};
 
 use ntimestamp::Timestamp;
 use pkarr_relay::Relay;
 use rstest::rstest;
 use simple_dns::rdata::SVCB;
 
-use crate::errors::{BuildError, ConcurrencyError, PublishError};
-use crate::{Client, ClientBuilder, Keypair, SignedPacket};
+use crate::errors::{BuildError, ConcurrencyError, PublishError, QueryError, QuorumError};
+#[cfg(feature = "relays")]
+use crate::RelayOutcome;
+use crate::{
+    CacheDiagnostic, CachePolicy, Client, ClientBuilder, ClientEvent, ClientObserver, Keypair,
+    NetworkSelection, RequestOptions, ResolveOptions, SignedPacket, Source,
+};
 
 #[derive(Copy, Clone)]
 pub(crate) enum Networks {
@@ -21,7 +26,7 @@ pub(crate) enum Networks {
 /// Parametric [ClientBuilder] with no default networks,
 /// instead it uses mainline or relays depending on `networks` enum.
 pub(crate) fn builder(
-    relay: &Relay,
+    #[cfg_attr(not(feature = "relays"), allow(unused_variables))] relay: &Relay,
     testnet: &mainline::Testnet,
     networks: Networks,
 ) -> ClientBuilder {
@@ -669,156 +674,3017 @@ async fn regression_relay_cas(#[case] networks: Networks) {
         .unwrap();
 }
 
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn resolve_stream_sources(#[case] networks: Networks) {
+    use futures_lite::StreamExt;
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&signed_packet, None).await.unwrap();
+
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+
+    let resolved = b
+        .resolve_stream(&keypair.public_key())
+        .collect::<Vec<_>>()
+        .await;
+
+    assert!(!resolved.is_empty());
+
+    for resolved in resolved {
+        assert_eq!(resolved.packet.as_bytes(), signed_packet.as_bytes());
+
+        match networks {
+            Networks::Dht => assert_eq!(resolved.source, Source::Dht),
+            #[cfg(feature = "relays")]
+            Networks::Relays => assert_eq!(
+                resolved.source,
+                Source::Relay {
+                    url: relay.local_url()
+                }
+            ),
+            Networks::Both => assert_ne!(resolved.source, Source::Cache),
+        }
+    }
+
+    let first = b
+        .resolve_stream(&keypair.public_key())
+        .next()
+        .await
+        .unwrap();
+
+    assert_eq!(first.source, Source::Cache);
+    assert_eq!(first.packet.as_bytes(), signed_packet.as_bytes());
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn diagnose(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&signed_packet, None).await.unwrap();
+
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+
+    let report = b.diagnose(&keypair.public_key()).await;
+
+    assert!(matches!(report.cache, CacheDiagnostic::Miss));
+    assert_eq!(
+        report.most_recent().unwrap().as_bytes(),
+        signed_packet.as_bytes()
+    );
+
+    match networks {
+        Networks::Dht => {
+            let dht = report.dht.unwrap();
+
+            assert!(!dht.packets.is_empty());
+            assert!(dht.rejected.is_empty());
+            assert!(!dht.closest_nodes.is_empty());
+            #[cfg(feature = "relays")]
+            assert!(report.relays.is_empty());
+        }
+        #[cfg(feature = "relays")]
+        Networks::Relays => {
+            assert!(report.dht.is_none());
+            assert_eq!(report.relays.len(), 1);
+
+            let relay_diagnostic = &report.relays[0];
+
+            assert_eq!(relay_diagnostic.url, relay.local_url());
+            assert_eq!(relay_diagnostic.status, Some(200));
+            assert!(matches!(
+                &relay_diagnostic.outcome,
+                RelayOutcome::Found(packet) if packet.as_bytes() == signed_packet.as_bytes()
+            ));
+        }
+        Networks::Both => {
+            assert!(report.dht.is_some());
+            #[cfg(feature = "relays")]
+            assert_eq!(report.relays.len(), 1);
+        }
+    }
+
+    // Diagnosing doesn't update the cache
+    assert!(b.cache().unwrap().is_empty());
+
+    b.resolve(&keypair.public_key()).await.unwrap();
+
+    let report = b.diagnose(&keypair.public_key()).await;
+
+    assert!(matches!(
+        report.cache,
+        CacheDiagnostic::Hit { expired: false, .. }
+    ));
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn diagnose_not_found() {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let client = builder(&relay, &testnet, Networks::Both).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let report = client.diagnose(&keypair.public_key()).await;
+
+    assert!(report.most_recent().is_none());
+    assert!(report.dht.unwrap().packets.is_empty());
+    assert_eq!(report.relays[0].status, Some(404));
+    assert!(matches!(report.relays[0].outcome, RelayOutcome::NotFound));
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn diagnose_rejected_relay_payload() {
+    use axum::{routing::get, Router};
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    // A misbehaving relay that returns an invalid payload for every key
+    let app = Router::new().route("/{key}", get(|| async { vec![0_u8; 100] }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let mut builder = builder(&relay, &testnet, Networks::Relays);
+    builder.relays(&[format!("http://{address}")]).unwrap();
+    let client = builder.build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let report = client.diagnose(&keypair.public_key()).await;
+
+    assert!(report.most_recent().is_none());
+    assert_eq!(report.relays[0].status, Some(200));
+    assert!(matches!(
+        report.relays[0].outcome,
+        RelayOutcome::Rejected(_)
+    ));
+}
+
+/// Records the kind of every [ClientEvent].
+#[derive(Debug, Default)]
+struct Recorder(std::sync::Mutex<Vec<&'static str>>);
+
+impl Recorder {
+    fn events(&self) -> Vec<&'static str> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl ClientObserver for Recorder {
+    fn on_event(&self, event: &ClientEvent) {
+        let kind = match event {
+            ClientEvent::CacheHit { .. } => "cache_hit",
+            ClientEvent::CacheMiss { .. } => "cache_miss",
+            #[cfg(feature = "relays")]
+            ClientEvent::RelayResponse {
+                status: Some(_), ..
+            } => "relay_response",
+            #[cfg(feature = "relays")]
+            ClientEvent::RelayResponse { status: None, .. } => "relay_error",
+            ClientEvent::DhtResponse { .. } => "dht_response",
+            ClientEvent::PacketRejected { .. } => "packet_rejected",
+            ClientEvent::Published { result: Ok(()), .. } => "published",
+            ClientEvent::Published { result: Err(_), .. } => "publish_failed",
+        };
+
+        self.0.lock().unwrap().push(kind);
+    }
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn observer(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a_recorder = Arc::new(Recorder::default());
+    let a = builder(&relay, &testnet, networks)
+        .observer(a_recorder.clone())
+        .build()
+        .unwrap();
+
+    let b_recorder = Arc::new(Recorder::default());
+    let b = builder(&relay, &testnet, networks)
+        .observer(b_recorder.clone())
+        .build()
+        .unwrap();
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&signed_packet, None).await.unwrap();
+    a.resolve(&keypair.public_key()).await.unwrap();
+
+    let events = a_recorder.events();
+
+    assert!(events.contains(&"published"));
+    assert_eq!(events.last(), Some(&"cache_hit"));
+    #[cfg(feature = "relays")]
+    if !matches!(networks, Networks::Dht) {
+        assert!(events.contains(&"relay_response"));
+    }
+
+    b.resolve(&keypair.public_key()).await.unwrap();
+
+    let events = b_recorder.events();
+
+    assert_eq!(events.first(), Some(&"cache_miss"));
+    match networks {
+        Networks::Dht => assert!(events.contains(&"dht_response")),
+        #[cfg(feature = "relays")]
+        Networks::Relays => assert!(events.contains(&"relay_response")),
+        Networks::Both => {
+            assert!(events.contains(&"dht_response") || events.contains(&"relay_response"))
+        }
+    }
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn observer_rejected_relay_payload() {
+    use axum::{routing::get, Router};
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    // A misbehaving relay that returns an invalid payload for every key
+    let app = Router::new().route("/{key}", get(|| async { vec![0_u8; 100] }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let recorder = Arc::new(Recorder::default());
+
+    let mut builder = builder(&relay, &testnet, Networks::Relays);
+    builder
+        .relays(&[format!("http://{address}")])
+        .unwrap()
+        .observer(recorder.clone());
+    let client = builder.build().unwrap();
+
+    assert_eq!(client.resolve(&Keypair::random().public_key()).await, None);
+
+    assert_eq!(
+        recorder.events(),
+        vec!["cache_miss", "relay_response", "packet_rejected"]
+    );
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn publish_with_report(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let client = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    let report = client.publish_with_report(&signed_packet, None).await;
+
+    assert_eq!(report.public_key, keypair.public_key());
+    assert_eq!(report.result, Ok(()));
+
+    match networks {
+        Networks::Dht => {
+            assert!(report.swallowed_errors.is_empty());
+
+            let dht = report.dht.unwrap();
+            assert_eq!(dht.result, Ok(()));
+            assert!(dht.stored_at > 0);
+            #[cfg(feature = "relays")]
+            assert!(report.relays.is_empty());
+        }
+        #[cfg(feature = "relays")]
+        Networks::Relays => {
+            assert!(report.swallowed_errors.is_empty());
+            assert!(report.dht.is_none());
+            assert_eq!(report.relays.len(), 1);
+            assert_eq!(report.relays_accepted(), 1);
+            assert_eq!(report.relays[0].url, relay.local_url());
+            assert!(report.relays[0].status.is_some());
+        }
+        Networks::Both => {
+            // The relay may fail, racing to publish the same packet to the same Dht,
+            // in which case its error is swallowed by the Dht's success.
+            assert!(report.dht.as_ref().unwrap().stored_at > 0);
+            #[cfg(feature = "relays")]
+            {
+                assert_eq!(report.relays.len(), 1);
+                assert_eq!(report.relays_accepted() + report.swallowed_errors.len(), 1);
+            }
+        }
+    }
+
+    assert_eq!(
+        client
+            .resolve(&keypair.public_key())
+            .await
+            .unwrap()
+            .as_bytes(),
+        signed_packet.as_bytes()
+    );
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn publish_with_report_failing_relay() {
+    use axum::http::StatusCode;
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let (broken, _) = stub_relay(StatusCode::INTERNAL_SERVER_ERROR, Duration::ZERO).await;
+
+    let client = builder(&relay, &testnet, Networks::Relays)
+        .relays(&[relay.local_url().as_str(), broken.as_str()])
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&Keypair::random())
+        .unwrap();
+
+    let report = client.publish_with_report(&signed_packet, None).await;
+
+    assert_eq!(report.result, Ok(()));
+    assert_eq!(report.relays.len(), 2);
+    assert_eq!(report.relays_accepted(), 1);
+
+    assert_eq!(report.relays[0].result, Ok(()));
+    assert_eq!(report.relays[1].url.as_str(), format!("{broken}/"));
+    assert_eq!(report.relays[1].status, Some(500));
+    assert_eq!(
+        report.relays[1].result,
+        Err(PublishError::UnexpectedResponses)
+    );
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn update(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let first = a
+        .update(&keypair, |current| {
+            assert!(current.is_none());
+
+            SignedPacket::builder().txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        })
+        .await
+        .unwrap();
+
+    let second = b
+        .update(&keypair, |current| {
+            assert_eq!(current.unwrap().as_bytes(), first.as_bytes());
+
+            SignedPacket::builder()
+                .record(
+                    current
+                        .unwrap()
+                        .all_resource_records()
+                        .next()
+                        .unwrap()
+                        .clone(),
+                )
+                .txt("baz".try_into().unwrap(), "qux".try_into().unwrap(), 30)
+        })
+        .await
+        .unwrap();
+
+    assert!(second.more_recent_than(&first));
+    assert_eq!(second.all_resource_records().count(), 2);
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test(flavor = "multi_thread")]
+async fn update_retries_concurrency_errors(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let client = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let initial = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "initial".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    client.publish(&initial, None).await.unwrap();
+
+    let competing = SignedPacket::builder()
+        .txt(
+            "foo".try_into().unwrap(),
+            "competing".try_into().unwrap(),
+            30,
+        )
+        .sign(&keypair)
+        .unwrap();
+
+    let mut calls = 0;
+
+    let updated = client
+        .update(&keypair, |current| {
+            calls += 1;
+
+            if calls == 1 {
+                assert_eq!(current.unwrap().as_bytes(), initial.as_bytes());
+
+                // Publish a competing packet after resolving, but before publishing.
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current()
+                        .block_on(client.publish(&competing, None))
+                        .unwrap()
+                });
+            } else {
+                assert_eq!(current.unwrap().as_bytes(), competing.as_bytes());
+            }
+
+            SignedPacket::builder().txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(calls, 2);
+    assert!(updated.more_recent_than(&competing));
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn retry_rate_limited_relay() {
+    use pkarr_relay::RateLimiterConfig;
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+
+    let mut relay_builder = Relay::builder();
+    relay_builder
+        .http_port(0)
+        .rate_limiter_config(RateLimiterConfig {
+            behind_proxy: false,
+            per_second: 1,
+            burst_size: 1,
+        })
+        .pkarr(|builder| {
+            builder
+                .no_default_network()
+                .bootstrap(&testnet.bootstrap)
+                .request_timeout(Duration::from_millis(100))
+                .dht(|builder| builder.server_mode())
+        });
+    let relay = unsafe { relay_builder.run() }.await.unwrap();
+
+    // The same rate limiter filters Dht requests from the testnet nodes (also on localhost),
+    // so wait for the quota to replenish after bootstrapping.
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    let signed_packets = (0..2)
+        .map(|_| {
+            SignedPacket::builder()
+                .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+                .sign(&Keypair::random())
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // Without retries, the rate limited request fails.
+    let client = builder(&relay, &testnet, Networks::Relays).build().unwrap();
+
+    client.publish(&signed_packets[0], None).await.unwrap();
+    assert_eq!(
+        client.publish(&signed_packets[1], None).await,
+        Err(PublishError::UnexpectedResponses)
+    );
+
+    // With retries, the rate limited request waits for `Retry-After` and succeeds.
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    let client = builder(&relay, &testnet, Networks::Relays)
+        .retry_policy(crate::RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+
+    client.publish(&signed_packets[0], None).await.unwrap();
+    client.publish(&signed_packets[1], None).await.unwrap();
+}
+
+#[cfg(feature = "relays")]
+#[rstest]
+#[case::server_error(axum::http::StatusCode::SERVICE_UNAVAILABLE, 3)]
+#[case::conflict(axum::http::StatusCode::CONFLICT, 1)]
+#[case::cas_failed(axum::http::StatusCode::PRECONDITION_FAILED, 1)]
+#[case::bad_request(axum::http::StatusCode::BAD_REQUEST, 1)]
+#[tokio::test]
+async fn retry_policy_relay_statuses(
+    #[case] status: axum::http::StatusCode,
+    #[case] expected_attempts: usize,
+) {
+    use std::sync::atomic::Ordering;
+
+    let (stub, hits) = stub_relay(status, Duration::ZERO).await;
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[stub.as_str()])
+        .unwrap()
+        .retry_policy(crate::RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&Keypair::random())
+        .unwrap();
+
+    assert!(client.publish(&signed_packet, None).await.is_err());
+    assert_eq!(hits.load(Ordering::SeqCst), expected_attempts);
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn retry_policy_cas_after_ambiguous_failure() {
+    use axum::{body::Bytes, http::StatusCode, routing::get, Router};
+    use std::sync::Mutex;
+
+    // A relay that stores the first PUT, but responds after the client timed out,
+    // then fails the retry's `If-Match` because the packet was already stored.
+    let stored = Arc::new(Mutex::new(None::<Bytes>));
+
+    let app = Router::new().route(
+        "/{key}",
+        get({
+            let stored = stored.clone();
+
+            move || async move {
+                match stored.lock().unwrap().clone() {
+                    Some(body) => (StatusCode::OK, body),
+                    None => (StatusCode::NOT_FOUND, Bytes::new()),
+                }
+            }
+        })
+        .put({
+            let stored = stored.clone();
+
+            move |body: Bytes| async move {
+                if stored.lock().unwrap().replace(body).is_some() {
+                    return StatusCode::PRECONDITION_FAILED;
+                }
+
+                tokio::time::sleep(Duration::from_secs(2)).await;
+
+                StatusCode::NO_CONTENT
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[format!("http://{address}")])
+        .unwrap()
+        .request_timeout(Duration::from_millis(200))
+        .retry_policy(crate::RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    client
+        .publish(&signed_packet, Some(Timestamp::from(1)))
+        .await
+        .unwrap();
+}
+
+#[cfg(feature = "relays")]
+/// Runs a stub relay that responds to every request with `status` after `delay`,
+/// and returns its Url and a counter of the requests it received.
+async fn stub_relay(
+    status: axum::http::StatusCode,
+    delay: Duration,
+) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+    use axum::{routing::any, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let hits = Arc::new(AtomicUsize::new(0));
+
+    let app = Router::new().route(
+        "/{key}",
+        any({
+            let hits = hits.clone();
+
+            move || async move {
+                hits.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(delay).await;
+
+                status
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    (format!("http://{address}"), hits)
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_circuit_breaker() {
+    use axum::http::StatusCode;
+    use std::sync::atomic::Ordering;
+
+    let (healthy, healthy_hits) = stub_relay(StatusCode::NOT_FOUND, Duration::ZERO).await;
+    let (broken, broken_hits) = stub_relay(StatusCode::INTERNAL_SERVER_ERROR, Duration::ZERO).await;
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[healthy.as_str(), broken.as_str()])
+        .unwrap()
+        .relay_circuit_breaker(2, Duration::from_secs(60))
+        .build()
+        .unwrap();
+
+    for _ in 0..4 {
+        assert_eq!(client.resolve(&Keypair::random().public_key()).await, None);
+    }
+
+    assert_eq!(healthy_hits.load(Ordering::SeqCst), 4);
+    assert_eq!(broken_hits.load(Ordering::SeqCst), 2);
+
+    let health = client.relays_health();
+
+    assert_eq!(health.len(), 2);
+    assert!(!health[0].skipped);
+    assert_eq!(health[0].consecutive_failures, 0);
+    assert!(health[0].latency.is_some());
+    assert!(health[1].skipped);
+    assert_eq!(health[1].consecutive_failures, 2);
+    assert!(health[1].error_rate > 0.0);
+    assert_eq!(health[1].latency, None);
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_circuit_breaker_clock() {
+    use axum::http::StatusCode;
+    use std::sync::atomic::Ordering;
+
+    use crate::TestClock;
+
+    let (broken, broken_hits) = stub_relay(StatusCode::INTERNAL_SERVER_ERROR, Duration::ZERO).await;
+    let (healthy, _) = stub_relay(StatusCode::NOT_FOUND, Duration::ZERO).await;
+
+    let clock = TestClock::default();
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[broken.as_str(), healthy.as_str()])
+        .unwrap()
+        .relay_circuit_breaker(1, Duration::from_secs(60))
+        .clock(Arc::new(clock.clone()))
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        assert_eq!(client.resolve(&Keypair::random().public_key()).await, None);
+    }
+
+    assert_eq!(broken_hits.load(Ordering::SeqCst), 1);
+    assert!(client.relays_health()[0].skipped);
+
+    // The cooldown ends according to the client's clock.
+    clock.advance(Duration::from_secs(61));
+
+    assert!(!client.relays_health()[0].skipped);
+    assert_eq!(client.resolve(&Keypair::random().public_key()).await, None);
+    assert_eq!(broken_hits.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_publish_health() {
+    use axum::http::StatusCode;
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&Keypair::random())
+        .unwrap();
+
+    for (status, healthy) in [
+        (StatusCode::NO_CONTENT, true),
+        (StatusCode::PRECONDITION_FAILED, true),
+        (StatusCode::CONFLICT, true),
+        (StatusCode::FORBIDDEN, false),
+        (StatusCode::TOO_MANY_REQUESTS, false),
+        (StatusCode::INTERNAL_SERVER_ERROR, false),
+    ] {
+        let (relay, _) = stub_relay(status, Duration::ZERO).await;
+
+        let client = Client::builder()
+            .no_default_network()
+            .relays(&[relay.as_str()])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let _ = client.publish(&signed_packet, None).await;
+
+        assert_eq!(
+            client.relays_health()[0].consecutive_failures == 0,
+            healthy,
+            "{status}"
+        );
+    }
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_hedging() {
+    use axum::http::StatusCode;
+    use std::sync::atomic::Ordering;
+
+    let (slow, slow_hits) = stub_relay(StatusCode::NOT_FOUND, Duration::from_secs(1)).await;
+    let (fast, fast_hits) = stub_relay(StatusCode::NOT_FOUND, Duration::ZERO).await;
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[slow.as_str(), fast.as_str()])
+        .unwrap()
+        .relay_hedging(1, Duration::from_millis(200))
+        .request_timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+
+    // Unknown latencies keep the configured order, so the slow relay is queried first,
+    // and the fast relay is hedged after the delay.
+    assert_eq!(client.resolve(&Keypair::random().public_key()).await, None);
+
+    assert_eq!(slow_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(fast_hits.load(Ordering::SeqCst), 1);
+
+    // Now the fast relay is queried first, and answers before the hedging delay.
+    for _ in 0..3 {
+        assert_eq!(client.resolve(&Keypair::random().public_key()).await, None);
+    }
+
+    assert_eq!(slow_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(fast_hits.load(Ordering::SeqCst), 4);
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relays_from_key_published_later() {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let list_keypair = Keypair::random();
+
+    // The default refresh interval is an hour, so the list is only picked up by retries.
+    let client = Client::builder()
+        .no_default_network()
+        .bootstrap(&testnet.bootstrap)
+        .relays_from_key(list_keypair.public_key())
+        .build()
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(client.relays().is_empty());
+
+    let relays_list = SignedPacket::builder()
+        .txt(
+            crate::RELAYS_RECORD_NAME.try_into().unwrap(),
+            relay.local_url().as_str().try_into().unwrap(),
+            300,
+        )
+        .sign(&list_keypair)
+        .unwrap();
+
+    builder(&relay, &testnet, Networks::Dht)
+        .build()
+        .unwrap()
+        .publish(&relays_list, None)
+        .await
+        .unwrap();
+
+    for _ in 0..300 {
+        if client.relays() == vec![relay.local_url()] {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("timed out waiting for the relays list");
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relays_from_key() {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let publisher = builder(&relay, &testnet, Networks::Dht).build().unwrap();
+
+    let list_keypair = Keypair::random();
+    let relays_list = SignedPacket::builder()
+        .txt(
+            crate::RELAYS_RECORD_NAME.try_into().unwrap(),
+            relay.local_url().as_str().try_into().unwrap(),
+            300,
+        )
+        .txt(
+            crate::RELAYS_RECORD_NAME.try_into().unwrap(),
+            "ftp://example.com".try_into().unwrap(),
+            300,
+        )
+        .txt(
+            "_other".try_into().unwrap(),
+            "https://ignored.example.com".try_into().unwrap(),
+            300,
+        )
+        .sign(&list_keypair)
+        .unwrap();
+
+    publisher.publish(&relays_list, None).await.unwrap();
+
+    let client = Client::builder()
+        .no_default_network()
+        .bootstrap(&testnet.bootstrap)
+        .relays_from_key(list_keypair.public_key())
+        .relays_refresh_interval(Duration::from_secs(1))
+        .build()
+        .unwrap();
+
+    /// Wait for the client's relays to be `expected` or panic after 20 seconds.
+    async fn wait_for_relays(client: &Client, expected: Vec<url::Url>) {
+        for _ in 0..200 {
+            if client.relays() == expected {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        panic!("timed out waiting for relays {expected:?}");
+    }
+
+    wait_for_relays(&client, vec![relay.local_url()]).await;
+
+    // Discovered relays are used for publishing and resolving.
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    let relays_only = RequestOptions {
+        networks: NetworkSelection::Relays,
+        ..Default::default()
+    };
+
+    client
+        .publish_with_options(&signed_packet, None, relays_only.clone())
+        .await
+        .unwrap();
+
+    let resolver = builder(&relay, &testnet, Networks::Relays).build().unwrap();
+    assert_eq!(
+        resolver
+            .resolve(&keypair.public_key())
+            .await
+            .unwrap()
+            .as_bytes(),
+        signed_packet.as_bytes()
+    );
+
+    // Updated relays lists are picked up on refresh.
+    let mut https = SVCB::new(0, "relay.example.com".try_into().unwrap());
+    https.set_port(8443);
+
+    let updated_list = SignedPacket::builder()
+        .https(crate::RELAYS_RECORD_NAME.try_into().unwrap(), https, 300)
+        .sign(&list_keypair)
+        .unwrap();
+
+    publisher.publish(&updated_list, None).await.unwrap();
+
+    wait_for_relays(
+        &client,
+        vec!["https://relay.example.com:8443".try_into().unwrap()],
+    )
+    .await;
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn watch(#[case] networks: Networks) {
+    use futures_lite::StreamExt;
+
+    /// Returns the next item of the stream or panics after 10 seconds.
+    async fn next_or_timeout(
+        stream: &mut (impl futures_lite::Stream<Item = SignedPacket> + Unpin),
+    ) -> SignedPacket {
+        futures_lite::future::or(async { stream.next().await.unwrap() }, async {
+            super::sleep(Duration::from_secs(10)).await;
+            panic!("timed out waiting for an update")
+        })
+        .await
+    }
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let builder =
+        SignedPacket::builder().txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30);
+
+    let first = builder.clone().sign(&keypair).unwrap();
+
+    a.publish(&first, None).await.unwrap();
+
+    let mut stream = b.watch(&keypair.public_key(), Duration::from_millis(100));
+
+    assert_eq!(
+        next_or_timeout(&mut stream).await.as_bytes(),
+        first.as_bytes()
+    );
+
+    // Relays compare `If-Modified-Since` with a precision of one second.
+    super::sleep(Duration::from_secs(1)).await;
+
+    let second = builder.sign(&keypair).unwrap();
+
+    a.publish(&second, Some(first.timestamp())).await.unwrap();
+
+    assert_eq!(
+        next_or_timeout(&mut stream).await.as_bytes(),
+        second.as_bytes()
+    );
+
+    // Only strictly newer packets
+    let no_update = futures_lite::future::or(async { stream.next().await }, async {
+        super::sleep(Duration::from_millis(500)).await;
+        None
+    })
+    .await;
+
+    assert!(no_update.is_none());
+
+    b.shutdown();
+
+    let ended = futures_lite::future::or(async { stream.next().await.is_none() }, async {
+        super::sleep(Duration::from_secs(10)).await;
+        false
+    })
+    .await;
+
+    assert!(ended, "the stream ends once the client is shut down");
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn publish_many_resolve_many(#[case] networks: Networks) {
+    use futures_lite::StreamExt;
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypairs = (0..3).map(|_| Keypair::random()).collect::<Vec<_>>();
+
+    let packet_builder =
+        SignedPacket::builder().txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30);
+
+    let older = packet_builder.clone().sign(&keypairs[0]).unwrap();
+    let signed_packets = keypairs
+        .iter()
+        .map(|keypair| packet_builder.clone().sign(keypair).unwrap())
+        .collect::<Vec<_>>();
+
+    let results = a
+        .publish_many(
+            signed_packets.iter().cloned().chain([older]),
+            crate::DEFAULT_PUBLISH_CONCURRENCY,
+        )
+        .await;
+
+    assert_eq!(results.len(), 3);
+    for (_, result) in results {
+        result.unwrap();
+    }
+
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+
+    let missing = Keypair::random().public_key();
+
+    let resolved = b
+        .resolve_many(
+            keypairs
+                .iter()
+                .map(|keypair| keypair.public_key())
+                .chain([missing.clone(), keypairs[0].public_key()]),
+            2,
+        )
+        .collect::<std::collections::HashMap<_, _>>()
+        .await;
+
+    assert_eq!(resolved.len(), 4);
+    assert_eq!(resolved[&missing], None);
+
+    for signed_packet in &signed_packets {
+        assert_eq!(
+            resolved[&signed_packet.public_key()]
+                .as_ref()
+                .unwrap()
+                .as_bytes(),
+            signed_packet.as_bytes()
+        );
+    }
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn resolve_many_deduplicates_inflight() {
+    use axum::http::StatusCode;
+    use futures_lite::StreamExt;
+    use std::sync::atomic::Ordering;
+
+    let (relay, hits) = stub_relay(StatusCode::NOT_FOUND, Duration::from_millis(300)).await;
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[relay])
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let public_key = Keypair::random().public_key();
+
+    let (first, second) = futures_lite::future::zip(
+        client
+            .resolve_many([public_key.clone()], 1)
+            .collect::<Vec<_>>(),
+        client
+            .clone()
+            .resolve_many([public_key.clone()], 1)
+            .collect::<Vec<_>>(),
+    )
+    .await;
+
+    assert_eq!(first, vec![(public_key.clone(), None)]);
+    assert_eq!(second, vec![(public_key, None)]);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[test]
+fn blocking_many(#[case] networks: Networks) {
+    let (relay, testnet) = futures_lite::future::block_on(async_compat::Compat::new(async {
+        let testnet = mainline::Testnet::new_async(5).await.unwrap();
+        let relay = Relay::run_test(&testnet).await.unwrap();
+
+        (relay, testnet)
+    }));
+
+    let a = builder(&relay, &testnet, networks)
+        .build()
+        .unwrap()
+        .as_blocking();
+
+    let signed_packets = (0..3)
+        .map(|_| {
+            SignedPacket::builder()
+                .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+                .sign(&Keypair::random())
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    for (_, result) in a.publish_many(signed_packets.clone(), 2) {
+        result.unwrap();
+    }
+
+    let b = builder(&relay, &testnet, networks)
+        .build()
+        .unwrap()
+        .as_blocking();
+
+    let resolved = b
+        .resolve_many(signed_packets.iter().map(|s| s.public_key()), 3)
+        .collect::<Vec<_>>();
+
+    assert_eq!(resolved.len(), 3);
+    for (public_key, signed_packet) in resolved {
+        assert_eq!(signed_packet.unwrap().public_key(), public_key);
+    }
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn resolve_with(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&signed_packet, None).await.unwrap();
+
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+
+    let quorum = b
+        .resolve_with(&keypair.public_key(), ResolveOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(quorum.packet.as_bytes(), signed_packet.as_bytes());
+    assert!(quorum.confirmations >= 1);
+    assert!(quorum.responses >= quorum.confirmations);
+
+    let quorum = b
+        .resolve_with(
+            &keypair.public_key(),
+            ResolveOptions {
+                min_responses: 1,
+                require_relays_agree: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(quorum.packet.as_bytes(), signed_packet.as_bytes());
+
+    match b
+        .resolve_with(
+            &keypair.public_key(),
+            ResolveOptions {
+                min_responses: 100,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        Err(QuorumError::InsufficientConfirmations { quorum, required }) => {
+            assert_eq!(required, 100);
+            assert_eq!(quorum.packet.as_bytes(), signed_packet.as_bytes());
+        }
+        result => panic!("unexpected result {result:?}"),
+    }
+
+    assert_eq!(
+        b.resolve_with(&Keypair::random().public_key(), ResolveOptions::default())
+            .await,
+        Err(QuorumError::NotFound)
+    );
+
+    assert_eq!(
+        b.resolve_with(
+            &keypair.public_key(),
+            ResolveOptions {
+                deadline: Some(Duration::ZERO),
+                ..Default::default()
+            }
+        )
+        .await,
+        Err(QuorumError::NotFound)
+    );
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_response_too_large() {
+    use axum::{body::Body, routing::get, Router};
+    use reqwest::header::HeaderMap;
+
+    use crate::errors::RelayTransportError;
+    use crate::{RelayTransport, ReqwestTransport, MAX_RESPONSE_BYTES};
+
+    let app = Router::new()
+        .route(
+            "/sized/{key}",
+            get(|| async { vec![0; MAX_RESPONSE_BYTES as usize + 1] }),
+        )
+        // Endless body without a Content-Length.
+        .route(
+            "/streamed/{key}",
+            get(|| async {
+                Body::from_stream(futures_lite::stream::repeat_with(|| {
+                    Ok::<_, std::io::Error>(vec![0; 1024])
+                }))
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let public_key = Keypair::random().public_key();
+    let transport = ReqwestTransport::default();
+
+    for path in ["sized", "streamed"] {
+        let url = format!("http://{address}/{path}/{public_key}")
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            transport
+                .get(url, HeaderMap::new(), Duration::from_secs(5))
+                .await
+                .unwrap_err(),
+            RelayTransportError::BodyTooLarge
+        );
+
+        let client = Client::builder()
+            .no_default_network()
+            .relays(&[format!("http://{address}/{path}")])
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(client.resolve(&public_key).await, None);
+    }
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn resolve_with_relays_disagree() {
+    use axum::{http::StatusCode, routing::get, Router};
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    // A relay that doesn't have any packets
+    let app = Router::new().route("/{key}", get(|| async { StatusCode::NOT_FOUND }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    builder(&relay, &testnet, Networks::Relays)
+        .build()
+        .unwrap()
+        .publish(&signed_packet, None)
+        .await
+        .unwrap();
+
+    let mut builder = builder(&relay, &testnet, Networks::Relays);
+    builder
+        .relays(&[relay.local_url().to_string(), format!("http://{address}")])
+        .unwrap();
+    let client = builder.build().unwrap();
+
+    let options = ResolveOptions {
+        require_relays_agree: true,
+        ..Default::default()
+    };
+
+    match client.resolve_with(&keypair.public_key(), options).await {
+        Err(QuorumError::RelaysDisagree(quorum)) => {
+            assert_eq!(quorum.packet.as_bytes(), signed_packet.as_bytes());
+            assert_eq!(quorum.confirmations, 1);
+        }
+        result => panic!("unexpected result {result:?}"),
+    }
+
+    let quorum = client
+        .resolve_with(&keypair.public_key(), ResolveOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(quorum.packet.as_bytes(), signed_packet.as_bytes());
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn resolve_with_relays_agree_hedging() {
+    use axum::{routing::get, Router};
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    let mut relays = vec![];
+
+    // Two relays that both have the packet.
+    for _ in 0..2 {
+        let payload = signed_packet.to_relay_payload();
+
+        let app = Router::new().route("/{key}", get(move || async move { payload }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        relays.push(format!("http://{address}"));
+    }
+
+    // Hedging would skip the second relay once the first one responded.
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&relays)
+        .unwrap()
+        .relay_hedging(1, Duration::from_secs(5))
+        .build()
+        .unwrap();
+
+    let options = ResolveOptions {
+        require_relays_agree: true,
+        ..Default::default()
+    };
+
+    let quorum = client
+        .resolve_with(&keypair.public_key(), options)
+        .await
+        .unwrap();
+
+    assert_eq!(quorum.packet.as_bytes(), signed_packet.as_bytes());
+    assert_eq!(quorum.confirmations, 2);
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn request_options_cache_policy(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    let no_store = RequestOptions {
+        cache: CachePolicy::NoStore,
+        ..Default::default()
+    };
+    let only = RequestOptions {
+        cache: CachePolicy::Only,
+        ..Default::default()
+    };
+    let bypass = RequestOptions {
+        cache: CachePolicy::Bypass,
+        ..Default::default()
+    };
+
+    a.publish_with_options(&signed_packet, None, no_store.clone())
+        .await
+        .unwrap();
+
+    assert!(a.cache().unwrap().is_empty());
+    assert_eq!(
+        a.resolve_with_options(&keypair.public_key(), only.clone())
+            .await,
+        None
+    );
+
+    let resolved = b
+        .resolve_with_options(&keypair.public_key(), no_store)
+        .await
+        .unwrap();
+
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+    assert!(b.cache().unwrap().is_empty());
+
+    let resolved = b
+        .resolve_with_options(&keypair.public_key(), bypass)
+        .await
+        .unwrap();
+
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+
+    let cached = b
+        .resolve_with_options(&keypair.public_key(), only.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(cached.as_bytes(), signed_packet.as_bytes());
+
+    // Publishing with `CachePolicy::Only` doesn't touch the network.
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish_with_options(&signed_packet, None, only.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        a.resolve_with_options(&keypair.public_key(), only).await,
+        Some(signed_packet)
+    );
+    assert_eq!(b.resolve(&keypair.public_key()).await, None);
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn request_options_cache_policy_outdated(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+    let b = builder(&relay, &testnet, networks)
+        .maximum_ttl(0)
+        .build()
+        .unwrap();
+
+    let keypair = Keypair::random();
+    let cache_key = keypair.public_key().into();
+
+    let packet = |timestamp: u64| {
+        SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .timestamp(timestamp.into())
+            .sign(&keypair)
+            .unwrap()
+    };
+    // Seconds apart, since relays compare `If-Modified-Since` in seconds.
+    let now = Timestamp::now().as_u64();
+    let (older, newer, newest) = (
+        packet(now - 4_000_000),
+        packet(now - 2_000_000),
+        packet(now),
+    );
+
+    a.publish_with_options(
+        &newer,
+        None,
+        RequestOptions {
+            cache: CachePolicy::Only,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    // Bypassing the conflict check doesn't replace a more recent cached packet.
+    a.publish_with_options(
+        &older,
+        None,
+        RequestOptions {
+            cache: CachePolicy::Bypass,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(a.cache().unwrap().get_read_only(&cache_key), Some(newer));
+
+    a.publish(&newest, None).await.unwrap();
+
+    // An expired cached packet is refreshed from the network, without storing.
+    b.cache().unwrap().put(&cache_key, &older);
+
+    let resolved = b
+        .resolve_with_options(
+            &keypair.public_key(),
+            RequestOptions {
+                cache: CachePolicy::NoStore,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(resolved.as_bytes(), newest.as_bytes());
+    assert_eq!(b.cache().unwrap().get_read_only(&cache_key), Some(older));
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn request_options_networks(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let client = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    let selection = match networks {
+        Networks::Dht => NetworkSelection::Relays,
+        #[cfg(feature = "relays")]
+        Networks::Relays => NetworkSelection::Dht,
+        Networks::Both => NetworkSelection::All,
+    };
+
+    let options = RequestOptions {
+        networks: selection,
+        ..Default::default()
+    };
+
+    let result = client
+        .publish_with_options(&signed_packet, None, options.clone())
+        .await;
+
+    if matches!(networks, Networks::Both) {
+        result.unwrap();
+    } else {
+        assert_eq!(result, Err(PublishError::Query(QueryError::NoNetwork)));
+
+        let options = RequestOptions {
+            cache: CachePolicy::Bypass,
+            ..options
+        };
+
+        assert_eq!(
+            client
+                .resolve_with_options(&keypair.public_key(), options)
+                .await,
+            None
+        );
+    }
+}
+
+#[tokio::test]
+async fn request_options_timeout() {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let client = builder(&relay, &testnet, Networks::Both).build().unwrap();
+
+    let keypair = Keypair::random();
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    let options = RequestOptions {
+        timeout: Some(Duration::ZERO),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        client
+            .publish_with_options(&signed_packet, None, options.clone())
+            .await,
+        Err(PublishError::Query(QueryError::Timeout))
+    );
+
+    let options = RequestOptions {
+        cache: CachePolicy::Bypass,
+        ..options
+    };
+
+    assert_eq!(
+        client
+            .resolve_with_options(&keypair.public_key(), options)
+            .await,
+        None
+    );
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn negative_ttl(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+    let b = builder(&relay, &testnet, networks)
+        .negative_ttl(60)
+        .build()
+        .unwrap();
+
+    let keypair = Keypair::random();
+    let cache_key = keypair.public_key().into();
+
+    assert_eq!(b.resolve(&keypair.public_key()).await, None);
+    assert!(b.cache().unwrap().get_miss(&cache_key).is_some());
+
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&signed_packet, None).await.unwrap();
+
+    // Still a recent miss, so the network is not queried.
+    assert_eq!(b.resolve(&keypair.public_key()).await, None);
+
+    let resolved = b.resolve_most_recent(&keypair.public_key()).await.unwrap();
+
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+    assert!(b.cache().unwrap().get_miss(&cache_key).is_none());
+
+    let resolved = b.resolve(&keypair.public_key()).await.unwrap();
+
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+
+    // Misses are not recorded without a negative TTL.
+    let keypair = Keypair::random();
+
+    assert_eq!(a.resolve(&keypair.public_key()).await, None);
+    assert!(a
+        .cache()
+        .unwrap()
+        .get_miss(&keypair.public_key().into())
+        .is_none());
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn negative_ttl_skips_network() {
+    use axum::http::StatusCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (relay, relay_hits) = stub_relay(StatusCode::NOT_FOUND, Duration::ZERO).await;
+
+    // A Dht bootstrapping node that counts `get` queries and ignores every request.
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bootstrap = socket.local_addr().unwrap().to_string();
+    let dht_hits = Arc::new(AtomicUsize::new(0));
+    tokio::spawn({
+        let dht_hits = dht_hits.clone();
+
+        async move {
+            let mut buf = [0; 2048];
+            while let Ok((len, _)) = socket.recv_from(&mut buf).await {
+                if buf[..len].windows(8).any(|w| w == b"1:q3:get") {
+                    dht_hits.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+    });
+
+    let client = Client::builder()
+        .no_default_network()
+        .bootstrap(&[bootstrap])
+        .relays(&[relay.as_str()])
+        .unwrap()
+        .request_timeout(Duration::from_millis(100))
+        .negative_ttl(60)
+        .build()
+        .unwrap();
+
+    let public_key = Keypair::random().public_key();
+
+    assert_eq!(client.resolve(&public_key).await, None);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let requests = (
+        relay_hits.load(Ordering::SeqCst),
+        dht_hits.load(Ordering::SeqCst),
+    );
+    assert!(requests.0 > 0);
+    assert!(requests.1 > 0);
+
+    for _ in 0..3 {
+        assert_eq!(client.resolve(&public_key).await, None);
+    }
+
+    // A fresh cached packet is not refreshed from the network either.
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&Keypair::random())
+        .unwrap();
+    client
+        .cache()
+        .unwrap()
+        .put(&signed_packet.public_key().into(), &signed_packet);
+
+    let resolved = client.resolve(&signed_packet.public_key()).await.unwrap();
+
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert_eq!(
+        (
+            relay_hits.load(Ordering::SeqCst),
+            dht_hits.load(Ordering::SeqCst),
+        ),
+        requests
+    );
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn prefetch(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+    let b = builder(&relay, &testnet, networks)
+        .minimum_ttl(0)
+        .prefetch_threshold(1.0)
+        .build()
+        .unwrap();
+
+    assert!(a.prefetch_stats().is_none());
+
+    let keypair = Keypair::random();
+
+    let first = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&first, None).await.unwrap();
+
+    let resolved = b.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(resolved.as_bytes(), first.as_bytes());
+    assert_eq!(b.prefetch_stats().unwrap().started, 0);
+
+    // Wait for the first query to finish, otherwise the Dht node
+    // would return its responses for the prefetch query too.
+    super::sleep(Duration::from_secs(2)).await;
+
+    let second = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "zar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&second, None).await.unwrap();
+
+    // Responds with the cached packet, while prefetching the more recent one.
+    let resolved = b.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(resolved.as_bytes(), first.as_bytes());
+
+    for _ in 0..100 {
+        if b.prefetch_stats().unwrap().inflight == 0 {
+            break;
+        }
+        super::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(
+        b.prefetch_stats().unwrap(),
+        crate::PrefetchStats {
+            started: 1,
+            updated: 1,
+            ..Default::default()
+        }
+    );
+
+    let resolved = b.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(resolved.as_bytes(), second.as_bytes());
+
+    // Bounded concurrency
+    let c = builder(&relay, &testnet, networks)
+        .minimum_ttl(0)
+        .prefetch_threshold(1.0)
+        .max_concurrent_prefetches(0)
+        .build()
+        .unwrap();
+
+    c.resolve(&keypair.public_key()).await.unwrap();
+    c.resolve(&keypair.public_key()).await.unwrap();
+
+    assert_eq!(
+        c.prefetch_stats().unwrap(),
+        crate::PrefetchStats {
+            skipped: 1,
+            ..Default::default()
+        }
+    );
+
+    // Minimum hits
+    let d = builder(&relay, &testnet, networks)
+        .minimum_ttl(0)
+        .prefetch_threshold(1.0)
+        .prefetch_min_hits(3)
+        .max_concurrent_prefetches(0)
+        .build()
+        .unwrap();
+
+    d.resolve(&keypair.public_key()).await.unwrap();
+    d.resolve(&keypair.public_key()).await.unwrap();
+    d.resolve(&keypair.public_key()).await.unwrap();
+
+    assert_eq!(d.prefetch_stats().unwrap(), crate::PrefetchStats::default());
+
+    d.resolve(&keypair.public_key()).await.unwrap();
+
+    assert_eq!(
+        d.prefetch_stats().unwrap(),
+        crate::PrefetchStats {
+            skipped: 1,
+            ..Default::default()
+        }
+    );
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn discard_cache_with_zero_capacity() {
+    let testnet = crate::mainline::Testnet::new_async(2).await.unwrap();
+
+    // Create relay
+    let storage = std::env::temp_dir().join(Timestamp::now().to_string());
+    let mut builder = pkarr_relay::Relay::builder();
+    builder
+        .disable_rate_limiter()
+        .cache_size(0) // Comment this line out and it works
+        .http_port(0)
+        .storage(storage)
+        .pkarr(|builder| {
+            builder.no_default_network();
+            builder.bootstrap(&testnet.bootstrap);
+            builder
+        });
+    let relay = unsafe { builder.run().await.unwrap() };
+    let relay_url = relay.local_url();
+
+    let keypair = Keypair::random();
+
+    // Publish packet on the DHT without using the relay.
+    let client = Client::builder()
+        .no_default_network()
+        .bootstrap(&testnet.bootstrap)
+        .build()
+        .unwrap();
+
+    let signed_packet = SignedPacket::builder()
+        .txt(
+            "example.com".try_into().unwrap(),
+            "foo".try_into().unwrap(),
+            300,
+        )
+        .sign(&keypair)
+        .unwrap();
+    client.publish(&signed_packet, None).await.unwrap();
+
+    // Resolve packet with only the relay, no DHT
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[relay_url])
+        .unwrap()
+        .build()
+        .unwrap();
+    let packet = client.resolve(&keypair.public_key()).await;
+    assert!(
+        packet.is_some(),
+        "Published packet is not available over the relay only."
+    );
+}
+
+#[tokio::test]
+async fn regression_relay_timeout_stack_overflow() {
+    let host: crate::dns::Name = "example.com".try_into().unwrap();
+    let svcb = SVCB::new(0, host);
+    let signed_packet_builder =
+        SignedPacket::builder().https("_pubky".try_into().unwrap(), svcb.clone(), 60 * 60);
+
+    let client = Client::builder()
+        .no_dht()
+        .request_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+
+    // 1) do the resolve_most_recent
+    let existing = client
+        .resolve_most_recent(&Keypair::random().public_key())
+        .await;
+
+    // 2) build a new `_pubky` packet
+    let kp = Keypair::random();
+    let pkt = signed_packet_builder.clone().sign(&kp).unwrap();
+
+    // 3) publish with CAS
+    let cas = existing.map(|p| p.timestamp());
+    let _ = client.publish(&pkt, cas).await;
+}
+
+#[cfg(feature = "reqwest-builder")]
+mod reqwest_builder {
+    use super::*;
+
+    use std::{
+        net::{SocketAddr, TcpListener},
+        sync::Arc,
+    };
+
+    use axum::routing::get;
+    use axum::Router;
+    use axum_server::tls_rustls::RustlsConfig;
+
+    use crate::{dns::rdata::SVCB, Client, Keypair, SignedPacket};
+
+    async fn publish_server_pkarr(client: &Client, keypair: &Keypair, socket_addr: &SocketAddr) {
+        let mut svcb = SVCB::new(0, ".".try_into().unwrap());
+        svcb.set_port(socket_addr.port());
+
+        let signed_packet = SignedPacket::builder()
+            .https(".".try_into().unwrap(), svcb, 60 * 60)
+            .address(".".try_into().unwrap(), socket_addr.ip(), 60 * 60)
+            .sign(keypair)
+            .unwrap();
+
+        client.publish(&signed_packet, None).await.unwrap();
+    }
+
+    #[rstest]
+    #[case::dht(Networks::Dht)]
+    #[case::both_networks(Networks::Both)]
+    #[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+    #[tokio::test]
+    async fn reqwest_pkarr_domain(#[case] networks: Networks) {
+        let testnet = mainline::Testnet::new_async(5).await.unwrap();
+        let relay = Relay::run_test(&testnet).await.unwrap();
+
+        let keypair = Keypair::random();
+
+        {
+            // Run a server on Pkarr
+            let app = Router::new().route("/", get(|| async { "Hello, world!" }));
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap(); // Bind to any available port
+            let address = listener.local_addr().unwrap();
+
+            let client = builder(&relay, &testnet, networks).build().unwrap();
+            publish_server_pkarr(&client, &keypair, &address).await;
+
+            println!("Server running on https://{}", keypair.public_key());
+
+            let server = axum_server::from_tcp_rustls(
+                listener,
+                RustlsConfig::from_config(Arc::new((&keypair).into())),
+            );
+
+            tokio::spawn(server.serve(app.into_make_service()));
+        }
+
+        // Client setup
+        let pkarr_client = builder(&relay, &testnet, networks).build().unwrap();
+        let reqwest = reqwest::ClientBuilder::from(pkarr_client).build().unwrap();
+
+        // Make a request
+        let response = reqwest
+            .get(format!("https://{}", keypair.public_key()))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "Hello, world!");
+    }
+}
+
+mod republisher {
+    use super::*;
+
+    use crate::RepublishState;
+
+    fn client(testnet: &mainline::Testnet) -> Client {
+        let mut builder = Client::builder();
+
+        builder
+            .no_default_network()
+            .bootstrap(&testnet.bootstrap)
+            .request_timeout(Duration::from_millis(1000));
+
+        builder.build().unwrap()
+    }
+
+    /// Wait up to 30 seconds for the condition to be true.
+    fn wait_for(condition: impl Fn() -> bool) {
+        for _ in 0..300 {
+            if condition() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        panic!("timed out waiting for condition");
+    }
+
+    #[test]
+    fn republish() {
+        let testnet = mainline::Testnet::new(5).unwrap();
+
+        let keypair = Keypair::random();
+
+        let signed_packet = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign(&keypair)
+            .unwrap();
+
+        let republisher = client(&testnet)
+            .republisher()
+            .interval(Duration::from_millis(500))
+            .jitter(Duration::ZERO)
+            .build()
+            .unwrap();
+
+        republisher.add(signed_packet.clone()).unwrap();
+
+        let public_key = keypair.public_key();
+
+        wait_for(|| republisher.status(&public_key).unwrap().state == RepublishState::Republished);
+
+        let resolved = client(&testnet).as_blocking().resolve(&public_key).unwrap();
+
+        assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+
+        // Republishes again after the interval
+        let first_attempt = republisher.status(&public_key).unwrap().last_attempt;
+
+        wait_for(|| republisher.status(&public_key).unwrap().last_attempt > first_attempt);
+    }
+
+    #[test]
+    fn retry_failed_with_backoff() {
+        // No reachable Dht nodes, so every publish fails.
+        let client = Client::builder()
+            .no_default_network()
+            .bootstrap(&["127.0.0.1:1"])
+            .request_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let signed_packet = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign(&Keypair::random())
+            .unwrap();
+        let public_key = signed_packet.public_key();
+
+        let republisher = client
+            .republisher()
+            .interval(Duration::from_secs(3600))
+            .jitter(Duration::ZERO)
+            .retry_backoff(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        republisher.add(signed_packet).unwrap();
+
+        wait_for(|| {
+            matches!(
+                republisher.status(&public_key).unwrap().state,
+                RepublishState::Failed(_)
+            )
+        });
+
+        // Retried long before the interval.
+        let first_attempt = republisher.status(&public_key).unwrap().last_attempt;
+
+        wait_for(|| republisher.status(&public_key).unwrap().last_attempt > first_attempt);
+    }
+
+    #[test]
+    fn do_not_overwrite_more_recent_packet() {
+        let testnet = mainline::Testnet::new(5).unwrap();
+
+        let keypair = Keypair::random();
+        let public_key = keypair.public_key();
+
+        let builder =
+            SignedPacket::builder().txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30);
+
+        let t1 = Timestamp::now();
+        let t2 = Timestamp::now();
+
+        let older = builder.clone().timestamp(t1).sign(&keypair).unwrap();
+        let newer = builder.timestamp(t2).sign(&keypair).unwrap();
+
+        client(&testnet)
+            .as_blocking()
+            .publish(&newer, None)
+            .unwrap();
+
+        let republisher = client(&testnet)
+            .republisher()
+            .jitter(Duration::ZERO)
+            .build()
+            .unwrap();
+
+        republisher.add(older).unwrap();
+
+        wait_for(|| republisher.status(&public_key).unwrap().state != RepublishState::Pending);
+
+        assert_eq!(
+            republisher.status(&public_key).unwrap().state,
+            RepublishState::Superseded
+        );
+        assert_eq!(
+            republisher.get(&public_key).unwrap().as_bytes(),
+            newer.as_bytes()
+        );
+
+        let resolved = client(&testnet)
+            .as_blocking()
+            .resolve_most_recent(&public_key)
+            .unwrap();
+
+        assert_eq!(resolved.as_bytes(), newer.as_bytes());
+    }
+
+    #[test]
+    fn storage() {
+        let testnet = mainline::Testnet::new(5).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("republisher");
+
+        let packets = (0..3)
+            .map(|_| {
+                SignedPacket::builder()
+                    .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+                    .sign(&Keypair::random())
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        {
+            let republisher = client(&testnet)
+                .republisher()
+                .storage(&path)
+                .build()
+                .unwrap();
+
+            for packet in &packets {
+                republisher.add(packet.clone()).unwrap();
+            }
+
+            republisher.remove(&packets[2].public_key()).unwrap();
+        }
+
+        let republisher = client(&testnet)
+            .republisher()
+            .storage(&path)
+            .build()
+            .unwrap();
+
+        assert_eq!(republisher.len(), 2);
+
+        for packet in &packets[0..2] {
+            assert_eq!(
+                republisher.get(&packet.public_key()).unwrap().as_bytes(),
+                packet.as_bytes()
+            );
+        }
+        assert!(republisher.get(&packets[2].public_key()).is_none());
+    }
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
 #[tokio::test]
-async fn discard_cache_with_zero_capacity() {
-    let testnet = crate::mainline::Testnet::new_async(2).await.unwrap();
+async fn shutdown(#[case] networks: Networks) {
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let client = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    client.publish(&signed_packet, None).await.unwrap();
+
+    client.clone().shutdown();
+
+    assert!(client.is_shutdown());
+    assert!(client.dht().is_none());
+
+    assert_eq!(
+        client.publish(&signed_packet, None).await,
+        Err(PublishError::Query(QueryError::Cancelled))
+    );
+
+    // Only the cache is used.
+    assert_eq!(
+        client.resolve(&keypair.public_key()).await,
+        Some(signed_packet)
+    );
+    assert_eq!(client.resolve(&Keypair::random().public_key()).await, None);
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn shutdown_cancels_in_flight_requests() {
+    use axum::http::StatusCode;
+
+    let (url, hits) = stub_relay(StatusCode::NOT_FOUND, Duration::from_secs(10)).await;
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[url.as_str()])
+        .unwrap()
+        .request_timeout(Duration::from_secs(20))
+        .build()
+        .unwrap();
+
+    let resolving = tokio::spawn({
+        let client = client.clone();
+
+        async move {
+            client
+                .resolve_most_recent(&Keypair::random().public_key())
+                .await
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    client.shutdown();
+
+    let resolved = tokio::time::timeout(Duration::from_secs(1), resolving)
+        .await
+        .expect("cancelled before the relay responded")
+        .unwrap();
+
+    assert_eq!(resolved, None);
+    assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn cancellation_token() {
+    use axum::http::StatusCode;
+
+    use crate::CancellationToken;
+
+    let (url, _) = stub_relay(StatusCode::NO_CONTENT, Duration::from_secs(10)).await;
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&[url.as_str()])
+        .unwrap()
+        .request_timeout(Duration::from_secs(20))
+        .build()
+        .unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder().sign(&keypair).unwrap();
+
+    let token = CancellationToken::new();
+
+    let publishing = tokio::spawn({
+        let client = client.clone();
+        let token = token.clone();
+
+        async move {
+            client
+                .publish_with_cancellation(&signed_packet, None, &token)
+                .await
+        }
+    });
+    let resolving = tokio::spawn({
+        let client = client.clone();
+        let token = token.clone();
+
+        async move {
+            client
+                .resolve_most_recent_with_cancellation(&Keypair::random().public_key(), &token)
+                .await
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    token.cancel();
+
+    let (published, resolved) = tokio::time::timeout(Duration::from_secs(1), async {
+        (publishing.await.unwrap(), resolving.await.unwrap())
+    })
+    .await
+    .expect("cancelled before the relay responded");
+
+    assert_eq!(published, Err(PublishError::Query(QueryError::Cancelled)));
+    assert_eq!(resolved, None);
+
+    // The client itself isn't affected.
+    assert!(!client.is_shutdown());
+    assert!(client
+        .resolve_with_cancellation(&keypair.public_key(), &token)
+        .await
+        .is_none());
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn static_zone(#[case] networks: Networks) {
+    use futures_lite::StreamExt;
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let keypair = Keypair::random();
+
+    let published = SignedPacket::builder()
+        .txt(
+            "foo".try_into().unwrap(),
+            "published".try_into().unwrap(),
+            30,
+        )
+        .sign(&keypair)
+        .unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+    a.publish(&published, None).await.unwrap();
+
+    // Older than the published packet, yet still takes precedence.
+    let local = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "local".try_into().unwrap(), 30)
+        .timestamp(published.timestamp() - 1_000_000)
+        .sign(&keypair)
+        .unwrap();
+
+    let b = builder(&relay, &testnet, networks)
+        .static_zone(keypair.public_key(), local.clone())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    // Signed by a different key.
+    assert!(matches!(
+        Client::builder().static_zone(
+            Keypair::random().public_key(),
+            SignedPacket::builder().sign(&keypair).unwrap(),
+        ),
+        Err(crate::errors::StaticZonesError::KeyMismatch)
+    ));
+
+    let public_key = keypair.public_key();
+
+    assert_eq!(b.static_zone(&public_key), Some(local.clone()));
+
+    let resolved = b.resolve(&public_key).await.unwrap();
+    assert_eq!(resolved.as_bytes(), local.as_bytes());
+
+    let most_recent = b.resolve_most_recent(&public_key).await.unwrap();
+    assert_eq!(most_recent.as_bytes(), local.as_bytes());
+
+    let quorum = b
+        .resolve_with(&public_key, ResolveOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(quorum.packet.as_bytes(), local.as_bytes());
+
+    let stream = b.resolve_stream(&public_key).collect::<Vec<_>>().await;
+    assert_eq!(stream.len(), 1);
+    assert_eq!(stream[0].source, Source::Static);
+    assert_eq!(stream[0].packet.as_bytes(), local.as_bytes());
+
+    // Watching yields the static packet once, and never the published one.
+    let mut watch = b.watch(&public_key, Duration::from_millis(50));
+    assert_eq!(watch.next().await.unwrap().as_bytes(), local.as_bytes());
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), watch.next())
+            .await
+            .is_err()
+    );
+
+    // Static zones never reach the cache.
+    assert!(b.cache().unwrap().get(&public_key.clone().into()).is_none());
+}
+
+#[test]
+fn static_zones_only() {
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder().sign(&keypair).unwrap();
+
+    let client = Client::builder()
+        .no_default_network()
+        .static_zone(keypair.public_key(), signed_packet.clone())
+        .unwrap()
+        .build()
+        .unwrap()
+        .as_blocking();
+
+    let resolved = client.resolve(&keypair.public_key()).unwrap();
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+
+    assert_eq!(client.resolve(&Keypair::random().public_key()), None);
+}
+
+#[test]
+fn static_zones_dir() {
+    use crate::errors::StaticZonesError;
+
+    let dir = tempfile::tempdir().unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    std::fs::write(
+        dir.path().join(format!("{}.pkarr", keypair.public_key())),
+        signed_packet.to_relay_payload(),
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("README"), "not a packet").unwrap();
+
+    // A zone file signed with the keypair file next to it.
+    let zone_keypair = Keypair::random();
+    zone_keypair
+        .write_secret_key_file(&dir.path().join("example.key"))
+        .unwrap();
+    std::fs::write(
+        dir.path().join("example.zone"),
+        "; comment\n\
+         @    300 A    192.0.2.1\n\
+         www  300 AAAA 2001:db8::1\n\
+         api  300 CNAME www\n\
+         \n\
+         @    300 TXT  \"hello world\"\n",
+    )
+    .unwrap();
+
+    let client = Client::builder()
+        .no_default_network()
+        .static_zones_dir(dir.path())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let resolved = client.static_zone(&keypair.public_key()).unwrap();
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+
+    let zone = client.static_zone(&zone_keypair.public_key()).unwrap();
+    assert_eq!(zone.all_resource_records().count(), 4);
+    assert_eq!(zone.resource_records("www").count(), 1);
+    assert_eq!(zone.resource_records("api").count(), 1);
+
+    // An invalid record in a zone file.
+    std::fs::write(dir.path().join("example.zone"), "@ 300 MX mail\n").unwrap();
+
+    assert!(matches!(
+        Client::builder().static_zones_dir(dir.path()),
+        Err(StaticZonesError::InvalidZone { .. })
+    ));
+
+    std::fs::remove_file(dir.path().join("example.zone")).unwrap();
+
+    // A packet signed by a different key than its file name.
+    std::fs::write(
+        dir.path().join(Keypair::random().public_key().to_string()),
+        signed_packet.to_relay_payload(),
+    )
+    .unwrap();
+
+    assert!(matches!(
+        Client::builder().static_zones_dir(dir.path()),
+        Err(StaticZonesError::InvalidPacket { .. })
+    ));
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_proxy() {
+    use axum::http::StatusCode;
+    use std::sync::atomic::Ordering;
+
+    // Stands in for a forward proxy, answering on behalf of any relay.
+    let (proxy, proxy_hits) = stub_relay(StatusCode::NO_CONTENT, Duration::ZERO).await;
+
+    let client = Client::builder()
+        .no_default_network()
+        // Unreachable without the proxy.
+        .relays(&["http://relay.pkarr.invalid"])
+        .unwrap()
+        .relay_proxy(proxy.as_str())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder().sign(&keypair).unwrap();
+
+    client.publish(&signed_packet, None).await.unwrap();
+
+    assert_eq!(proxy_hits.load(Ordering::SeqCst), 1);
+
+    assert!(Client::builder().relay_proxy("not a url").is_err());
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_http_client() {
+    use axum::http::StatusCode;
+    use std::sync::atomic::Ordering;
 
-    // Create relay
-    let storage = std::env::temp_dir().join(Timestamp::now().to_string());
-    let mut builder = pkarr_relay::Relay::builder();
-    builder
-        .disable_rate_limiter()
-        .cache_size(0) // Comment this line out and it works
-        .http_port(0)
-        .storage(storage)
-        .pkarr(|builder| {
-            builder.no_default_network();
-            builder.bootstrap(&testnet.bootstrap);
-            builder
-        });
-    let relay = unsafe { builder.run().await.unwrap() };
-    let relay_url = relay.local_url();
+    let (proxy, proxy_hits) = stub_relay(StatusCode::NO_CONTENT, Duration::ZERO).await;
+    let (ignored_proxy, ignored_proxy_hits) =
+        stub_relay(StatusCode::NO_CONTENT, Duration::ZERO).await;
+
+    let http_client = reqwest::Client::builder()
+        .user_agent("pkarr-test")
+        .proxy(reqwest::Proxy::all(proxy.as_str()).unwrap())
+        .build()
+        .unwrap();
+
+    let client = Client::builder()
+        .no_default_network()
+        .relays(&["http://relay.pkarr.invalid"])
+        .unwrap()
+        .relay_proxy(ignored_proxy.as_str())
+        .unwrap()
+        .relay_http_client(http_client)
+        .build()
+        .unwrap();
 
     let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder().sign(&keypair).unwrap();
+
+    client.publish(&signed_packet, None).await.unwrap();
+
+    assert_eq!(proxy_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(ignored_proxy_hits.load(Ordering::SeqCst), 0);
+}
+
+#[cfg(feature = "relays")]
+#[tokio::test]
+async fn relay_transport() {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use bytes::Bytes;
+    use reqwest::{header::HeaderMap, StatusCode};
+    use url::Url;
+
+    use crate::{RelayResponse, RelayTransport, RelayTransportFuture};
+
+    #[derive(Debug, Default)]
+    /// An in-process relay, storing payloads by Url.
+    struct InProcess {
+        payloads: Mutex<HashMap<Url, Bytes>>,
+        cas: Mutex<Vec<String>>,
+    }
+
+    impl RelayTransport for InProcess {
+        fn get(&self, url: Url, _: HeaderMap, _: Duration) -> RelayTransportFuture<'_> {
+            let body = self.payloads.lock().unwrap().get(&url).cloned();
+
+            Box::pin(async move {
+                Ok(RelayResponse {
+                    status: if body.is_some() {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::NOT_FOUND
+                    },
+                    headers: HeaderMap::new(),
+                    body: body.unwrap_or_default(),
+                })
+            })
+        }
+
+        fn put(
+            &self,
+            url: Url,
+            headers: HeaderMap,
+            body: Bytes,
+            _: Duration,
+        ) -> RelayTransportFuture<'_> {
+            if let Some(cas) = headers.get(reqwest::header::IF_MATCH) {
+                self.cas
+                    .lock()
+                    .unwrap()
+                    .push(cas.to_str().unwrap().to_string());
+            }
+            self.payloads.lock().unwrap().insert(url, body);
+
+            Box::pin(async {
+                Ok(RelayResponse {
+                    status: StatusCode::NO_CONTENT,
+                    headers: HeaderMap::new(),
+                    body: Bytes::new(),
+                })
+            })
+        }
+    }
+
+    let transport = Arc::new(InProcess::default());
 
-    // Publish packet on the DHT without using the relay.
     let client = Client::builder()
         .no_default_network()
-        .bootstrap(&testnet.bootstrap)
+        .relays(&["http://relay.pkarr.invalid"])
+        .unwrap()
+        .relay_transport(transport.clone())
         .build()
         .unwrap();
 
-    let signed_packet = SignedPacket::builder()
-        .txt(
-            "example.com".try_into().unwrap(),
-            "foo".try_into().unwrap(),
-            300,
-        )
+    let keypair = Keypair::random();
+    let first = SignedPacket::builder().sign(&keypair).unwrap();
+    client.publish(&first, None).await.unwrap();
+
+    let second = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .timestamp(first.timestamp() + 1)
         .sign(&keypair)
         .unwrap();
-    client.publish(&signed_packet, None).await.unwrap();
+    client
+        .publish(&second, Some(first.timestamp()))
+        .await
+        .unwrap();
 
-    // Resolve packet with only the relay, no DHT
-    let client = Client::builder()
+    assert_eq!(
+        *transport.cas.lock().unwrap(),
+        vec![first.timestamp().as_u64().to_string()]
+    );
+
+    let resolver = Client::builder()
         .no_default_network()
-        .relays(&[relay_url])
+        .relays(&["http://relay.pkarr.invalid"])
         .unwrap()
+        .relay_transport(transport)
         .build()
         .unwrap();
-    let packet = client.resolve(&keypair.public_key()).await;
-    assert!(
-        packet.is_some(),
-        "Published packet is not available over the relay only."
+
+    let resolved = resolver.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(resolved.as_bytes(), second.as_bytes());
+
+    assert_eq!(
+        resolver.resolve(&Keypair::random().public_key()).await,
+        None
     );
 }
 
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
 #[tokio::test]
-async fn regression_relay_timeout_stack_overflow() {
-    let host: crate::dns::Name = "example.com".try_into().unwrap();
-    let svcb = SVCB::new(0, host);
-    let signed_packet_builder =
-        SignedPacket::builder().https("_pubky".try_into().unwrap(), svcb.clone(), 60 * 60);
+async fn async_cache(#[case] networks: Networks) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
-    let client = Client::builder()
-        .no_dht()
-        .request_timeout(Duration::from_millis(100))
+    use crate::{AsyncCache, Cache, CacheFuture, CacheKey, InMemoryCache};
+
+    #[derive(Debug)]
+    /// A cache that answers asynchronously, like a remote cache would.
+    struct Remote {
+        inner: InMemoryCache,
+        requests: AtomicUsize,
+    }
+
+    impl Remote {
+        async fn request<T>(&self, operation: impl FnOnce(&InMemoryCache) -> T) -> T {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+
+            operation(&self.inner)
+        }
+    }
+
+    impl AsyncCache for Remote {
+        fn capacity(&self) -> usize {
+            Cache::capacity(&self.inner)
+        }
+
+        fn len(&self) -> CacheFuture<'_, usize> {
+            Box::pin(self.request(Cache::len))
+        }
+
+        fn put<'a>(
+            &'a self,
+            key: &'a CacheKey,
+            signed_packet: &'a SignedPacket,
+        ) -> CacheFuture<'a, ()> {
+            Box::pin(self.request(move |inner| Cache::put(inner, key, signed_packet)))
+        }
+
+        fn get<'a>(&'a self, key: &'a CacheKey) -> CacheFuture<'a, Option<SignedPacket>> {
+            Box::pin(self.request(move |inner| Cache::get(inner, key)))
+        }
+    }
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&signed_packet, None).await.unwrap();
+
+    let remote = Arc::new(Remote {
+        inner: InMemoryCache::new(10.try_into().unwrap()),
+        requests: AtomicUsize::new(0),
+    });
+
+    let b = builder(&relay, &testnet, networks)
+        .async_cache(remote.clone())
         .build()
         .unwrap();
 
-    // 1) do the resolve_most_recent
-    let existing = client
-        .resolve_most_recent(&Keypair::random().public_key())
-        .await;
+    assert!(b.cache().is_none());
 
-    // 2) build a new `_pubky` packet
-    let kp = Keypair::random();
-    let pkt = signed_packet_builder.clone().sign(&kp).unwrap();
+    let resolved = b.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
 
-    // 3) publish with CAS
-    let cas = existing.map(|p| p.timestamp());
-    let _ = client.publish(&pkt, cas).await;
+    let cache = b.async_cache().unwrap();
+    assert_eq!(cache.len().await, 1);
+    assert_eq!(
+        cache
+            .get(&keypair.public_key().into())
+            .await
+            .unwrap()
+            .as_bytes(),
+        signed_packet.as_bytes()
+    );
+
+    let from_cache = b
+        .resolve_with_options(
+            &keypair.public_key(),
+            RequestOptions {
+                cache: CachePolicy::Only,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(from_cache.as_bytes(), signed_packet.as_bytes());
+
+    assert!(remote.requests.load(Ordering::SeqCst) > 0);
 }
 
-#[cfg(feature = "reqwest-builder")]
-mod reqwest_builder {
-    use super::*;
+#[cfg(feature = "lmdb-cache")]
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn lmdb_cache(#[case] networks: Networks) {
+    use crate::{extra::lmdb_cache::LmdbCache, Cache};
 
-    use std::{
-        net::{SocketAddr, TcpListener},
-        sync::Arc,
-    };
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
 
-    use axum::routing::get;
-    use axum::Router;
-    use axum_server::tls_rustls::RustlsConfig;
+    let a = builder(&relay, &testnet, networks).build().unwrap();
 
-    use crate::{dns::rdata::SVCB, Client, Keypair, SignedPacket};
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
 
-    async fn publish_server_pkarr(client: &Client, keypair: &Keypair, socket_addr: &SocketAddr) {
-        let mut svcb = SVCB::new(0, ".".try_into().unwrap());
-        svcb.set_port(socket_addr.port());
+    a.publish(&signed_packet, None).await.unwrap();
 
-        let signed_packet = SignedPacket::builder()
-            .https(".".try_into().unwrap(), svcb, 60 * 60)
-            .address(".".try_into().unwrap(), socket_addr.ip(), 60 * 60)
-            .sign(keypair)
-            .unwrap();
+    let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+    let cache = Arc::new(LmdbCache::open_unsafe(&env_path, 10).unwrap());
+    assert!(cache.is_blocking());
 
-        client.publish(&signed_packet, None).await.unwrap();
-    }
+    let b = builder(&relay, &testnet, networks)
+        .cache(cache.clone())
+        .build()
+        .unwrap();
 
-    #[rstest]
-    #[case::dht(Networks::Dht)]
-    #[case::both_networks(Networks::Both)]
-    #[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
-    #[tokio::test]
-    async fn reqwest_pkarr_domain(#[case] networks: Networks) {
-        let testnet = mainline::Testnet::new_async(5).await.unwrap();
-        let relay = Relay::run_test(&testnet).await.unwrap();
+    let resolved = b.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
 
-        let keypair = Keypair::random();
+    assert_eq!(Cache::len(cache.as_ref()), 1);
+    assert_eq!(b.async_cache().unwrap().len().await, 1);
 
-        {
-            // Run a server on Pkarr
-            let app = Router::new().route("/", get(|| async { "Hello, world!" }));
-            let listener = TcpListener::bind("127.0.0.1:0").unwrap(); // Bind to any available port
-            let address = listener.local_addr().unwrap();
+    let from_cache = b.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(from_cache.as_bytes(), signed_packet.as_bytes());
+}
 
-            let client = builder(&relay, &testnet, networks).build().unwrap();
-            publish_server_pkarr(&client, &keypair, &address).await;
+#[cfg(feature = "lmdb-cache")]
+#[test]
+fn lmdb_cache_runtime_shutdown() {
+    use crate::{extra::lmdb_cache::LmdbCache, AsyncCache, Cache, CacheKey};
 
-            println!("Server running on https://{}", keypair.public_key());
+    let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+    let cache = LmdbCache::open_unsafe(&env_path, 10).unwrap();
 
-            let server = axum_server::from_tcp_rustls(
-                listener,
-                RustlsConfig::from_config(Arc::new((&keypair).into())),
-            );
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+    let key = CacheKey::from(keypair.public_key());
 
-            tokio::spawn(server.serve(app.into_make_service()));
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let handle = runtime.handle().clone();
+
+    // Blocking tasks spawned on a runtime that is shutting down are cancelled.
+    runtime.shutdown_background();
+
+    let put = {
+        let _guard = handle.enter();
+
+        AsyncCache::put(&cache, &key, &signed_packet)
+    };
+
+    futures_lite::future::block_on(put);
+
+    assert_eq!(
+        Cache::get(&cache, &key).map(|cached| cached.as_bytes().clone()),
+        Some(signed_packet.as_bytes().clone())
+    );
+}
+
+#[rstest]
+#[case::dht(Networks::Dht)]
+#[case::both_networks(Networks::Both)]
+#[cfg_attr(feature = "relays", case::relays(Networks::Relays))]
+#[tokio::test]
+async fn cache_management(#[case] networks: Networks) {
+    use crate::{CacheKey, CacheStats};
+
+    let testnet = mainline::Testnet::new_async(5).await.unwrap();
+    let relay = Relay::run_test(&testnet).await.unwrap();
+
+    let a = builder(&relay, &testnet, networks).build().unwrap();
+
+    let keypair = Keypair::random();
+    let signed_packet = SignedPacket::builder()
+        .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+        .sign(&keypair)
+        .unwrap();
+
+    a.publish(&signed_packet, None).await.unwrap();
+
+    let b = builder(&relay, &testnet, networks).build().unwrap();
+    let cache = b.cache().unwrap();
+
+    // Each resolve is counted once.
+    b.resolve(&keypair.public_key()).await.unwrap();
+    b.resolve(&keypair.public_key()).await.unwrap();
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 1,
+            misses: 1,
+            evictions: 0,
         }
+    );
 
-        // Client setup
-        let pkarr_client = builder(&relay, &testnet, networks).build().unwrap();
-        let reqwest = reqwest::ClientBuilder::from(pkarr_client).build().unwrap();
+    let key = CacheKey::from(keypair.public_key());
 
-        // Make a request
-        let response = reqwest
-            .get(format!("https://{}", keypair.public_key()))
-            .send()
-            .await
-            .unwrap();
+    let entries = cache.iter().collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].0, key);
+    assert_eq!(entries[0].1.as_bytes(), signed_packet.as_bytes());
 
-        assert_eq!(response.status(), reqwest::StatusCode::OK);
-        assert_eq!(response.text().await.unwrap(), "Hello, world!");
-    }
+    // Evict the packet.
+    assert_eq!(
+        cache.remove(&key).unwrap().as_bytes(),
+        signed_packet.as_bytes()
+    );
+
+    // Removing a key forgets its recorded miss too.
+    let missing = CacheKey::from(Keypair::random().public_key());
+    cache.put_miss(&missing);
+    assert!(cache.remove(&missing).is_none());
+    assert!(cache.get_miss(&missing).is_none());
+
+    let only_cache = RequestOptions {
+        cache: CachePolicy::Only,
+        ..Default::default()
+    };
+    assert!(b
+        .resolve_with_options(&keypair.public_key(), only_cache.clone())
+        .await
+        .is_none());
+
+    b.resolve(&keypair.public_key()).await.unwrap();
+    assert_eq!(cache.len(), 1);
+
+    cache.clear();
+
+    assert!(cache.is_empty());
+    assert!(b
+        .resolve_with_options(&keypair.public_key(), only_cache)
+        .await
+        .is_none());
 }
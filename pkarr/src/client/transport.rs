@@ -0,0 +1,165 @@
+//! Pluggable HTTP transport for requests to [Relays](https://pkarr.org/relays),
+//! see [crate::ClientBuilder::relay_transport].
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::{header::HeaderMap, StatusCode};
+use url::Url;
+
+use crate::SignedPacket;
+
+/// Maximum size of a response body read by [ReqwestTransport], the size of the largest
+/// valid relay payload, so a misbehaving relay can't make the client buffer a large body.
+pub const MAX_RESPONSE_BYTES: u64 = SignedPacket::MAX_BYTES;
+
+#[derive(Debug, Clone)]
+/// Response of a [Relay](https://pkarr.org/relays) to a [RelayTransport] request.
+pub struct RelayResponse {
+    /// Status code of the response.
+    pub status: StatusCode,
+    /// Headers of the response.
+    pub headers: HeaderMap,
+    /// The full body of the response.
+    pub body: Bytes,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+/// Errors returned by a [RelayTransport] when no response was received.
+pub enum RelayTransportError {
+    #[error("Relay request timed out")]
+    /// The request didn't complete within its timeout.
+    Timeout,
+
+    #[error("Relay request failed: {0}")]
+    /// Failed to connect, send the request, or read the response.
+    Request(String),
+
+    #[error("Relay response body is larger than {MAX_RESPONSE_BYTES} bytes")]
+    /// The response body is larger than [MAX_RESPONSE_BYTES].
+    BodyTooLarge,
+}
+
+#[cfg(not(wasm_browser))]
+/// Future returned by [RelayTransport] methods.
+pub type RelayTransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<RelayResponse, RelayTransportError>> + Send + 'a>>;
+#[cfg(wasm_browser)]
+/// Future returned by [RelayTransport] methods.
+pub type RelayTransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<RelayResponse, RelayTransportError>> + 'a>>;
+
+/// An HTTP client for sending relay payloads to, and fetching them from,
+/// [Relays](https://pkarr.org/relays).
+///
+/// Implementations only need to send the request and return the response, whatever its status;
+/// building the Urls and headers, interpreting the status codes, verifying packets,
+/// retries and hedging are all done by the [crate::Client].
+///
+/// Implementations should stop reading the body, and return [RelayTransportError::BodyTooLarge],
+/// once it exceeds [MAX_RESPONSE_BYTES].
+///
+/// Defaults to [ReqwestTransport].
+pub trait RelayTransport: Debug + Send + Sync {
+    /// Send a `GET` request to the `url` with these `headers`,
+    /// failing with [RelayTransportError::Timeout] after `timeout`.
+    fn get(&self, url: Url, headers: HeaderMap, timeout: Duration) -> RelayTransportFuture<'_>;
+
+    /// Send a `PUT` request with the `body` to the `url` with these `headers`,
+    /// failing with [RelayTransportError::Timeout] after `timeout`.
+    fn put(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        body: Bytes,
+        timeout: Duration,
+    ) -> RelayTransportFuture<'_>;
+}
+
+#[derive(Debug, Clone, Default)]
+/// The default [RelayTransport], using a [reqwest::Client].
+pub struct ReqwestTransport(reqwest::Client);
+
+impl ReqwestTransport {
+    /// Create a transport sending requests with this [reqwest::Client].
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self(http_client)
+    }
+}
+
+impl RelayTransport for ReqwestTransport {
+    fn get(&self, url: Url, headers: HeaderMap, timeout: Duration) -> RelayTransportFuture<'_> {
+        Box::pin(send(self.0.get(url).headers(headers).timeout(timeout)))
+    }
+
+    fn put(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        body: Bytes,
+        timeout: Duration,
+    ) -> RelayTransportFuture<'_> {
+        Box::pin(send(
+            self.0.put(url).headers(headers).body(body).timeout(timeout),
+        ))
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder) -> Result<RelayResponse, RelayTransportError> {
+    #[cfg_attr(wasm_browser, allow(unused_mut))]
+    let mut response = request.send().await.map_err(map_reqwest_error)?;
+
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_RESPONSE_BYTES)
+    {
+        return Err(RelayTransportError::BodyTooLarge);
+    }
+
+    let status = response.status();
+    let headers = response.headers().clone();
+
+    // Stop reading as soon as the body exceeds the limit, even without a Content-Length.
+    #[cfg(not(wasm_browser))]
+    let body = {
+        let mut body = bytes::BytesMut::new();
+
+        while let Some(chunk) = response.chunk().await.map_err(map_reqwest_error)? {
+            if (body.len() + chunk.len()) as u64 > MAX_RESPONSE_BYTES {
+                return Err(RelayTransportError::BodyTooLarge);
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        body.freeze()
+    };
+    // Browsers already buffer the body, so only check its size.
+    #[cfg(wasm_browser)]
+    let body = {
+        let body = response.bytes().await.map_err(map_reqwest_error)?;
+
+        if body.len() as u64 > MAX_RESPONSE_BYTES {
+            return Err(RelayTransportError::BodyTooLarge);
+        }
+
+        body
+    };
+
+    Ok(RelayResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn map_reqwest_error(error: reqwest::Error) -> RelayTransportError {
+    if error.is_timeout() {
+        RelayTransportError::Timeout
+    } else {
+        RelayTransportError::Request(error.to_string())
+    }
+}
@@ -0,0 +1,128 @@
+//! Read-modify-write updates of a [SignedPacket], see [Client::update].
+
+use crate::errors::SignedPacketBuildError;
+use crate::{Keypair, SignedPacket, SignedPacketBuilder};
+
+use super::{async_compat_if_necessary, sleep, Client, PublishError, RetryPolicy};
+
+/// Default maximum number of attempts of [Client::update], including the first one.
+pub const DEFAULT_UPDATE_ATTEMPTS: u32 = 3;
+
+#[derive(thiserror::Error, Debug)]
+/// Errors returned by [Client::update].
+pub enum UpdateError {
+    #[error(transparent)]
+    /// Failed to sign the [SignedPacketBuilder] returned from the update closure.
+    Build(#[from] SignedPacketBuildError),
+
+    #[error(transparent)]
+    /// Publishing failed, either with a [crate::errors::QueryError],
+    /// or with a [crate::errors::ConcurrencyError] after all attempts.
+    Publish(#[from] PublishError),
+}
+
+impl Client {
+    /// Resolves the most recent [SignedPacket] of the `keypair`'s [crate::PublicKey],
+    /// passes it (or `None` if not found) to `update` to author the new packet,
+    /// then signs and publishes it using the resolved packet's timestamp as a `CAS`.
+    ///
+    /// If publishing fails with a [crate::errors::ConcurrencyError], the loop is repeated
+    /// from resolving, up to [DEFAULT_UPDATE_ATTEMPTS] times, so `update` may be called
+    /// more than once and should not have side effects.
+    ///
+    /// Returns the published [SignedPacket].
+    ///
+    /// This is the recommended way to avoid lost updates, as described in [Self::publish].
+    ///
+    /// ```rust
+    /// use pkarr::{Client, SignedPacket, Keypair};
+    /// // For local testing
+    /// use pkarr::mainline::Testnet;
+    ///
+    /// #[tokio::main]
+    /// async fn run() -> anyhow::Result<()> {
+    ///     let testnet = Testnet::new_async(3).await?;
+    ///     let client = Client::builder()
+    ///         .no_default_network()
+    ///         .bootstrap(&testnet.bootstrap)
+    ///         .build()?;
+    ///
+    ///     let keypair = Keypair::random();
+    ///
+    ///     client
+    ///         .update(&keypair, |current| {
+    ///             let mut builder = SignedPacket::builder();
+    ///
+    ///             // Keep every existing record except "foo".
+    ///             for record in current.iter().flat_map(|p| p.all_resource_records()) {
+    ///                 if record.name.to_string() != "foo" {
+    ///                     builder = builder.record(record.clone());
+    ///                 }
+    ///             }
+    ///
+    ///             builder.txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+    ///         })
+    ///         .await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn update<F>(&self, keypair: &Keypair, update: F) -> Result<SignedPacket, UpdateError>
+    where
+        F: FnMut(Option<&SignedPacket>) -> SignedPacketBuilder,
+    {
+        async_compat_if_necessary(self.update_inner(keypair, update)).await
+    }
+
+    async fn update_inner<F>(
+        &self,
+        keypair: &Keypair,
+        mut update: F,
+    ) -> Result<SignedPacket, UpdateError>
+    where
+        F: FnMut(Option<&SignedPacket>) -> SignedPacketBuilder,
+    {
+        let public_key = keypair.public_key();
+        let policy = RetryPolicy {
+            max_attempts: DEFAULT_UPDATE_ATTEMPTS,
+            ..Default::default()
+        };
+
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let current = self.resolve_most_recent(&public_key).await;
+            let builder = update(current.as_ref());
+
+            let mut signed_packet = builder.clone().sign_with_clock(keypair, self.clock())?;
+
+            if let Some(current) = &current {
+                // The new packet must be more recent, even if the current one's clock is ahead.
+                if !signed_packet.more_recent_than(current) {
+                    signed_packet = builder
+                        .timestamp(current.timestamp() + 1)
+                        .sign_with_clock(keypair, self.clock())?;
+                }
+            }
+
+            let cas = current.as_ref().map(|current| current.timestamp());
+
+            let error = match self.publish(&signed_packet, cas).await {
+                Ok(()) => return Ok(signed_packet),
+                Err(error @ PublishError::Concurrency(_)) => error,
+                Err(error) => return Err(error.into()),
+            };
+
+            match policy.backoff(attempt) {
+                Some(backoff) => {
+                    cross_debug!("Retrying update of {public_key} after {error}, in {backoff:?}");
+
+                    sleep(backoff).await;
+                }
+                None => return Err(error.into()),
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Watch a [PublicKey] for updates, see [Client::watch].
+
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_lite::{stream, Stream, StreamExt};
+
+use crate::{CacheKey, PublicKey, SignedPacket};
+
+use super::{
+    async_compat_if_necessary, filter_incoming_signed_packet, sleep, Client, RequestOptions,
+};
+
+/// Maximum multiple of the watch interval to back off to, while no updates are found.
+const MAX_BACKOFF_FACTOR: u32 = 8;
+
+#[cfg(not(wasm_browser))]
+/// A [Stream] of [SignedPacket]s, see [Client::watch].
+pub(crate) type WatchStream = Pin<Box<dyn Stream<Item = SignedPacket> + Send>>;
+#[cfg(wasm_browser)]
+/// A [Stream] of [SignedPacket]s, see [Client::watch].
+pub(crate) type WatchStream = Pin<Box<dyn Stream<Item = SignedPacket>>>;
+
+impl Client {
+    /// Returns an endless [Stream] of updates to the [SignedPacket] of the given [PublicKey].
+    ///
+    /// The stream starts with the most recent packet found in the cache or the network (if any),
+    /// then polls the network every `interval`, and yields only packets strictly more recent
+    /// than the last yielded one, see [SignedPacket::more_recent_than].
+    ///
+    /// Polling uses `If-Modified-Since` against [Relays](https://pkarr.org/relays),
+    /// and asks [mainline] Dht nodes only for more recent packets. Since `If-Modified-Since`
+    /// has a precision of one second, relays won't return an update published within the same
+    /// second as the last yielded packet.
+    ///
+    /// While no updates are found, the interval doubles after each poll up to
+    /// 8 times the given `interval`, and resets as soon as an update is found.
+    ///
+    /// A key with a [static zone](crate::ClientBuilder::static_zone) only yields
+    /// the static packet, without touching the network.
+    ///
+    /// Polling stops once the stream is dropped, and the stream ends once the client
+    /// is [shut down](Client::shutdown).
+    pub fn watch(&self, public_key: &PublicKey, interval: Duration) -> WatchStream {
+        let state = WatchState {
+            client: self.clone(),
+            public_key: public_key.clone(),
+            interval,
+            current_interval: interval,
+            last: None,
+        };
+
+        Box::pin(stream::unfold(state, |mut state| async move {
+            let next = async_compat_if_necessary(state.next()).await?;
+
+            Some((next, state))
+        }))
+    }
+}
+
+struct WatchState {
+    client: Client,
+    public_key: PublicKey,
+    interval: Duration,
+    current_interval: Duration,
+    last: Option<SignedPacket>,
+}
+
+impl WatchState {
+    /// Poll until a more recent packet than [Self::last] is found,
+    /// or return `None` once the client is shut down.
+    async fn next(&mut self) -> Option<SignedPacket> {
+        if self.last.is_some() {
+            self.sleep(self.current_interval).await?;
+        }
+
+        loop {
+            if self.client.is_shutdown() {
+                return None;
+            }
+
+            if let Some(packet) = self.poll().await {
+                self.current_interval = self.interval;
+                self.last = Some(packet.clone());
+
+                return Some(packet);
+            }
+
+            self.current_interval =
+                (self.current_interval * 2).min(self.interval * MAX_BACKOFF_FACTOR);
+
+            self.sleep(self.current_interval).await?;
+        }
+    }
+
+    /// Sleep for `duration`, or return `None` as soon as the client is shut down.
+    async fn sleep(&self, duration: Duration) -> Option<()> {
+        futures_lite::future::or(
+            async {
+                sleep(duration).await;
+
+                Some(())
+            },
+            async {
+                self.client.0.shutdown.cancelled().await;
+
+                None
+            },
+        )
+        .await
+    }
+
+    /// Returns the most recent packet found, if it is more recent than [Self::last].
+    async fn poll(&self) -> Option<SignedPacket> {
+        // A static zone never changes, so it is yielded once without touching the network.
+        if let Some(static_zone) = self.client.static_zone(&self.public_key) {
+            return Some(static_zone).filter(|_| self.last.is_none());
+        }
+
+        let cache_key: CacheKey = (&self.public_key).into();
+        let cache = self.client.0.async_cache.clone();
+
+        // Another resolution could have already cached a more recent packet.
+        let cached = match &cache {
+            Some(cache) => cache.get_read_only(&cache_key).await,
+            None => None,
+        };
+        let mut most_recent = cached.filter(|cached| self.is_more_recent(cached));
+
+        if most_recent.is_none() {
+            let mut stream = self.client.network_stream(
+                &self.public_key,
+                self.last.as_ref().map(|last| last.timestamp()),
+                &RequestOptions::default(),
+            );
+
+            while let Some(resolved) = stream.next().await {
+                let packet = resolved.packet;
+
+                if self.is_more_recent(&packet)
+                    && most_recent
+                        .as_ref()
+                        .is_none_or(|most_recent| packet.more_recent_than(most_recent))
+                {
+                    filter_incoming_signed_packet(
+                        &self.public_key,
+                        cache.clone(),
+                        &cache_key,
+                        packet.clone(),
+                    )
+                    .await;
+
+                    most_recent = Some(packet);
+                }
+            }
+        }
+
+        most_recent
+    }
+
+    fn is_more_recent(&self, packet: &SignedPacket) -> bool {
+        self.last
+            .as_ref()
+            .is_none_or(|last| packet.more_recent_than(last))
+    }
+}
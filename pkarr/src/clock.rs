@@ -0,0 +1,65 @@
+//! Source of the current time for TTL and expiry logic, see [Clock].
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ntimestamp::Timestamp;
+
+/// A source of the current time, used for [SignedPacket::last_seen](crate::SignedPacket::last_seen),
+/// expiry, and cache timestamps.
+///
+/// Defaults to the [SystemClock], use a [TestClock] to control time in tests,
+/// see `ClientBuilder::clock`.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Timestamp;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The [Clock] of the system, using [Timestamp::now].
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [Clock] that only moves when told to, see [Self::advance] and [Self::set].
+///
+/// Clones share the same time, so advancing any clone advances all of them.
+pub struct TestClock(Arc<AtomicU64>);
+
+impl TestClock {
+    /// Create a new clock starting at the given time.
+    pub fn new(start: Timestamp) -> Self {
+        Self(Arc::new(AtomicU64::new(start.as_u64())))
+    }
+
+    /// Move this clock forward.
+    pub fn advance(&self, duration: Duration) {
+        self.0
+            .fetch_add(duration.as_micros() as u64, Ordering::SeqCst);
+    }
+
+    /// Set this clock to the given time, even if it is in the past.
+    pub fn set(&self, timestamp: Timestamp) {
+        self.0.store(timestamp.as_u64(), Ordering::SeqCst);
+    }
+}
+
+impl Default for TestClock {
+    /// Starts at the current system time.
+    fn default() -> Self {
+        Self::new(Timestamp::now())
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::from(self.0.load(Ordering::SeqCst))
+    }
+}
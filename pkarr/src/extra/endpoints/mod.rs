@@ -246,6 +246,34 @@ mod tests {
         assert_eq!(endpoint.domain(), Some("example.com"));
     }
 
+    #[tokio::test]
+    async fn static_zone_without_network() {
+        let keypair = Keypair::random();
+
+        let mut svcb = SVCB::new(1, "example.com".try_into().unwrap());
+        svcb.set_port(8443);
+
+        let signed_packet = SignedPacket::builder()
+            .https(".".try_into().unwrap(), svcb, 3600)
+            .sign(&keypair)
+            .unwrap();
+
+        let client = Client::builder()
+            .no_default_network()
+            .static_zone(keypair.public_key(), signed_packet)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let endpoint = client
+            .resolve_https_endpoint(&keypair.public_key().to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(endpoint.domain(), Some("example.com"));
+        assert_eq!(endpoint.port(), Some(8443));
+    }
+
     #[tokio::test]
     async fn empty() {
         let testnet = Testnet::new_async(5).await.unwrap();
@@ -4,20 +4,23 @@ use std::{
     borrow::Cow,
     fmt::Debug,
     fs,
+    ops::Bound,
     path::Path,
     sync::{Arc, RwLock},
 };
 
 use byteorder::BigEndian;
 use heed::{
-    types::U64, BoxedError, BytesDecode, BytesEncode, Database, Env, EnvOpenOptions, RwTxn,
+    types::{Bytes, U64},
+    BoxedError, BytesDecode, BytesEncode, Database, Env, EnvOpenOptions, RoTxn, RwTxn,
 };
 
 use tracing::debug;
 
 use ntimestamp::Timestamp;
 
-use crate::{Cache, CacheKey, SignedPacket};
+use crate::client::cache::CacheCounters;
+use crate::{Cache, CacheIter, CacheKey, CacheStats, Clock, SignedPacket, SystemClock};
 
 const MAX_MAP_SIZE: usize = 10995116277760; // 10 TB
 const MIN_MAP_SIZE: usize = 10 * 1024 * 1024; // 10 mb
@@ -25,10 +28,17 @@ const MIN_MAP_SIZE: usize = 10 * 1024 * 1024; // 10 mb
 const SIGNED_PACKET_TABLE: &str = "pkarrcache:signed_packet";
 const KEY_TO_TIME_TABLE: &str = "pkarrcache:key_to_time";
 const TIME_TO_KEY_TABLE: &str = "pkarrcache:time_to_key";
+const MISSES_TABLE: &str = "pkarrcache:misses";
+const MISS_TIME_TO_KEY_TABLE: &str = "pkarrcache:miss_time_to_key";
 
-type SignedPacketsTable = Database<CacheKeyCodec, SignedPacket>;
+/// Number of entries read in each transaction by [LmdbCache::iter].
+const ITER_CHUNK_SIZE: usize = 1000;
+
+/// Packets are decoded with [LmdbCache::decode], to use the cache's [Clock].
+type SignedPacketsTable = Database<CacheKeyCodec, Bytes>;
 type KeyToTimeTable = Database<CacheKeyCodec, U64<BigEndian>>;
 type TimeToKeyTable = Database<U64<BigEndian>, CacheKeyCodec>;
+type MissesTable = Database<CacheKeyCodec, U64<BigEndian>>;
 
 /// A wrapper for [CacheKey] to implement [BytesEncode] and [BytesDecode].
 pub struct CacheKeyCodec;
@@ -36,7 +46,7 @@ pub struct CacheKeyCodec;
 impl BytesEncode<'_> for CacheKeyCodec {
     type EItem = CacheKey;
 
-    fn bytes_encode(key: &Self::EItem) -> Result<Cow<[u8]>, BoxedError> {
+    fn bytes_encode(key: &Self::EItem) -> Result<Cow<'_, [u8]>, BoxedError> {
         Ok(Cow::Owned(key.to_vec()))
     }
 }
@@ -53,7 +63,7 @@ impl<'a> BytesDecode<'a> for CacheKeyCodec {
 impl BytesEncode<'_> for SignedPacket {
     type EItem = SignedPacket;
 
-    fn bytes_encode(signed_packet: &Self::EItem) -> Result<Cow<[u8]>, BoxedError> {
+    fn bytes_encode(signed_packet: &Self::EItem) -> Result<Cow<'_, [u8]>, BoxedError> {
         Ok(Cow::Owned(signed_packet.serialize().to_vec()))
     }
 }
@@ -74,7 +84,11 @@ pub struct LmdbCache {
     signed_packets_table: SignedPacketsTable,
     key_to_time_table: KeyToTimeTable,
     time_to_key_table: TimeToKeyTable,
+    misses_table: MissesTable,
+    miss_time_to_key_table: TimeToKeyTable,
     batch: Arc<RwLock<Vec<CacheKey>>>,
+    clock: Arc<dyn Clock>,
+    counters: Arc<CacheCounters>,
 }
 
 impl Debug for LmdbCache {
@@ -111,7 +125,7 @@ impl LmdbCache {
         let env = unsafe {
             EnvOpenOptions::new()
                 .map_size(map_size)
-                .max_dbs(3)
+                .max_dbs(5)
                 .open(env_path)?
         };
 
@@ -123,6 +137,9 @@ impl LmdbCache {
             env.create_database(&mut wtxn, Some(KEY_TO_TIME_TABLE))?;
         let time_to_key_table: TimeToKeyTable =
             env.create_database(&mut wtxn, Some(TIME_TO_KEY_TABLE))?;
+        let misses_table: MissesTable = env.create_database(&mut wtxn, Some(MISSES_TABLE))?;
+        let miss_time_to_key_table: TimeToKeyTable =
+            env.create_database(&mut wtxn, Some(MISS_TIME_TO_KEY_TABLE))?;
 
         wtxn.commit()?;
 
@@ -132,12 +149,23 @@ impl LmdbCache {
             signed_packets_table,
             key_to_time_table,
             time_to_key_table,
+            misses_table,
+            miss_time_to_key_table,
             batch: Arc::new(RwLock::new(vec![])),
+            clock: Arc::new(SystemClock),
+            counters: Arc::new(CacheCounters::default()),
         };
 
         Ok(instance)
     }
 
+    /// Use a custom [Clock] for the LRU order and the time of recorded misses.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
     /// Convenient wrapper around [Self::open].
     ///
     /// Make sure to read the safety section in [Self::open]
@@ -145,6 +173,13 @@ impl LmdbCache {
         unsafe { Self::open(env_path, capacity) }
     }
 
+    /// Deserialize a stored [SignedPacket], checking its [SignedPacket::last_seen]
+    /// against the cache's [Clock] instead of the system time.
+    fn decode(&self, bytes: &[u8]) -> Result<SignedPacket, heed::Error> {
+        SignedPacket::deserialize_at(bytes, self.clock.now())
+            .map_err(|error| heed::Error::Decoding(Box::new(error)))
+    }
+
     fn internal_len(&self) -> Result<usize, heed::Error> {
         let rtxn = self.env.read_txn()?;
         let len = self.signed_packets_table.len(&rtxn)? as usize;
@@ -169,7 +204,14 @@ impl LmdbCache {
         let time_to_key = self.time_to_key_table;
 
         let mut batch = self.batch.write().expect("LmdbCache::batch.write()");
-        update_lru(&mut wtxn, packets, key_to_time, time_to_key, &batch)?;
+        update_lru(
+            &mut wtxn,
+            packets,
+            key_to_time,
+            time_to_key,
+            &batch,
+            self.clock.as_ref(),
+        )?;
 
         let len = packets.len(&wtxn)? as usize;
 
@@ -184,6 +226,8 @@ impl LmdbCache {
                 time_to_key.delete(&mut wtxn, &time)?;
                 key_to_time.delete(&mut wtxn, &key)?;
                 packets.delete(&mut wtxn, &key)?;
+
+                self.counters.record_eviction();
             };
         }
 
@@ -193,12 +237,17 @@ impl LmdbCache {
             time_to_key.delete(&mut wtxn, &old_time)?;
         }
 
-        let new_time = Timestamp::now();
+        let new_time = unique_time(&wtxn, time_to_key, self.clock.as_ref())?;
 
-        time_to_key.put(&mut wtxn, &new_time.as_u64(), key)?;
-        key_to_time.put(&mut wtxn, key, &new_time.as_u64())?;
+        time_to_key.put(&mut wtxn, &new_time, key)?;
+        key_to_time.put(&mut wtxn, key, &new_time)?;
 
-        packets.put(&mut wtxn, key, signed_packet)?;
+        packets.put(&mut wtxn, key, &signed_packet.serialize())?;
+
+        if let Some(time) = self.misses_table.get(&wtxn, key)? {
+            self.misses_table.delete(&mut wtxn, key)?;
+            self.miss_time_to_key_table.delete(&mut wtxn, &time)?;
+        }
 
         wtxn.commit()?;
 
@@ -217,14 +266,167 @@ impl LmdbCache {
     fn internal_get_read_only(&self, key: &CacheKey) -> Result<Option<SignedPacket>, heed::Error> {
         let rtxn = self.env.read_txn()?;
 
-        if let Some(signed_packet) = self.signed_packets_table.get(&rtxn, key)? {
-            return Ok(Some(signed_packet));
+        if let Some(bytes) = self.signed_packets_table.get(&rtxn, key)? {
+            return Ok(Some(self.decode(bytes)?));
         }
 
         rtxn.commit()?;
 
         Ok(None)
     }
+
+    fn internal_put_miss(&self, key: &CacheKey) -> Result<(), heed::Error> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+
+        let mut wtxn = self.env.write_txn()?;
+
+        let misses = self.misses_table;
+        let time_to_key = self.miss_time_to_key_table;
+
+        if let Some(old_time) = misses.get(&wtxn, key)? {
+            time_to_key.delete(&mut wtxn, &old_time)?;
+        } else if misses.len(&wtxn)? as usize >= self.capacity {
+            debug!(?self.capacity, "Reached misses capacity, deleting the oldest miss.");
+
+            if let Some((time, oldest)) = time_to_key.first(&wtxn)? {
+                time_to_key.delete(&mut wtxn, &time)?;
+                misses.delete(&mut wtxn, &oldest)?;
+            }
+        }
+
+        let new_time = unique_time(&wtxn, time_to_key, self.clock.as_ref())?;
+
+        time_to_key.put(&mut wtxn, &new_time, key)?;
+        misses.put(&mut wtxn, key, &new_time)?;
+
+        wtxn.commit()?;
+
+        Ok(())
+    }
+
+    fn internal_get_miss(&self, key: &CacheKey) -> Result<Option<Timestamp>, heed::Error> {
+        let rtxn = self.env.read_txn()?;
+
+        let time = self.misses_table.get(&rtxn, key)?;
+
+        rtxn.commit()?;
+
+        Ok(time.map(Timestamp::from))
+    }
+
+    fn internal_remove(&self, key: &CacheKey) -> Result<Option<SignedPacket>, heed::Error> {
+        let mut wtxn = self.env.write_txn()?;
+
+        let signed_packet = match self.signed_packets_table.get(&wtxn, key)? {
+            Some(bytes) => Some(self.decode(bytes)?),
+            None => None,
+        };
+
+        if signed_packet.is_some() {
+            self.signed_packets_table.delete(&mut wtxn, key)?;
+
+            if let Some(time) = self.key_to_time_table.get(&wtxn, key)? {
+                self.time_to_key_table.delete(&mut wtxn, &time)?;
+                self.key_to_time_table.delete(&mut wtxn, key)?;
+            }
+        }
+
+        if let Some(time) = self.misses_table.get(&wtxn, key)? {
+            self.misses_table.delete(&mut wtxn, key)?;
+            self.miss_time_to_key_table.delete(&mut wtxn, &time)?;
+        }
+
+        wtxn.commit()?;
+
+        Ok(signed_packet)
+    }
+
+    fn internal_clear(&self) -> Result<(), heed::Error> {
+        let mut wtxn = self.env.write_txn()?;
+
+        self.signed_packets_table.clear(&mut wtxn)?;
+        self.key_to_time_table.clear(&mut wtxn)?;
+        self.time_to_key_table.clear(&mut wtxn)?;
+        self.misses_table.clear(&mut wtxn)?;
+        self.miss_time_to_key_table.clear(&mut wtxn)?;
+
+        self.batch
+            .write()
+            .expect("LmdbCache::batch.write()")
+            .clear();
+
+        wtxn.commit()?;
+
+        Ok(())
+    }
+
+    /// Returns up to [ITER_CHUNK_SIZE] entries with keys greater than `after`.
+    fn internal_iter_chunk(
+        &self,
+        after: Option<&CacheKey>,
+    ) -> Result<Vec<(CacheKey, SignedPacket)>, heed::Error> {
+        let rtxn = self.env.read_txn()?;
+
+        let range = (
+            after.map_or(Bound::Unbounded, Bound::Excluded),
+            Bound::Unbounded,
+        );
+
+        let chunk = self
+            .signed_packets_table
+            .range(&rtxn, &range)?
+            .take(ITER_CHUNK_SIZE)
+            .map(|entry| {
+                let (key, bytes) = entry?;
+
+                Ok((key, self.decode(bytes)?))
+            })
+            .collect::<Result<Vec<_>, heed::Error>>()?;
+
+        rtxn.commit()?;
+
+        Ok(chunk)
+    }
+}
+
+/// Iterator over the entries of a [LmdbCache], reading [ITER_CHUNK_SIZE] entries
+/// per transaction, so it doesn't hold a transaction open between calls to [Iterator::next].
+struct Iter<'a> {
+    cache: &'a LmdbCache,
+    last: Option<CacheKey>,
+    chunk: std::vec::IntoIter<(CacheKey, SignedPacket)>,
+    done: bool,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (CacheKey, SignedPacket);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.chunk.next() {
+                return Some(entry);
+            }
+
+            if self.done {
+                return None;
+            }
+
+            match self.cache.internal_iter_chunk(self.last.as_ref()) {
+                Ok(chunk) => {
+                    self.done = chunk.len() < ITER_CHUNK_SIZE;
+                    self.last = chunk.last().map(|(key, _)| *key);
+                    self.chunk = chunk.into_iter();
+                }
+                Err(error) => {
+                    debug!(?error, "Error in LmdbCache::iter");
+
+                    self.done = true;
+                }
+            }
+        }
+    }
 }
 
 fn update_lru(
@@ -233,6 +435,7 @@ fn update_lru(
     key_to_time: KeyToTimeTable,
     time_to_key: TimeToKeyTable,
     to_update: &[CacheKey],
+    clock: &dyn Clock,
 ) -> Result<(), heed::Error> {
     for key in to_update {
         if packets.get(wtxn, key)?.is_some() {
@@ -240,16 +443,32 @@ fn update_lru(
                 time_to_key.delete(wtxn, &time)?;
             };
 
-            let new_time = Timestamp::now();
+            let new_time = unique_time(wtxn, time_to_key, clock)?;
 
-            time_to_key.put(wtxn, &new_time.as_u64(), key)?;
-            key_to_time.put(wtxn, key, &new_time.as_u64())?;
+            time_to_key.put(wtxn, &new_time, key)?;
+            key_to_time.put(wtxn, key, &new_time)?;
         }
     }
 
     Ok(())
 }
 
+/// Returns the current time of the `clock`, or the closest later time that
+/// isn't already used in the `time_to_key` order, since a [crate::TestClock] may not move.
+fn unique_time(
+    rtxn: &RoTxn,
+    time_to_key: TimeToKeyTable,
+    clock: &dyn Clock,
+) -> Result<u64, heed::Error> {
+    let mut time = clock.now().as_u64();
+
+    while time_to_key.get(rtxn, &time)?.is_some() {
+        time += 1;
+    }
+
+    Ok(time)
+}
+
 impl Cache for LmdbCache {
     fn capacity(&self) -> usize {
         self.capacity
@@ -273,7 +492,11 @@ impl Cache for LmdbCache {
 
     fn get(&self, key: &CacheKey) -> Option<SignedPacket> {
         match self.internal_get(key) {
-            Ok(result) => result,
+            Ok(result) => {
+                self.counters.record_get(result.is_some());
+
+                result
+            }
             Err(error) => {
                 debug!(?error, "Error in LmdbCache::get");
 
@@ -292,6 +515,61 @@ impl Cache for LmdbCache {
             }
         }
     }
+
+    fn put_miss(&self, key: &CacheKey) {
+        if let Err(error) = self.internal_put_miss(key) {
+            debug!(?error, "Error in LmdbCache::put_miss");
+        };
+    }
+
+    fn get_miss(&self, key: &CacheKey) -> Option<Timestamp> {
+        match self.internal_get_miss(key) {
+            Ok(result) => result,
+            Err(error) => {
+                debug!(?error, "Error in LmdbCache::get_miss");
+
+                None
+            }
+        }
+    }
+    /// Reads and writes are disk transactions, so they are run on a blocking thread pool
+    /// when used as an [AsyncCache](crate::AsyncCache).
+    fn is_blocking(&self) -> bool {
+        true
+    }
+
+    fn remove(&self, key: &CacheKey) -> Option<SignedPacket> {
+        match self.internal_remove(key) {
+            Ok(result) => result,
+            Err(error) => {
+                debug!(?error, "Error in LmdbCache::remove");
+
+                None
+            }
+        }
+    }
+
+    fn clear(&self) {
+        if let Err(error) = self.internal_clear() {
+            debug!(?error, "Error in LmdbCache::clear");
+        };
+    }
+
+    /// Iterates over the entries in the order of their keys, reading them in chunks,
+    /// so entries put or removed during the iteration may or may not be returned.
+    fn iter(&self) -> CacheIter<'_> {
+        Box::new(Iter {
+            cache: self,
+            last: None,
+            chunk: Vec::new().into_iter(),
+            done: false,
+        })
+    }
+
+    /// Counted since this [LmdbCache] was opened.
+    fn stats(&self) -> CacheStats {
+        self.counters.stats()
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -439,4 +717,175 @@ mod tests {
             "most recent key survived"
         )
     }
+
+    #[test]
+    fn lru_with_test_clock() {
+        use crate::{Clock, TestClock};
+
+        let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+
+        // The clock never moves, so every put happens at the same time.
+        let clock = TestClock::default();
+        let cache = LmdbCache::open_unsafe(&env_path, 2)
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+
+        let keys = (0..3)
+            .map(|_| {
+                let signed_packet = SignedPacket::builder().sign(&Keypair::random()).unwrap();
+                let key = CacheKey::from(signed_packet.public_key());
+
+                cache.put(&key, &signed_packet);
+
+                key
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(cache.len(), 2);
+        assert!(
+            cache.get_read_only(&keys[0]).is_none(),
+            "oldest key dropped"
+        );
+        assert!(cache.get_read_only(&keys[1]).is_some());
+        assert!(cache.get_read_only(&keys[2]).is_some());
+
+        cache.put_miss(&keys[0]);
+        assert_eq!(cache.get_miss(&keys[0]), Some(clock.now()));
+    }
+
+    #[test]
+    fn last_seen_with_test_clock() {
+        use crate::{Clock, TestClock};
+
+        let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+
+        let clock = TestClock::default();
+        let cache = LmdbCache::open_unsafe(&env_path, 2)
+            .unwrap()
+            .with_clock(Arc::new(clock.clone()));
+
+        // Far ahead of the system time.
+        clock.advance(std::time::Duration::from_secs(3600));
+
+        let mut signed_packet = SignedPacket::builder().sign(&Keypair::random()).unwrap();
+        signed_packet.refresh_at(clock.now());
+        let key = CacheKey::from(signed_packet.public_key());
+
+        cache.put(&key, &signed_packet);
+
+        assert_eq!(
+            cache.get_read_only(&key).unwrap().last_seen(),
+            &clock.now(),
+            "last_seen is checked against the cache's clock"
+        );
+        assert_eq!(cache.iter().next().unwrap().1.last_seen(), &clock.now());
+    }
+
+    #[test]
+    fn misses() {
+        let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+
+        let cache = LmdbCache::open_unsafe(&env_path, 2).unwrap();
+
+        let signed_packet = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign(&Keypair::random())
+            .unwrap();
+        let key = CacheKey::from(signed_packet.public_key());
+
+        assert!(cache.get_miss(&key).is_none());
+
+        let before = Timestamp::now();
+        cache.put_miss(&key);
+
+        assert!(cache.get_miss(&key).unwrap() >= before);
+        assert!(cache.is_empty(), "misses are not counted as packets");
+
+        cache.put(&key, &signed_packet);
+
+        assert!(cache.get_miss(&key).is_none(), "put forgets the miss");
+
+        // Misses are bounded by the capacity too, evicting the oldest miss first.
+        let keys = (0..3)
+            .map(|_| CacheKey::from(Keypair::random().public_key()))
+            .collect::<Vec<_>>();
+
+        cache.put_miss(&keys[0]);
+        cache.put_miss(&keys[1]);
+        // Recording a miss again makes it the most recent.
+        cache.put_miss(&keys[0]);
+        cache.put_miss(&keys[2]);
+
+        let rtxn = cache.env.read_txn().unwrap();
+        assert_eq!(cache.misses_table.len(&rtxn).unwrap(), 2);
+        assert_eq!(cache.miss_time_to_key_table.len(&rtxn).unwrap(), 2);
+        drop(rtxn);
+
+        assert!(cache.get_miss(&keys[0]).is_some());
+        assert!(cache.get_miss(&keys[1]).is_none(), "oldest miss dropped");
+        assert!(cache.get_miss(&keys[2]).is_some());
+    }
+
+    #[test]
+    fn remove_clear_iter_stats() {
+        let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+
+        let cache = LmdbCache::open_unsafe(&env_path, 2).unwrap();
+
+        let mut entries = vec![];
+
+        for _ in 0..3 {
+            let signed_packet = SignedPacket::builder()
+                .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+                .sign(&Keypair::random())
+                .unwrap();
+            let key = CacheKey::from(signed_packet.public_key());
+
+            cache.put(&key, &signed_packet);
+            entries.push((key, signed_packet));
+        }
+
+        // The first entry was evicted.
+        assert!(cache.get(&entries[0].0).is_none());
+        assert_eq!(cache.get(&entries[1].0).unwrap(), entries[1].1);
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                evictions: 1,
+            }
+        );
+
+        let mut expected = entries[1..].to_vec();
+        expected.sort_by_key(|(key, _)| *key);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), expected);
+
+        assert_eq!(cache.remove(&entries[1].0).unwrap(), entries[1].1);
+        assert!(cache.remove(&entries[1].0).is_none());
+        assert_eq!(cache.iter().collect::<Vec<_>>(), vec![entries[2].clone()]);
+
+        // Removed entries are no longer in the LRU list either.
+        let rtxn = cache.env.read_txn().unwrap();
+        assert_eq!(cache.key_to_time_table.len(&rtxn).unwrap(), 1);
+        assert_eq!(cache.time_to_key_table.len(&rtxn).unwrap(), 1);
+        drop(rtxn);
+
+        // Removing a key forgets its recorded miss too.
+        cache.put_miss(&entries[1].0);
+        assert!(cache.remove(&entries[1].0).is_none());
+        assert!(cache.get_miss(&entries[1].0).is_none());
+
+        let rtxn = cache.env.read_txn().unwrap();
+        assert_eq!(cache.miss_time_to_key_table.len(&rtxn).unwrap(), 0);
+        drop(rtxn);
+
+        cache.put_miss(&entries[0].0);
+        cache.clear();
+
+        assert!(cache.is_empty());
+        assert!(cache.iter().next().is_none());
+        assert!(cache.get_miss(&entries[0].0).is_none());
+    }
 }
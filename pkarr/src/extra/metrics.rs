@@ -0,0 +1,106 @@
+//! [ClientObserver] implementation exporting [ClientEvent]s with the [metrics] crate.
+//!
+//! Install any [metrics] recorder (for example `metrics-exporter-prometheus`),
+//! then register a [MetricsObserver] with [crate::ClientBuilder::observer].
+
+use crate::errors::PublishError;
+use crate::{ClientEvent, ClientObserver, Source};
+
+/// Counter of cache hits, labeled by `expired`.
+pub const CACHE_HITS: &str = "pkarr_cache_hits_total";
+/// Counter of cache misses.
+pub const CACHE_MISSES: &str = "pkarr_cache_misses_total";
+/// Counter of relay requests, labeled by `relay`, `request` and `status`.
+pub const RELAY_REQUESTS: &str = "pkarr_relay_requests_total";
+/// Histogram of relay request latencies in seconds, labeled by `relay` and `request`.
+pub const RELAY_REQUEST_DURATION: &str = "pkarr_relay_request_duration_seconds";
+/// Counter of valid Dht responses.
+pub const DHT_RESPONSES: &str = "pkarr_dht_responses_total";
+/// Histogram of Dht response latencies in seconds.
+pub const DHT_RESPONSE_DURATION: &str = "pkarr_dht_response_duration_seconds";
+/// Counter of rejected packets, labeled by `source`.
+pub const PACKETS_REJECTED: &str = "pkarr_packets_rejected_total";
+/// Counter of publish results, labeled by `result`.
+pub const PUBLISHED: &str = "pkarr_published_total";
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A [ClientObserver] that records every [ClientEvent] with the global [metrics] recorder.
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use pkarr::{extra::metrics::MetricsObserver, Client};
+///
+/// let client = Client::builder()
+///     .observer(Arc::new(MetricsObserver))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct MetricsObserver;
+
+impl ClientObserver for MetricsObserver {
+    fn on_event(&self, event: &ClientEvent) {
+        match event {
+            ClientEvent::CacheHit { expired, .. } => {
+                metrics::counter!(CACHE_HITS, "expired" => expired.to_string()).increment(1);
+            }
+            ClientEvent::CacheMiss { .. } => {
+                metrics::counter!(CACHE_MISSES).increment(1);
+            }
+            #[cfg(relays)]
+            ClientEvent::RelayResponse {
+                url,
+                request,
+                status,
+                latency,
+                ..
+            } => {
+                let request = match request {
+                    crate::RelayRequest::Resolve => "resolve",
+                    crate::RelayRequest::Publish => "publish",
+                };
+                let status = status.map_or("error".to_string(), |status| status.to_string());
+
+                metrics::counter!(
+                    RELAY_REQUESTS,
+                    "relay" => url.to_string(),
+                    "request" => request,
+                    "status" => status
+                )
+                .increment(1);
+                metrics::histogram!(
+                    RELAY_REQUEST_DURATION,
+                    "relay" => url.to_string(),
+                    "request" => request
+                )
+                .record(latency.as_secs_f64());
+            }
+            #[cfg(dht)]
+            ClientEvent::DhtResponse { latency, .. } => {
+                metrics::counter!(DHT_RESPONSES).increment(1);
+                metrics::histogram!(DHT_RESPONSE_DURATION).record(latency.as_secs_f64());
+            }
+            ClientEvent::PacketRejected { source, .. } => {
+                let source = match source {
+                    Source::Cache => "cache",
+                    Source::Static => "static",
+                    Source::Dht => "dht",
+                    #[cfg(relays)]
+                    Source::Relay { .. } => "relay",
+                };
+
+                metrics::counter!(PACKETS_REJECTED, "source" => source).increment(1);
+            }
+            ClientEvent::Published { result, .. } => {
+                let result = match result {
+                    Ok(()) => "ok",
+                    Err(PublishError::Query(_)) => "query_error",
+                    Err(PublishError::Concurrency(_)) => "concurrency_error",
+                    Err(PublishError::UnexpectedResponses) => "unexpected_responses",
+                };
+
+                metrics::counter!(PUBLISHED, "result" => result).increment(1);
+            }
+        }
+    }
+}
@@ -12,3 +12,9 @@ pub mod tls;
 
 #[cfg(all(not(wasm_browser), feature = "lmdb-cache"))]
 pub mod lmdb_cache;
+
+#[cfg(all(not(wasm_browser), feature = "lmdb-cache"))]
+pub mod outbox;
+
+#[cfg(all(client, feature = "metrics"))]
+pub mod metrics;
@@ -0,0 +1,458 @@
+//! Persistent publish [Outbox] using LMDB's bindings [heed], for publishing while offline.
+
+use std::{
+    fmt::Debug,
+    fs,
+    path::Path,
+    sync::{mpsc, Arc, Condvar, Mutex, Weak},
+    thread,
+    time::Duration,
+};
+
+use heed::{Database, Env, EnvOpenOptions};
+
+use tracing::debug;
+
+use ntimestamp::Timestamp;
+
+use crate::errors::{ConcurrencyError, PublishError, QueryError};
+use crate::{CacheKey, Client, PublicKey, SignedPacket};
+
+use super::lmdb_cache::{CacheKeyCodec, Error};
+
+const MAP_SIZE: usize = 10 * 1024 * 1024; // 10 mb
+
+const OUTBOX_TABLE: &str = "pkarroutbox:signed_packet";
+
+type OutboxTable = Database<CacheKeyCodec, SignedPacket>;
+
+/// Default interval between flushing attempts, see [Outbox::flush_in_background].
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A per-key event emitted by the [Outbox], see [Outbox::subscribe].
+pub enum OutboxEvent {
+    /// A queued packet was published successfully and removed from the outbox.
+    Published {
+        /// The published packet's [PublicKey].
+        public_key: PublicKey,
+        /// The published packet's timestamp.
+        timestamp: Timestamp,
+    },
+    /// A queued packet was replaced by a more recent packet for the same [PublicKey],
+    /// before it was published.
+    Superseded {
+        /// The superseded packet's [PublicKey].
+        public_key: PublicKey,
+        /// The superseded packet's timestamp.
+        timestamp: Timestamp,
+    },
+    /// A queued packet failed with an error that retrying won't fix,
+    /// and was removed from the outbox.
+    Failed {
+        /// The failed packet's [PublicKey].
+        public_key: PublicKey,
+        /// The failed packet's timestamp.
+        timestamp: Timestamp,
+        /// The error returned from [Client::publish].
+        error: PublishError,
+    },
+}
+
+#[derive(Clone)]
+/// Persistent queue of [SignedPacket]s waiting to be published, using LMDB's bindings [heed].
+///
+/// Queued packets survive restarts, and are published with [Self::flush], or periodically
+/// with [Self::flush_in_background], until the network is reachable.
+///
+/// Only the most recent packet of each [PublicKey] is kept.
+pub struct Outbox(Arc<Inner>);
+
+struct Inner {
+    env: Env,
+    table: OutboxTable,
+    subscribers: Mutex<Vec<mpsc::Sender<OutboxEvent>>>,
+    /// Set when a packet is pushed, to wake up the background flushing thread.
+    pushed: (Mutex<bool>, Condvar),
+}
+
+impl Debug for Outbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outbox")
+            .field("env", &self.0.env)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Outbox {
+    /// Opens (or creates) an [Outbox] at the `env_path`.
+    ///
+    /// # Safety
+    /// Outbox uses LMDB, [opening][heed::EnvOpenOptions::open] which is marked unsafe,
+    /// because the possible Undefined Behavior (UB) if the lock file is broken.
+    pub unsafe fn open(env_path: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(env_path)?;
+
+        let env = unsafe {
+            EnvOpenOptions::new()
+                .map_size(MAP_SIZE)
+                .max_dbs(1)
+                .open(env_path)?
+        };
+
+        let mut wtxn = env.write_txn()?;
+        let table: OutboxTable = env.create_database(&mut wtxn, Some(OUTBOX_TABLE))?;
+        wtxn.commit()?;
+
+        Ok(Self(Arc::new(Inner {
+            env,
+            table,
+            subscribers: Mutex::new(vec![]),
+            pushed: (Mutex::new(false), Condvar::new()),
+        })))
+    }
+
+    /// Convenient wrapper around [Self::open].
+    ///
+    /// Make sure to read the safety section in [Self::open]
+    pub fn open_unsafe(env_path: &Path) -> Result<Self, Error> {
+        unsafe { Self::open(env_path) }
+    }
+
+    /// Returns a receiver of every [OutboxEvent] emitted after this call.
+    pub fn subscribe(&self) -> mpsc::Receiver<OutboxEvent> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.0
+            .subscribers
+            .lock()
+            .expect("Outbox::subscribers.lock()")
+            .push(sender);
+
+        receiver
+    }
+
+    /// Queues a [SignedPacket] to be published.
+    ///
+    /// Replaces an older queued packet for the same [PublicKey], emitting [OutboxEvent::Superseded],
+    /// and returns `false` without queuing if a packet at least as recent is already queued.
+    pub fn push(&self, signed_packet: &SignedPacket) -> Result<bool, Error> {
+        let key = CacheKey::from(signed_packet.public_key());
+
+        let mut wtxn = self.0.env.write_txn()?;
+
+        let existing = self.0.table.get(&wtxn, &key)?;
+
+        if let Some(existing) = &existing {
+            if !signed_packet.more_recent_than(existing) {
+                return Ok(false);
+            }
+        }
+
+        self.0.table.put(&mut wtxn, &key, signed_packet)?;
+        wtxn.commit()?;
+
+        if let Some(existing) = existing {
+            self.emit(OutboxEvent::Superseded {
+                public_key: existing.public_key(),
+                timestamp: existing.timestamp(),
+            });
+        }
+
+        let (pushed, condvar) = &self.0.pushed;
+        *pushed.lock().expect("Outbox::pushed.lock()") = true;
+        condvar.notify_all();
+
+        Ok(true)
+    }
+
+    /// Returns the queued packet for a [PublicKey], if any.
+    pub fn get(&self, public_key: &PublicKey) -> Result<Option<SignedPacket>, Error> {
+        let rtxn = self.0.env.read_txn()?;
+
+        Ok(self.0.table.get(&rtxn, &CacheKey::from(public_key))?)
+    }
+
+    /// Returns all the queued packets.
+    pub fn pending(&self) -> Result<Vec<SignedPacket>, Error> {
+        let rtxn = self.0.env.read_txn()?;
+
+        let pending = self
+            .0
+            .table
+            .iter(&rtxn)?
+            .map(|result| result.map(|(_, signed_packet)| signed_packet))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(pending)
+    }
+
+    /// Returns the number of queued packets.
+    pub fn len(&self) -> Result<usize, Error> {
+        let rtxn = self.0.env.read_txn()?;
+
+        Ok(self.0.table.len(&rtxn)? as usize)
+    }
+
+    /// Returns `true` if there are no queued packets.
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Tries to publish every queued packet using the `client`, and returns the number
+    /// of packets still queued.
+    ///
+    /// Packets are removed from the outbox once published, or if publishing failed with an
+    /// error that retrying won't fix, and are kept otherwise (for example if the network
+    /// is unreachable) to be retried in the next flush.
+    pub async fn flush(&self, client: &Client) -> Result<usize, Error> {
+        for signed_packet in self.pending()? {
+            let result = client.publish(&signed_packet, None).await;
+
+            self.complete(&signed_packet, result)?;
+        }
+
+        self.len()
+    }
+
+    /// Flushes the outbox in a background thread, every `interval` and whenever
+    /// a new packet is pushed, until all clones of this outbox are dropped,
+    /// or the client is [shut down](Client::shutdown).
+    pub fn flush_in_background(&self, client: Client, interval: Duration) {
+        let weak = Arc::downgrade(&self.0);
+
+        let result = thread::Builder::new()
+            .name("pkarr-outbox".to_string())
+            .spawn(move || loop {
+                let Some(inner) = weak.upgrade().filter(|_| !client.is_shutdown()) else {
+                    break;
+                };
+
+                let outbox = Outbox(inner);
+
+                if let Err(error) = futures_lite::future::block_on(outbox.flush(&client)) {
+                    debug!(?error, "Error in Outbox::flush");
+                }
+
+                drop(outbox);
+
+                if !wait(&weak, interval) {
+                    break;
+                }
+            });
+
+        if let Err(error) = result {
+            debug!(?error, "Failed to spawn outbox thread");
+        }
+    }
+
+    /// Remove a packet after a publishing attempt if it wasn't superseded,
+    /// unless the error is transient.
+    fn complete(
+        &self,
+        signed_packet: &SignedPacket,
+        result: Result<(), PublishError>,
+    ) -> Result<(), Error> {
+        let public_key = signed_packet.public_key();
+        let timestamp = signed_packet.timestamp();
+
+        let event = match result {
+            Ok(()) => OutboxEvent::Published {
+                public_key,
+                timestamp,
+            },
+            Err(error) if is_transient(&error) => {
+                debug!(?error, %public_key, "Failed to publish queued packet, will retry");
+
+                return Ok(());
+            }
+            Err(error) => OutboxEvent::Failed {
+                public_key,
+                timestamp,
+                error,
+            },
+        };
+
+        let key = CacheKey::from(signed_packet.public_key());
+
+        let mut wtxn = self.0.env.write_txn()?;
+
+        let superseded = self
+            .0
+            .table
+            .get(&wtxn, &key)?
+            .is_some_and(|queued| queued.timestamp() != timestamp);
+
+        if !superseded {
+            self.0.table.delete(&mut wtxn, &key)?;
+        }
+
+        wtxn.commit()?;
+
+        self.emit(event);
+
+        Ok(())
+    }
+
+    fn emit(&self, event: OutboxEvent) {
+        self.0
+            .subscribers
+            .lock()
+            .expect("Outbox::subscribers.lock()")
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Returns true if retrying to publish later might succeed.
+fn is_transient(error: &PublishError) -> bool {
+    matches!(
+        error,
+        PublishError::Query(QueryError::Timeout | QueryError::NoClosestNodes)
+            | PublishError::Concurrency(ConcurrencyError::ConflictRisk)
+            | PublishError::UnexpectedResponses
+    )
+}
+
+/// Wait for the interval or a new pushed packet, returns false if the outbox was dropped.
+fn wait(inner: &Weak<Inner>, interval: Duration) -> bool {
+    // Wake up regularly to check if the outbox was dropped.
+    const DROPPED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    let mut remaining = interval;
+
+    while !remaining.is_zero() {
+        let Some(inner) = inner.upgrade() else {
+            return false;
+        };
+
+        let step = remaining.min(DROPPED_CHECK_INTERVAL);
+
+        let (pushed, condvar) = &inner.pushed;
+        let guard = pushed.lock().expect("Outbox::pushed.lock()");
+        let (mut guard, _) = condvar
+            .wait_timeout_while(guard, step, |pushed| !*pushed)
+            .expect("Outbox::pushed.lock()");
+
+        if *guard {
+            *guard = false;
+
+            return true;
+        }
+
+        remaining = remaining.saturating_sub(step);
+    }
+
+    inner.strong_count() > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Keypair;
+
+    use super::*;
+
+    fn signed_packet(keypair: &Keypair, timestamp: u64) -> SignedPacket {
+        SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .timestamp(timestamp.into())
+            .sign(keypair)
+            .unwrap()
+    }
+
+    #[test]
+    fn supersede() {
+        let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+
+        let outbox = Outbox::open_unsafe(&env_path).unwrap();
+        let events = outbox.subscribe();
+
+        let keypair = Keypair::random();
+        let older = signed_packet(&keypair, 1000);
+        let newer = signed_packet(&keypair, 2000);
+
+        assert!(outbox.push(&older).unwrap());
+        assert!(outbox.push(&newer).unwrap());
+        assert!(!outbox.push(&older).unwrap(), "older packet ignored");
+
+        assert_eq!(outbox.len().unwrap(), 1);
+        assert_eq!(outbox.get(&keypair.public_key()).unwrap(), Some(newer));
+
+        assert_eq!(
+            events.try_recv().unwrap(),
+            OutboxEvent::Superseded {
+                public_key: keypair.public_key(),
+                timestamp: 1000.into()
+            }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn persisted() {
+        let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+
+        let keypair = Keypair::random();
+        let signed_packet = signed_packet(&keypair, 1000);
+
+        {
+            let outbox = Outbox::open_unsafe(&env_path).unwrap();
+            outbox.push(&signed_packet).unwrap();
+        }
+
+        let outbox = Outbox::open_unsafe(&env_path).unwrap();
+
+        assert_eq!(outbox.pending().unwrap(), vec![signed_packet]);
+    }
+
+    #[cfg(dht)]
+    #[tokio::test]
+    async fn flush_once_reachable() {
+        let env_path = std::env::temp_dir().join(Timestamp::now().to_string());
+
+        let outbox = Outbox::open_unsafe(&env_path).unwrap();
+        let events = outbox.subscribe();
+
+        let keypair = Keypair::random();
+        let signed_packet = SignedPacket::builder().sign(&keypair).unwrap();
+
+        outbox.push(&signed_packet).unwrap();
+
+        // No bootstrapping nodes.
+        let offline = Client::builder()
+            .no_default_network()
+            .bootstrap::<String>(&[])
+            .build()
+            .unwrap();
+
+        assert_eq!(outbox.flush(&offline).await.unwrap(), 1, "still queued");
+        assert!(events.try_recv().is_err());
+
+        let testnet = mainline::Testnet::new_async(5).await.unwrap();
+        let online = Client::builder()
+            .no_default_network()
+            .bootstrap(&testnet.bootstrap)
+            .build()
+            .unwrap();
+
+        outbox.flush_in_background(online.clone(), DEFAULT_FLUSH_INTERVAL);
+
+        let event =
+            tokio::task::spawn_blocking(move || events.recv_timeout(Duration::from_secs(10)))
+                .await
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(
+            event,
+            OutboxEvent::Published {
+                public_key: keypair.public_key(),
+                timestamp: signed_packet.timestamp()
+            }
+        );
+        assert!(outbox.is_empty().unwrap());
+        assert_eq!(
+            online.resolve(&keypair.public_key()).await,
+            Some(signed_packet)
+        );
+    }
+}
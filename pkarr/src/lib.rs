@@ -10,12 +10,16 @@
 // Modules
 #[cfg(client)]
 mod client;
+#[cfg(feature = "signed_packet")]
+mod clock;
 #[cfg(client)]
 pub mod extra;
 #[cfg(feature = "keys")]
 mod keys;
 #[cfg(feature = "signed_packet")]
 mod signed_packet;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 /// Default minimum TTL: 5 minutes.
 pub const DEFAULT_MINIMUM_TTL: u32 = 300;
@@ -31,9 +35,37 @@ pub const DEFAULT_CACHE_SIZE: usize = 1000;
 #[cfg(all(client, not(wasm_browser)))]
 pub use client::blocking::ClientBlocking;
 #[cfg(client)]
-pub use client::cache::{Cache, CacheKey, InMemoryCache};
+pub use client::cache::{
+    AsyncCache, Cache, CacheFuture, CacheIter, CacheKey, CacheStats, InMemoryCache,
+};
+#[cfg(all(client, not(wasm_browser)))]
+pub use client::republisher::{
+    RepublishState, RepublishStatus, Republisher, RepublisherBuilder, DEFAULT_REPUBLISH_INTERVAL,
+    DEFAULT_REPUBLISH_JITTER, DEFAULT_REPUBLISH_RETRY_BACKOFF,
+};
+#[cfg(dht)]
+pub use client::DhtDiagnostic;
+#[cfg(dht)]
+pub use client::DhtPublishReport;
+#[cfg(client)]
+pub use client::{builder::ClientBuilder, Client, Resolved, Source};
 #[cfg(client)]
-pub use client::{builder::ClientBuilder, Client};
+pub use client::{
+    CacheDiagnostic, CachePolicy, CancellationToken, ClientEvent, ClientObserver, DiagnosticReport,
+    NetworkSelection, PrefetchStats, PublishReport, Quorum, RequestOptions, ResolveOptions,
+    RetryPolicy, DEFAULT_MAX_CONCURRENT_PREFETCHES, DEFAULT_PUBLISH_CONCURRENCY,
+    DEFAULT_UPDATE_ATTEMPTS,
+};
+#[cfg(relays)]
+pub use client::{
+    RelayDiagnostic, RelayHealth, RelayOutcome, RelayPublishReport, RelayRequest, RelayResponse,
+    RelayTransport, RelayTransportFuture, ReqwestTransport, DEFAULT_RELAY_COOLDOWN,
+    DEFAULT_RELAY_FAILURE_THRESHOLD, MAX_RESPONSE_BYTES,
+};
+#[cfg(all(dht, relays))]
+pub use client::{DEFAULT_RELAYS_REFRESH_INTERVAL, RELAYS_RECORD_NAME};
+#[cfg(feature = "signed_packet")]
+pub use clock::{Clock, SystemClock, TestClock};
 #[cfg(feature = "keys")]
 pub use keys::{Keypair, PublicKey};
 #[cfg(feature = "signed_packet")]
@@ -57,5 +89,13 @@ pub mod errors {
     pub use super::signed_packet::{SignedPacketBuildError, SignedPacketVerifyError};
 
     #[cfg(client)]
-    pub use super::client::{BuildError, ConcurrencyError, PublishError, QueryError};
+    pub use super::client::{
+        BuildError, ConcurrencyError, PublishError, QueryError, QuorumError, UpdateError,
+    };
+
+    #[cfg(client)]
+    pub use super::client::StaticZonesError;
+
+    #[cfg(relays)]
+    pub use super::client::RelayTransportError;
 }
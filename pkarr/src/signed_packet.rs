@@ -1,6 +1,6 @@
 //! Signed DNS packet
 
-use crate::{Keypair, PublicKey};
+use crate::{Clock, Keypair, PublicKey};
 use bytes::{Bytes, BytesMut};
 use ed25519_dalek::{Signature, SignatureError};
 use self_cell::self_cell;
@@ -24,6 +24,7 @@ use ntimestamp::Timestamp;
 pub struct SignedPacketBuilder {
     records: Vec<ResourceRecord<'static>>,
     timestamp: Option<Timestamp>,
+    canonical: bool,
 }
 
 impl SignedPacketBuilder {
@@ -119,6 +120,17 @@ impl SignedPacketBuilder {
         self
     }
 
+    /// Sort the [ResourceRecord]s by name, type and rdata before encoding the packet.
+    ///
+    /// By default records are encoded in insertion order, so building the same set of
+    /// records in a different order produces a different [SignedPacket::encoded_packet]
+    /// (and signature). In canonical mode the same record set always encodes to the same bytes.
+    pub fn canonical(mut self) -> Self {
+        self.canonical = true;
+
+        self
+    }
+
     /// Alias to [Self::sign]
     pub fn build(self, keypair: &Keypair) -> Result<SignedPacket, SignedPacketBuildError> {
         self.sign(keypair)
@@ -129,11 +141,44 @@ impl SignedPacketBuilder {
     ///
     /// Read more about how names will be normalized in [SignedPacket::new].
     pub fn sign(self, keypair: &Keypair) -> Result<SignedPacket, SignedPacketBuildError> {
-        SignedPacket::new(
-            keypair,
-            &self.records,
-            self.timestamp.unwrap_or(Timestamp::now()),
-        )
+        self.sign_with_clock(keypair, &crate::SystemClock)
+    }
+
+    /// Same as [Self::sign], but uses the `clock` for the default [Self::timestamp],
+    /// and the [SignedPacket::last_seen].
+    pub fn sign_with_clock(
+        self,
+        keypair: &Keypair,
+        clock: &dyn Clock,
+    ) -> Result<SignedPacket, SignedPacketBuildError> {
+        let mut records = self.records;
+
+        if self.canonical {
+            let origin = keypair.public_key().to_z32();
+
+            // Encode every sort key first, so an rdata that fails to encode is reported
+            // as an error instead of panicking in the middle of sorting.
+            let mut keyed = records
+                .into_iter()
+                .map(|record| {
+                    let name = normalize_name(&origin, record.name.to_string());
+
+                    Ok((canonical_record_key(&name, &record)?, record))
+                })
+                .collect::<Result<Vec<_>, SimpleDnsError>>()?;
+
+            keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            records = keyed.into_iter().map(|(_, record)| record).collect();
+        }
+
+        let now = clock.now();
+
+        let mut signed_packet =
+            SignedPacket::new(keypair, &records, self.timestamp.unwrap_or(now))?;
+        signed_packet.set_last_seen(&now);
+
+        Ok(signed_packet)
     }
 }
 
@@ -331,9 +376,15 @@ impl SignedPacket {
     /// That is useful for backwards compatibility if you
     /// ever stored the [SignedPacket::last_seen] as Little Endian in previous versions.
     pub fn deserialize(bytes: &[u8]) -> Result<Self, SimpleDnsError> {
+        Self::deserialize_at(bytes, Timestamp::now())
+    }
+
+    /// Same as [Self::deserialize], but [SignedPacket::last_seen] is considered far in the future
+    /// relative to `now` instead of the system time, for example from a custom [crate::Clock].
+    pub fn deserialize_at(bytes: &[u8], now: Timestamp) -> Result<Self, SimpleDnsError> {
         let mut last_seen = Timestamp::try_from(&bytes[0..8]).unwrap_or_default();
 
-        if last_seen > (Timestamp::now() + 60_000_000) {
+        if last_seen > (now + 60_000_000) {
             last_seen = Timestamp::from(0)
         }
 
@@ -381,7 +432,7 @@ impl SignedPacket {
     }
 
     /// Return the DNS [Packet].
-    pub(crate) fn packet(&self) -> &Packet {
+    pub(crate) fn packet(&self) -> &Packet<'_> {
         self.inner.borrow_dependent()
     }
 
@@ -401,7 +452,13 @@ impl SignedPacket {
 
     /// Set the [Self::last_seen] to the current system time
     pub fn refresh(&mut self) {
-        self.last_seen = Timestamp::now();
+        self.refresh_at(Timestamp::now());
+    }
+
+    /// Same as [Self::refresh], but at `now` instead of the system time,
+    /// for example from a custom [crate::Clock].
+    pub fn refresh_at(&mut self, now: Timestamp) {
+        self.last_seen = now;
     }
 
     /// Return whether this [SignedPacket] is more recent than the given one.
@@ -425,13 +482,25 @@ impl SignedPacket {
         self.as_bytes() == other.as_bytes()
     }
 
+    /// Returns true if both packets contain the same set of [ResourceRecord]s,
+    /// regardless of their order, compression, [Self::timestamp] or [Self::signature].
+    ///
+    /// Useful for republishers to tell a real change in records from a mere reordering.
+    pub fn same_records_as(&self, other: &SignedPacket) -> bool {
+        if self.packet().answers.len() != other.packet().answers.len() {
+            return false;
+        }
+
+        self.canonical_records() == other.canonical_records()
+    }
+
     /// Return and iterator over the [ResourceRecord]s in the Answers section of the DNS [Packet]
     /// that matches the given name. The name will be normalized to the origin TLD of this packet.
     ///
     /// You can use `@` to filter the resource records at the Apex (the public key).
     ///
     /// Wildcards are also supported, so `*.foo.<key>` will match `bar.foo.<key>`.
-    pub fn resource_records(&self, name: &str) -> impl Iterator<Item = &ResourceRecord> {
+    pub fn resource_records(&self, name: &str) -> impl Iterator<Item = &ResourceRecord<'_>> {
         let origin = self.public_key().to_z32();
         let normalized_name = normalize_name(&origin, name.to_string());
         let is_wildcard = normalized_name.starts_with('*');
@@ -451,13 +520,13 @@ impl SignedPacket {
 
     /// Similar to [resource_records](SignedPacket::resource_records), but filters out
     /// expired records, according the the [Self::last_seen] value and each record's `ttl`.
-    pub fn fresh_resource_records(&self, name: &str) -> impl Iterator<Item = &ResourceRecord> {
+    pub fn fresh_resource_records(&self, name: &str) -> impl Iterator<Item = &ResourceRecord<'_>> {
         self.resource_records(name)
             .filter(move |rr| rr.ttl > self.elapsed())
     }
 
     /// Returns all resource records in this packet
-    pub fn all_resource_records(&self) -> impl Iterator<Item = &ResourceRecord> {
+    pub fn all_resource_records(&self) -> impl Iterator<Item = &ResourceRecord<'_>> {
         self.packet().answers.iter()
     }
 
@@ -468,7 +537,13 @@ impl SignedPacket {
     ///
     /// Panics if `min` < `max`
     pub fn expires_in(&self, min: u32, max: u32) -> u32 {
-        match self.ttl(min, max).overflowing_sub(self.elapsed()) {
+        self.expires_in_at(min, max, Timestamp::now())
+    }
+
+    /// Same as [Self::expires_in], but relative to `now` instead of the system time,
+    /// see [crate::Clock].
+    pub fn expires_in_at(&self, min: u32, max: u32, now: Timestamp) -> u32 {
+        match self.ttl(min, max).overflowing_sub(self.elapsed_at(now)) {
             (_, true) => 0,
             (ttl, false) => ttl,
         }
@@ -492,16 +567,47 @@ impl SignedPacket {
     /// Returns whether or not this packet is considered expired according to
     /// a given `min` and `max` TTLs, by comparing it to this [SignedPacket::ttl].
     pub fn is_expired(&self, min: u32, max: u32) -> bool {
-        self.expires_in(min, max) == 0
+        self.is_expired_at(min, max, Timestamp::now())
+    }
+
+    /// Same as [Self::is_expired], but relative to `now` instead of the system time,
+    /// see [crate::Clock].
+    pub fn is_expired_at(&self, min: u32, max: u32, now: Timestamp) -> bool {
+        self.expires_in_at(min, max, now) == 0
     }
 
     /// Time since the [Self::last_seen] in seconds
     pub fn elapsed(&self) -> u32 {
-        ((Timestamp::now().as_u64() - self.last_seen.as_u64()) / 1_000_000) as u32
+        self.elapsed_at(Timestamp::now())
+    }
+
+    /// Time since the [Self::last_seen] in seconds, relative to `now` instead of
+    /// the system time, see [crate::Clock].
+    ///
+    /// Returns `0` if the [Self::last_seen] is after `now`.
+    pub fn elapsed_at(&self, now: Timestamp) -> u32 {
+        (now.as_u64().saturating_sub(self.last_seen.as_u64()) / 1_000_000) as u32
     }
 
     // === Private Methods ===
 
+    /// Returns the sorted canonical keys of all resource records, see [canonical_record_key].
+    fn canonical_records(&self) -> Vec<CanonicalRecordKey> {
+        let mut records = self
+            .all_resource_records()
+            .map(|record| {
+                // The records were parsed from this packet's own encoded bytes,
+                // so encoding them again can't fail.
+                canonical_record_key(&record.name.to_string(), record)
+                    .expect("re-encoding rdata parsed from a valid packet")
+            })
+            .collect::<Vec<_>>();
+
+        records.sort();
+
+        records
+    }
+
     /// Creates a [Self] from the serialized representation:
     /// `<32 bytes public_key><64 bytes signature><8 bytes big-endian timestamp in microseconds><encoded DNS packet>`
     ///
@@ -562,6 +668,39 @@ fn signable(timestamp: u64, v: &[u8]) -> Box<[u8]> {
     signable.into()
 }
 
+/// Sort key of a [ResourceRecord]: `(name, type, rdata, class, ttl)`.
+type CanonicalRecordKey = (String, u16, Vec<u8>, u16, u32);
+
+fn canonical_record_key(
+    normalized_name: &str,
+    record: &ResourceRecord<'_>,
+) -> Result<CanonicalRecordKey, SimpleDnsError> {
+    Ok((
+        normalized_name.to_string(),
+        record.rdata.type_code().into(),
+        encode_rdata(&record.rdata)?,
+        record.class as u16,
+        record.ttl,
+    ))
+}
+
+/// Returns the uncompressed wire format of an [RData].
+fn encode_rdata(rdata: &RData<'_>) -> Result<Vec<u8>, SimpleDnsError> {
+    let mut packet = Packet::new_reply(0);
+    packet.answers.push(ResourceRecord::new(
+        Name::new_unchecked(""),
+        CLASS::IN,
+        0,
+        rdata.clone(),
+    ));
+
+    let bytes = packet.build_bytes_vec()?;
+
+    // Skip the header (12 bytes), the root name (1 byte),
+    // and the type, class, ttl and rdlength fields (10 bytes).
+    Ok(bytes[23..].to_vec())
+}
+
 fn normalize_name(origin: &str, name: String) -> String {
     let name = if name.ends_with(DOT) {
         name[..name.len() - 1].to_string()
@@ -1146,4 +1285,160 @@ mod tests {
 
         assert_eq!(signed_packet.fresh_resource_records("*.foo.").count(), 1);
     }
+
+    #[test]
+    fn canonical_ordering() {
+        let keypair = Keypair::random();
+        let timestamp = Timestamp::now();
+
+        let a = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .a("foo".try_into().unwrap(), "1.1.1.1".parse().unwrap(), 30)
+            .cname(
+                "bar".try_into().unwrap(),
+                "example.com".try_into().unwrap(),
+                30,
+            )
+            .a(".".try_into().unwrap(), "2.2.2.2".parse().unwrap(), 30)
+            .timestamp(timestamp)
+            .canonical()
+            .sign(&keypair)
+            .unwrap();
+
+        let b = SignedPacket::builder()
+            .a(
+                keypair.public_key().to_z32().as_str().try_into().unwrap(),
+                "2.2.2.2".parse().unwrap(),
+                30,
+            )
+            .cname(
+                "bar".try_into().unwrap(),
+                "example.com".try_into().unwrap(),
+                30,
+            )
+            .a("foo".try_into().unwrap(), "1.1.1.1".parse().unwrap(), 30)
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .timestamp(timestamp)
+            .canonical()
+            .sign(&keypair)
+            .unwrap();
+
+        assert_eq!(a.encoded_packet(), b.encoded_packet());
+        assert_eq!(a.signature(), b.signature());
+
+        let names = a
+            .all_resource_records()
+            .map(|record| record.name.to_string())
+            .collect::<Vec<_>>();
+        let mut sorted = names.clone();
+        sorted.sort();
+
+        assert_eq!(names, sorted);
+    }
+
+    #[test]
+    fn canonical_invalid_rdata() {
+        use simple_dns::rdata::LOC;
+
+        let invalid = ResourceRecord::new(
+            Name::new("foo").unwrap(),
+            CLASS::IN,
+            30,
+            RData::LOC(LOC {
+                version: 1,
+                size: 0,
+                horizontal_precision: 0,
+                vertical_precision: 0,
+                latitude: 0,
+                longitude: 0,
+                altitude: 0,
+            }),
+        );
+
+        let result = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .record(invalid)
+            .canonical()
+            .sign(&Keypair::random());
+
+        assert!(matches!(
+            result,
+            Err(SignedPacketBuildError::FailedToWrite(
+                SimpleDnsError::InvalidDnsPacket
+            ))
+        ));
+    }
+
+    #[test]
+    fn same_records_as() {
+        let keypair = Keypair::random();
+
+        let a = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .a("foo".try_into().unwrap(), "1.1.1.1".parse().unwrap(), 30)
+            .sign(&keypair)
+            .unwrap();
+
+        let b = SignedPacket::builder()
+            .a("foo".try_into().unwrap(), "1.1.1.1".parse().unwrap(), 30)
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign(&keypair)
+            .unwrap();
+
+        assert_ne!(a.encoded_packet(), b.encoded_packet());
+        assert!(a.same_records_as(&b));
+        assert!(b.same_records_as(&a));
+
+        let different_ttl = SignedPacket::builder()
+            .a("foo".try_into().unwrap(), "1.1.1.1".parse().unwrap(), 60)
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign(&keypair)
+            .unwrap();
+
+        assert!(!a.same_records_as(&different_ttl));
+
+        let different_rdata = SignedPacket::builder()
+            .a("foo".try_into().unwrap(), "1.1.1.2".parse().unwrap(), 30)
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign(&keypair)
+            .unwrap();
+
+        assert!(!a.same_records_as(&different_rdata));
+
+        let subset = SignedPacket::builder()
+            .a("foo".try_into().unwrap(), "1.1.1.1".parse().unwrap(), 30)
+            .sign(&keypair)
+            .unwrap();
+
+        assert!(!a.same_records_as(&subset));
+    }
+
+    #[test]
+    fn expiry_with_clock() {
+        use crate::{Clock, TestClock};
+
+        let clock = TestClock::default();
+
+        let signed_packet = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign_with_clock(&Keypair::random(), &clock)
+            .unwrap();
+
+        assert_eq!(signed_packet.timestamp(), clock.now());
+        assert_eq!(signed_packet.last_seen(), &clock.now());
+
+        clock.advance(std::time::Duration::from_secs(20));
+
+        assert_eq!(signed_packet.elapsed_at(clock.now()), 20);
+        assert_eq!(signed_packet.expires_in_at(0, 60, clock.now()), 10);
+        assert!(!signed_packet.is_expired_at(0, 60, clock.now()));
+
+        clock.advance(std::time::Duration::from_secs(10));
+
+        assert!(signed_packet.is_expired_at(0, 60, clock.now()));
+
+        // Last seen in the future.
+        clock.set(Timestamp::from(0));
+        assert_eq!(signed_packet.elapsed_at(clock.now()), 0);
+    }
 }
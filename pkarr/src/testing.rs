@@ -0,0 +1,534 @@
+//! In-process simulated network for fast and deterministic tests of code using a [Client],
+//! without UDP sockets, a [mainline::Testnet], or a running [Relay](https://pkarr.org/relays).
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use pkarr::{testing::SimulatedNetwork, Keypair, SignedPacket};
+//!
+//! #[tokio::main]
+//! async fn run() -> anyhow::Result<()> {
+//!     let network = SimulatedNetwork::new();
+//!     network.set_latency(Duration::from_millis(5));
+//!
+//!     let client = network.client_builder().build()?;
+//!
+//!     let keypair = Keypair::random();
+//!     let signed_packet = SignedPacket::builder().sign(&keypair)?;
+//!
+//!     client.publish(&signed_packet, None).await?;
+//!
+//!     assert!(network.get(&keypair.public_key()).is_some());
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use ntimestamp::Timestamp;
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    StatusCode,
+};
+use url::Url;
+
+use crate::{
+    Client, ClientBuilder, Clock, PublicKey, RelayResponse, RelayTransport, RelayTransportFuture,
+    SignedPacket, TestClock,
+};
+
+use crate::client::sleep;
+use crate::errors::RelayTransportError;
+
+/// The Url of the simulated [Relay](https://pkarr.org/relays), used by [SimulatedNetwork::client_builder].
+pub const SIMULATED_RELAY: &str = "http://relay.simulated.pkarr";
+
+#[derive(Debug, Clone, Default)]
+/// An in-process network storing [SignedPacket]s, that behaves like a
+/// [Relay](https://pkarr.org/relays) backed by the Dht, with injectable latency,
+/// packet loss, and a controllable clock.
+///
+/// Clones share the same state, so changing the latency or loss of one clone
+/// affects all clients using any of them.
+pub struct SimulatedNetwork(Arc<Mutex<State>>);
+
+#[derive(Debug, Default)]
+struct State {
+    packets: HashMap<PublicKey, Stored>,
+    latency: Duration,
+    loss: f64,
+    rng: u64,
+    clock: TestClock,
+    retention: Option<Duration>,
+    requests: usize,
+}
+
+#[derive(Debug)]
+struct Stored {
+    signed_packet: SignedPacket,
+    stored_at: Timestamp,
+}
+
+impl SimulatedNetwork {
+    /// Create a new empty network, with no latency, no packet loss, and a random seed.
+    pub fn new() -> Self {
+        let network = Self::default();
+        network.set_seed(rand_seed());
+
+        network
+    }
+
+    /// Returns a [ClientBuilder] with no default network, using only this network,
+    /// and sharing its [Self::clock].
+    ///
+    /// Lost requests fail after the [ClientBuilder::request_timeout],
+    /// so you might want to reduce it when testing packet loss.
+    pub fn client_builder(&self) -> ClientBuilder {
+        let mut builder = Client::builder();
+
+        builder
+            .no_default_network()
+            .relays(&[SIMULATED_RELAY])
+            .expect("SIMULATED_RELAY is a valid relay Url")
+            .relay_transport(Arc::new(self.clone()))
+            .clock(Arc::new(self.clock()));
+
+        builder
+    }
+
+    /// Set the latency of every request, defaults to zero.
+    ///
+    /// Requests with a latency longer than their timeout fail with a timeout.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    /// Set the probability (between `0.0` and `1.0`) that a request is lost,
+    /// and fails with a timeout. Defaults to `0.0`.
+    pub fn set_loss(&self, loss: f64) {
+        self.state().loss = loss.clamp(0.0, 1.0);
+    }
+
+    /// Set the seed used to decide which requests are lost, to reproduce a test run.
+    pub fn set_seed(&self, seed: u64) {
+        self.state().rng = seed;
+    }
+
+    /// Drop packets that were not republished for longer than `retention`,
+    /// like Dht nodes do. Defaults to `None`, keeping packets forever.
+    pub fn set_retention(&self, retention: Option<Duration>) {
+        self.state().retention = retention;
+    }
+
+    /// Returns the [TestClock] of this network, shared with clients built with
+    /// [Self::client_builder].
+    pub fn clock(&self) -> TestClock {
+        self.state().clock.clone()
+    }
+
+    /// Move the [Self::clock] forward, expiring packets older than [Self::set_retention]
+    /// from the network, and packets past their TTL from the clients' caches.
+    pub fn advance(&self, duration: Duration) {
+        self.state().clock.advance(duration);
+    }
+
+    /// Returns the current time of this network's clock.
+    pub fn now(&self) -> Timestamp {
+        self.state().now()
+    }
+
+    /// Returns the [SignedPacket] currently stored for this [PublicKey], if any.
+    pub fn get(&self, public_key: &PublicKey) -> Option<SignedPacket> {
+        self.state()
+            .get(public_key)
+            .map(|stored| stored.signed_packet.clone())
+    }
+
+    /// Store a [SignedPacket] directly, regardless of what is already stored,
+    /// without any latency or packet loss.
+    pub fn insert(&self, signed_packet: SignedPacket) {
+        let mut state = self.state();
+
+        let stored_at = state.now();
+
+        state.packets.insert(
+            signed_packet.public_key(),
+            Stored {
+                signed_packet,
+                stored_at,
+            },
+        );
+    }
+
+    /// Remove the [SignedPacket] stored for this [PublicKey], if any.
+    pub fn remove(&self, public_key: &PublicKey) -> Option<SignedPacket> {
+        self.state()
+            .packets
+            .remove(public_key)
+            .map(|stored| stored.signed_packet)
+    }
+
+    /// Returns the number of requests received so far, including lost ones.
+    pub fn requests(&self) -> usize {
+        self.state().requests
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.0.lock().expect("SimulatedNetwork lock")
+    }
+
+    /// Wait for the latency of a request, or its timeout if it is lost or too slow.
+    async fn deliver(&self, timeout: Duration) -> Result<(), RelayTransportError> {
+        let (latency, lost) = {
+            let mut state = self.state();
+
+            state.requests += 1;
+
+            (state.latency, state.lose())
+        };
+
+        if lost || latency >= timeout {
+            sleep(timeout).await;
+
+            return Err(RelayTransportError::Timeout);
+        }
+
+        sleep(latency).await;
+
+        Ok(())
+    }
+
+    fn handle_get(&self, url: &Url, headers: &HeaderMap) -> RelayResponse {
+        let Some(public_key) = public_key_from_url(url) else {
+            return response(StatusCode::BAD_REQUEST, Bytes::new());
+        };
+
+        let Some(signed_packet) = self.get(&public_key) else {
+            return response(StatusCode::NOT_FOUND, Bytes::new());
+        };
+
+        let not_modified = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Timestamp::parse_http_date(value).ok())
+            // Http dates have a precision of seconds.
+            .is_some_and(|since| {
+                since.as_u64() / 1_000_000 >= signed_packet.timestamp().as_u64() / 1_000_000
+            });
+
+        if not_modified {
+            return response(StatusCode::NOT_MODIFIED, Bytes::new());
+        }
+
+        let mut response = response(StatusCode::OK, signed_packet.to_relay_payload());
+
+        if let Ok(last_modified) =
+            HeaderValue::from_str(&signed_packet.timestamp().format_http_date())
+        {
+            response
+                .headers
+                .insert(header::LAST_MODIFIED, last_modified);
+        }
+
+        response
+    }
+
+    fn handle_put(&self, url: &Url, headers: &HeaderMap, body: &Bytes) -> RelayResponse {
+        let Some(public_key) = public_key_from_url(url) else {
+            return response(StatusCode::BAD_REQUEST, Bytes::new());
+        };
+
+        let Ok(signed_packet) = SignedPacket::from_relay_payload(&public_key, body) else {
+            return response(StatusCode::BAD_REQUEST, Bytes::new());
+        };
+
+        let cas = headers
+            .get(header::IF_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(Timestamp::from);
+
+        let mut state = self.state();
+
+        // Same checks as Dht nodes.
+        if let Some(stored) = state.get(&public_key) {
+            if cas.is_some_and(|cas| cas != stored.signed_packet.timestamp()) {
+                return response(StatusCode::PRECONDITION_FAILED, Bytes::new());
+            }
+
+            if signed_packet.timestamp() < stored.signed_packet.timestamp() {
+                return response(StatusCode::CONFLICT, Bytes::new());
+            }
+        }
+
+        let stored_at = state.now();
+
+        state.packets.insert(
+            public_key,
+            Stored {
+                signed_packet,
+                stored_at,
+            },
+        );
+
+        response(StatusCode::NO_CONTENT, Bytes::new())
+    }
+}
+
+impl State {
+    fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    /// Returns the stored packet, unless it is older than the retention.
+    fn get(&self, public_key: &PublicKey) -> Option<&Stored> {
+        let now = self.now();
+
+        self.packets.get(public_key).filter(|stored| {
+            self.retention.is_none_or(|retention| {
+                now.as_u64().saturating_sub(stored.stored_at.as_u64())
+                    <= retention.as_micros() as u64
+            })
+        })
+    }
+
+    /// Returns `true` if the next request should be lost.
+    fn lose(&mut self) -> bool {
+        if self.loss <= 0.0 {
+            return false;
+        }
+
+        // SplitMix64
+        self.rng = self.rng.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+
+        (z as f64 / u64::MAX as f64) < self.loss
+    }
+}
+
+impl RelayTransport for SimulatedNetwork {
+    fn get(&self, url: Url, headers: HeaderMap, timeout: Duration) -> RelayTransportFuture<'_> {
+        Box::pin(async move {
+            self.deliver(timeout).await?;
+
+            Ok(self.handle_get(&url, &headers))
+        })
+    }
+
+    fn put(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        body: Bytes,
+        timeout: Duration,
+    ) -> RelayTransportFuture<'_> {
+        Box::pin(async move {
+            self.deliver(timeout).await?;
+
+            Ok(self.handle_put(&url, &headers, &body))
+        })
+    }
+}
+
+fn public_key_from_url(url: &Url) -> Option<PublicKey> {
+    let segment = url.path_segments()?.next_back()?;
+
+    PublicKey::try_from(segment).ok()
+}
+
+fn response(status: StatusCode, body: Bytes) -> RelayResponse {
+    RelayResponse {
+        status,
+        headers: HeaderMap::new(),
+        body,
+    }
+}
+
+fn rand_seed() -> u64 {
+    let mut bytes = [0_u8; 8];
+    getrandom::fill(&mut bytes).expect("getrandom failed");
+
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::errors::{ConcurrencyError, PublishError, QueryError};
+    use crate::{Keypair, SignedPacket};
+
+    use super::SimulatedNetwork;
+
+    #[tokio::test]
+    async fn publish_resolve() {
+        let network = SimulatedNetwork::new();
+
+        let a = network.client_builder().build().unwrap();
+        let b = network.client_builder().build().unwrap();
+
+        let keypair = Keypair::random();
+        let signed_packet = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "bar".try_into().unwrap(), 30)
+            .sign(&keypair)
+            .unwrap();
+
+        a.publish(&signed_packet, None).await.unwrap();
+
+        let resolved = b.resolve(&keypair.public_key()).await.unwrap();
+        assert_eq!(resolved.as_bytes(), signed_packet.as_bytes());
+
+        assert_eq!(
+            network.get(&keypair.public_key()).unwrap().as_bytes(),
+            signed_packet.as_bytes()
+        );
+        assert_eq!(b.resolve(&Keypair::random().public_key()).await, None);
+    }
+
+    #[tokio::test]
+    async fn concurrency() {
+        let network = SimulatedNetwork::new();
+
+        let keypair = Keypair::random();
+        let first = SignedPacket::builder().sign(&keypair).unwrap();
+        let second = SignedPacket::builder()
+            .timestamp(first.timestamp() + 1)
+            .sign(&keypair)
+            .unwrap();
+        let third = SignedPacket::builder()
+            .timestamp(first.timestamp() + 2)
+            .sign(&keypair)
+            .unwrap();
+
+        network.insert(second.clone());
+
+        // Fresh clients, so conflicts are detected by the network, not the cache.
+        let client = || network.client_builder().build().unwrap();
+
+        assert_eq!(
+            client().publish(&first, None).await,
+            Err(PublishError::Concurrency(ConcurrencyError::NotMostRecent))
+        );
+        assert_eq!(
+            client().publish(&third, Some(first.timestamp())).await,
+            Err(PublishError::Concurrency(ConcurrencyError::CasFailed))
+        );
+
+        client()
+            .publish(&third, Some(second.timestamp()))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            network.get(&keypair.public_key()).unwrap().as_bytes(),
+            third.as_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn latency_and_loss() {
+        let network = SimulatedNetwork::new();
+        network.set_seed(42);
+
+        let client = network
+            .client_builder()
+            .request_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        let keypair = Keypair::random();
+        let signed_packet = SignedPacket::builder().sign(&keypair).unwrap();
+
+        network.set_latency(Duration::from_millis(20));
+
+        let started_at = std::time::Instant::now();
+        client.publish(&signed_packet, None).await.unwrap();
+        assert!(started_at.elapsed() >= Duration::from_millis(20));
+
+        network.set_loss(1.0);
+
+        assert_eq!(
+            client.publish(&signed_packet, None).await,
+            Err(PublishError::Query(QueryError::Timeout))
+        );
+
+        network.set_loss(0.0);
+        // Slower than the timeout, which is longer for publishing.
+        network.set_latency(Duration::from_millis(200));
+
+        assert_eq!(
+            client.publish(&signed_packet, None).await,
+            Err(PublishError::Query(QueryError::Timeout))
+        );
+
+        assert_eq!(network.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn cache_expiry() {
+        let network = SimulatedNetwork::new();
+
+        let client = network.client_builder().build().unwrap();
+
+        let keypair = Keypair::random();
+        let first = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "first".try_into().unwrap(), 600)
+            .sign(&keypair)
+            .unwrap();
+
+        client.publish(&first, None).await.unwrap();
+
+        let second = SignedPacket::builder()
+            .txt("foo".try_into().unwrap(), "second".try_into().unwrap(), 600)
+            // `If-Modified-Since` has a precision of seconds.
+            .timestamp(first.timestamp() + 1_000_000)
+            .sign(&keypair)
+            .unwrap();
+        network.insert(second.clone());
+
+        // Still fresh in the cache.
+        let resolved = client.resolve(&keypair.public_key()).await.unwrap();
+        assert_eq!(resolved.as_bytes(), first.as_bytes());
+
+        network.advance(Duration::from_secs(601));
+
+        // Expired, refreshed from the network.
+        let resolved = client
+            .resolve_most_recent(&keypair.public_key())
+            .await
+            .unwrap();
+        assert_eq!(resolved.as_bytes(), second.as_bytes());
+        assert_eq!(resolved.last_seen(), &network.now());
+    }
+
+    #[tokio::test]
+    async fn retention() {
+        let network = SimulatedNetwork::new();
+        network.set_retention(Some(Duration::from_secs(60 * 60)));
+
+        let keypair = Keypair::random();
+        let signed_packet = SignedPacket::builder().sign(&keypair).unwrap();
+
+        network.insert(signed_packet.clone());
+
+        network.advance(Duration::from_secs(30 * 60));
+        assert!(network.get(&keypair.public_key()).is_some());
+
+        network.advance(Duration::from_secs(31 * 60));
+        assert!(network.get(&keypair.public_key()).is_none());
+
+        let client = network.client_builder().build().unwrap();
+        assert_eq!(client.resolve(&keypair.public_key()).await, None);
+
+        // Republishing stores the packet again.
+        client.publish(&signed_packet, None).await.unwrap();
+        assert!(network.get(&keypair.public_key()).is_some());
+    }
+}
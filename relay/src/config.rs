@@ -8,6 +8,10 @@ use std::{
 };
 
 pub const DEFAULT_CACHE_SIZE: usize = 1_000_000;
+/// Default negative TTL: 1 minute, see [pkarr::ClientBuilder::negative_ttl]
+pub const DEFAULT_NEGATIVE_TTL: u32 = 60;
+/// Default prefetch threshold: the last 10% of the TTL, see [pkarr::ClientBuilder::prefetch_threshold]
+pub const DEFAULT_PREFETCH_THRESHOLD: f32 = 0.1;
 pub const CACHE_DIR: &str = "pkarr-cache";
 
 use crate::rate_limiting::RateLimiterConfig;
@@ -38,6 +42,12 @@ struct CacheConfig {
     minimum_ttl: Option<u32>,
     /// See [pkarr::ClientBuilder::maximum_ttl]
     maximum_ttl: Option<u32>,
+    /// See [pkarr::ClientBuilder::negative_ttl]
+    negative_ttl: Option<u32>,
+    /// See [pkarr::ClientBuilder::prefetch_threshold]
+    prefetch_threshold: Option<f32>,
+    /// See [pkarr::ClientBuilder::max_concurrent_prefetches]
+    max_concurrent_prefetches: Option<usize>,
 }
 
 /// Pkarr Relay configuration
@@ -73,7 +83,10 @@ impl Default for Config {
             rate_limiter: Some(RateLimiterConfig::default()),
         };
 
-        this.pkarr.no_relays();
+        this.pkarr
+            .no_relays()
+            .negative_ttl(DEFAULT_NEGATIVE_TTL)
+            .prefetch_threshold(DEFAULT_PREFETCH_THRESHOLD);
 
         this
     }
@@ -99,6 +112,15 @@ impl Config {
             if let Some(ttl) = cache_config.maximum_ttl {
                 config.pkarr.maximum_ttl(ttl);
             }
+            if let Some(ttl) = cache_config.negative_ttl {
+                config.pkarr.negative_ttl(ttl);
+            }
+            if let Some(threshold) = cache_config.prefetch_threshold {
+                config.pkarr.prefetch_threshold(threshold);
+            }
+            if let Some(max) = cache_config.max_concurrent_prefetches {
+                config.pkarr.max_concurrent_prefetches(max);
+            }
 
             if let Some(cache_path) = cache_config.path.as_ref() {
                 config.cache_path = Some(if cache_path.is_relative() {
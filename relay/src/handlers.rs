@@ -100,16 +100,18 @@ pub async fn get(
         let mut response = response_headers.into_response();
 
         // Handle IF_MODIFIED_SINCE
-        if let Some(condition_http_date) = request_headers
+        let not_modified = request_headers
             .get(header::IF_MODIFIED_SINCE)
             .and_then(|h| h.to_str().ok())
             .and_then(|s| HttpDate::from_str(s).ok())
-        {
-            let entry_http_date: HttpDate = signed_packet.timestamp().into();
+            .is_some_and(|condition_http_date| {
+                let entry_http_date: HttpDate = signed_packet.timestamp().into();
 
-            if condition_http_date >= entry_http_date {
-                *response.status_mut() = StatusCode::NOT_MODIFIED;
-            }
+                condition_http_date >= entry_http_date
+            });
+
+        if not_modified {
+            *response.status_mut() = StatusCode::NOT_MODIFIED;
         } else {
             *response.body_mut() = signed_packet.to_relay_payload().into();
         };
@@ -127,6 +129,8 @@ pub async fn index(State(state): State<AppState>) -> Result<impl IntoResponse, E
     let capacity = cache.capacity();
     let utilization = 100.0 * size as f32 / capacity as f32;
 
+    let prefetch = state.client.prefetch_stats().unwrap_or_default();
+
     let info = state.client.dht().expect("dht node").info();
 
     let html_content = format!(
@@ -167,6 +171,15 @@ pub async fn index(State(state): State<AppState>) -> Result<impl IntoResponse, E
     utilization : {utilization:.2}%
     </pre>
 
+    <h2>Prefetch stats</h2>
+    <pre>
+    started     : {prefetch_started}
+    skipped     : {prefetch_skipped}
+    updated     : {prefetch_updated}
+    not found   : {prefetch_not_found}
+    inflight    : {prefetch_inflight}
+    </pre>
+
     <h2>Dht Info</h2>
     <pre>
     node port         : {node_port}
@@ -179,6 +192,11 @@ pub async fn index(State(state): State<AppState>) -> Result<impl IntoResponse, E
         size = format_number(size),
         capacity = format_number(capacity),
         utilization = utilization,
+        prefetch_started = format_number(prefetch.started as usize),
+        prefetch_skipped = format_number(prefetch.skipped as usize),
+        prefetch_updated = format_number(prefetch.updated as usize),
+        prefetch_not_found = format_number(prefetch.not_found as usize),
+        prefetch_inflight = prefetch.inflight,
         confidence = info.dht_size_estimate().1,
         dht_size = format_number(info.dht_size_estimate().0),
         node_port = info
@@ -208,7 +226,7 @@ fn format_number(num: usize) -> String {
 
     for (i, c) in num_str.chars().enumerate() {
         // Add a comma before every three digits, except for the first part
-        if i > 0 && (len - i) % 3 == 0 {
+        if i > 0 && (len - i).is_multiple_of(3) {
             result.push(',');
         }
         result.push(c);